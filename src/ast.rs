@@ -1,75 +1,125 @@
 // gwn::ast: The internal representation of the abstract syntax tree.
 
 use crate::scanner::Token;
+use crate::span::Span;
 use crate::typ::Typ;
+use crate::int::Int;
+
+use std::rc::Rc;
 
 // Decl: The top-level AST node
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Decl {
     // A constant declaration, e.g. `a = 2`, `foo = {x | x + 1}`
     Constant(Box<ConstantDecl>),
 
     // Just a normal expression that is evaluated, e.g. `print <- "Foo"`
-    Evaluated(Box<EvaluatedDecl>)
+    Evaluated(Box<EvaluatedDecl>),
+
+    // Bringing the constants of another module into scope, e.g.
+    // `import List`, `import ./util`
+    Import(Box<ImportDecl>),
 }
 
-// Generate an implementation for the `Into<Decl>` trait so that any
+impl Decl {
+    pub fn span(&self) -> Span {
+        match self {
+            Decl::Constant(constant) => constant.name.span.to(constant.value.span),
+            Decl::Evaluated(evaluated) => evaluated.expr.span,
+            Decl::Import(import) => import.keyword.span.to(import.path.span),
+        }
+    }
+}
+
+// Generate an implementation for the `From` trait so that any
 // variant of a Decl such as a `ConstantDecl` may be converted back to 
 // the Decl enum by calling `variant.into::<Decl>()`
 macro_rules! into_decl {
     ($from:ident, $to:ident) => {
-        impl Into<Decl> for $from {
-            fn into(self) -> Decl {
-                Decl::$to(Box::new(self))
+        impl From<$from> for Decl {
+            fn from(decl: $from) -> Decl {
+                Decl::$to(Box::new(decl))
             }
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ConstantDecl {
     // The name of the constant
-    name: Token,
+    pub name: Token,
 
-    // The typename provided. Empty if left out.
-    type_name: Token,
+    // The type the constant was declared with, e.g. `Int -> Int` in
+    // `inc: Int -> Int = {x | x + 1}`. None if left out.
+    pub annotation: Option<Typ>,
     
     // The constant's value
-    value: Expr,
+    pub value: Expr,
 }
 
 into_decl!(ConstantDecl, Constant);
 
-#[derive(Debug)]
+impl ConstantDecl {
+    // Constants whose names start with `_` are private to their module,
+    // while the rest can be imported by other modules.
+    pub fn is_public(&self) -> bool {
+        !self.name.lexeme.starts_with('_')
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct EvaluatedDecl {
     // The expression to be evaluated
-    expr: Expr,
+    pub expr: Expr,
 }
 
 into_decl!(EvaluatedDecl, Evaluated);
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportDecl {
+    // Hold on to the `import` token in case we need to report an error
+    // with it
+    pub keyword: Token,
+
+    // The module to import, either a name that is looked for on the search
+    // path, e.g. `List`, or a path relative to the importing file, e.g.
+    // `./util`
+    pub path: Token,
+}
+
+into_decl!(ImportDecl, Import);
+
 // Represents a single expression, like `2`, `42 + e`, `foo`,
 // `eat <- "food"`, etc, plus its type, e.g. Int.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Expr {
     // The actual node
     pub node: ExprKind,
 
     // The type of the expression, e.g. Int
-    pub typ: Typ
+    pub typ: Typ,
+
+    // The source that the expression was parsed from. The parser fills
+    // this in once it has seen the whole expression.
+    pub span: Span,
 }
 
 impl Expr {
     pub fn new(node: ExprKind, typ: Typ) -> Expr {
         Expr {
             node,
-            typ
+            typ,
+            span: Span::default(),
         }
     }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
 // The 'real' representation of an expression (without the type).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExprKind {
     // A constant, like `foo` or `boof`
     Constant(Box<ConstantExpr>),
@@ -86,8 +136,9 @@ pub enum ExprKind {
     // Applying an argument to a function, e.g. `21 -> fib`
     Apply(Box<ApplyExpr>),
 
-    // A function literal, like `{x y | x + y}`
-    Func(Box<FuncExpr>),
+    // A function literal, like `{x y | x + y}`. This is reference counted
+    // so that closures made from it at runtime can share it.
+    Func(Rc<FuncExpr>),
 
     // A tuple, e.g. (1, "e")
     Tuple(Box<TupleExpr>),
@@ -95,19 +146,25 @@ pub enum ExprKind {
     // A list, e.g. [1, 2, 3]
     List(Box<ListExpr>),
 
+    // A range of numbers, e.g. [1..10], [10..1], [1, 3..99] or [1..]
+    Range(Box<RangeExpr>),
+
+    // A string with expressions in it, e.g. "Hello {name}!"
+    InterpolatedString(Box<InterpolatedStringExpr>),
+
     // A regular literal value, e.g. `"goo"`, `42.42`
     Literal(Literal),
 }
 
-// Generate an implementation for the `Into<Expr>` trait so that any
+// Generate an implementation for the `From` trait so that any
 // variant of an Expr such as a `ConstantExpr` may be converted back to 
-// the Decl enum by calling `variant.into::<Expr>()`
+// the Expr struct by calling `variant.into::<Expr>()`
 macro_rules! into_expr {
     ($from:ident, $to:ident) => {
-        impl Into<Expr> for $from {
-            fn into(self) -> Expr {
+        impl From<$from> for Expr {
+            fn from(expr: $from) -> Expr {
                 Expr::new(
-                    ExprKind::$to(Box::new(self)),
+                    ExprKind::$to(Box::new(expr)),
                     Typ::Unknown
                 )
             }
@@ -115,7 +172,7 @@ macro_rules! into_expr {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ConstantExpr {
     // The name of the constant
     pub name: Token
@@ -123,7 +180,7 @@ pub struct ConstantExpr {
 
 into_expr!(ConstantExpr, Constant);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct UnaryExpr {
     pub operator: Token,
     pub operand: Expr,
@@ -131,7 +188,7 @@ pub struct UnaryExpr {
 
 into_expr!(UnaryExpr, Unary);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BinaryExpr {
     pub operator: Token,
     pub left: Expr,
@@ -140,7 +197,7 @@ pub struct BinaryExpr {
 
 into_expr!(BinaryExpr, Binary);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LogicalExpr {
     pub operator: Token,
     pub left: Expr,
@@ -149,7 +206,7 @@ pub struct LogicalExpr {
 
 into_expr!(LogicalExpr, Logical);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ApplyExpr {
     pub operator: Token,
     pub func: Expr,
@@ -158,12 +215,20 @@ pub struct ApplyExpr {
 
 into_expr!(ApplyExpr, Apply);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FuncExpr {
-    pub cases: Vec<FuncCase>
+    pub cases: Vec<FuncCase>,
+
+    // Hold on to the opening brace token of the first case in case we need
+    // to report an error with it.
+    pub brace: Token
 }
 
-into_expr!(FuncExpr, Func);
+impl From<FuncExpr> for Expr {
+    fn from(expr: FuncExpr) -> Expr {
+        Expr::new(ExprKind::Func(Rc::new(expr)), Typ::Unknown)
+    }
+}
 
 // The different cases of a function. Say we had the following code:
 // ```
@@ -172,10 +237,22 @@ into_expr!(FuncExpr, Func);
 // {x | "is not zero or one"}
 // ```
 // Each comma-seperated body is a 'case'.
-#[derive(Debug)]
+//
+// Inside a tuple or list, a comma starts the next element instead, so
+// `[{0 | 0}, {n | n}]` is a list of two functions with a case each. A
+// function with several cases has to be named before it can go in one.
+//
+// A case may take several parameters, as in `{x y | x + y}`. Every case
+// of a function takes the same number of parameters, and the function is
+// curried, so `{x y | x + y}` applied to `1` gives back `{y | 1 + y}`.
+#[derive(Debug, Clone, PartialEq)]
 pub struct FuncCase {
-    pub param: Pattern,
-    pub guards: Vec<FuncGuard>
+    pub params: Vec<Pattern>,
+    pub guards: Vec<FuncGuard>,
+
+    // Hold on to the opening brace token in case we need to report an
+    // error with it.
+    pub brace: Token
 }
 
 // For each case in a function, there may be any amount of guards.
@@ -188,66 +265,141 @@ pub struct FuncCase {
 // This case has 3 guards. The condition is before the question mark,
 // while the value is after. Multiple guards are seperated by commas.
 
+// Guards may also be seperated by a leading `|` instead of a comma:
+// ```
+// {x | x == 0 ? "is zero"
+//    | x == 1 ? "is one"
+//    | else ? "is not zero or one"}
+// ```
+// The `else` guard is just sugar for a guard whose condition is `true`.
+
 // This function appears to have no guards at all:
 // `{x | x + 1}`
 // However, this is just syntactic sugar for:
 // `{x | true ? x + 1}`
 // So, it actually has 1 guard that will always execute.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FuncGuard {
     pub condition: Expr,
     pub value: Expr,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TupleExpr {
     pub elements: Vec<Expr>,
-    
-    // Hold on to the opening paren token in case we need to report
-    // an error with it.
-    pub paren: Token
 }
 
 into_expr!(TupleExpr, Tuple);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ListExpr {
     pub elements: Vec<Expr>,
+}
+
+into_expr!(ListExpr, List);
+
+// The value of each hole is turned into a string as with `toString`, and
+// then all of the parts are concatenated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterpolatedStringExpr {
+    pub parts: Vec<InterpolatedPart>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpolatedPart {
+    Text(String),
+    Hole(Expr),
+}
+
+into_expr!(InterpolatedStringExpr, InterpolatedString);
+
+// A list of numbers counting from `start` to `end` inclusive, e.g. `[1..10]`.
+// If `start` is greater than `end` the range counts down, so `[99..1]`
+// goes 99, 98, ..., 1.
+//
+// When a second element is given, as in `[1, 3..99]`, the difference
+// between it and `start` is the step: 1, 3, 5, ..., 99.
+//
+// The end may be left out, e.g. `[1..]`, in which case the range never
+// ends, and so it is evaluated lazily.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeExpr {
+    pub start: Expr,
+    pub next: Option<Expr>,
+    pub end: Option<Expr>,
 
     // Hold on to the opening square bracket token in case we need to report
     // an error with it.
     pub square: Token
 }
 
-into_expr!(ListExpr, List);
+into_expr!(RangeExpr, Range);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
-    Int(i32),
+    Int(Int),
     Float(f64),
     Bool(bool),
-    String(Box<String>),
+    String(String),
 }
 
-// We don't use the auto-generated implementation of Into<Expr>
+// We don't use the auto-generated implementation of From<Literal>
 // here, as Literal is not wrapped in a Box.
-impl Into<Expr> for Literal {
-    fn into(self) -> Expr {
-        let typ = match self {
+impl From<Literal> for Expr {
+    fn from(literal: Literal) -> Expr {
+        let typ = match literal {
             Literal::Int(_) => Typ::Int,
             Literal::Float(_) => Typ::Float,
             Literal::Bool(_) => Typ::Bool,
             Literal::String(_) => Typ::String,
         };
 
-        let node = ExprKind::Literal(self);
+        let node = ExprKind::Literal(literal);
 
         Expr::new(node, typ)
     }
 }
 
-#[derive(Debug)]
-pub enum Pattern {
+// A pattern that the parameters of a function case are matched against.
+// Patterns may be nested, e.g. `((a, b) : rest)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub node: PatternKind,
+
+    // The source that the pattern was parsed from
+    pub span: Span,
+}
+
+impl Pattern {
+    pub fn new(node: PatternKind, span: Span) -> Pattern {
+        Pattern {
+            node,
+            span
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternKind {
+    // A literal value, e.g. `0`, `-1` or `"foo"`
     Literal(Literal),
-    Identifier(Box<Token>)
-}
\ No newline at end of file
+
+    // A name, which matches anything and binds it, e.g. `x`
+    Identifier(Box<Token>),
+
+    // `_`, which matches anything without binding it
+    Wildcard,
+
+    // A tuple, e.g. `(a, b)`
+    Tuple(Vec<Pattern>),
+
+    // A list with a head and a tail, e.g. `x : xs`
+    Cons(Box<Pattern>, Box<Pattern>),
+
+    // A list of an exact length, e.g. `[]` or `[a, b]`
+    List(Vec<Pattern>),
+}
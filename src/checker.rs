@@ -0,0 +1,1165 @@
+// gwn::checker: Hindley-Milner type inference.
+//
+// The checker walks each declaration, giving every expression a type and
+// solving the equations between those types by unification. Once a
+// declaration has been checked, the solved types are filled back into
+// each `Expr::typ`.
+
+use crate::ast::{Decl, EvaluatedDecl, Expr, ExprKind, Literal, Pattern, PatternKind, FuncExpr, InterpolatedPart};
+use crate::scanner::{Token, TokenKind};
+use crate::typ::{Typ, Class};
+use crate::span::{FileId, Span};
+use crate::error::Report;
+use crate::diagnostic::{Diagnostic, Label};
+use crate::prelude;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// A type that may be used at many different types. For example, `{x | x}`
+// has the scheme `a -> a`, where `a` can be replaced with any type each
+// time the function is used.
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<u32>,
+    typ: Typ,
+}
+
+// The ways in which two types may fail to unify
+enum UnifyError {
+    Mismatch,
+    Infinite(u32, Typ),
+    NotInClass(Class, Typ),
+}
+
+// The top-level constants in scope in a module.
+#[derive(Default)]
+pub struct ModuleScope {
+    // The types of the constants, whether from the prelude, an import or
+    // the module itself
+    globals: HashMap<String, Scheme>,
+
+    // The private constants of imported modules, and which module each is
+    // from, so that using one gives a better error than it being undefined
+    hidden: HashMap<String, String>,
+}
+
+// What a module that has been checked gives to the modules that import it
+struct Exports {
+    // The name of the module, for errors
+    name: String,
+
+    // The types of its public constants
+    public: HashMap<String, Scheme>,
+
+    // The names of its private constants
+    private: Vec<String>,
+}
+
+pub struct Checker {
+    // The types that type variables have been solved to
+    subst: HashMap<u32, Typ>,
+
+    // The classes that unsolved type variables are restricted to
+    classes: HashMap<u32, Vec<Class>>,
+
+    next_var: u32,
+
+    // The top-level constants of the module being checked
+    scope: ModuleScope,
+
+    // The types of the builtins, which every module starts out with
+    prelude: HashMap<String, Scheme>,
+
+    // The modules that have been checked, by the file they're in
+    modules: HashMap<FileId, Exports>,
+
+    // Function parameters that are in scope, innermost last
+    locals: Vec<(String, Typ)>,
+
+    // Type variables from a type annotation, which stand for any type at
+    // all, and so can't be solved to anything but themselves.
+    rigid: Vec<u32>,
+}
+
+impl Default for Checker {
+    fn default() -> Checker {
+        Checker::new()
+    }
+}
+
+impl Checker {
+    pub fn new() -> Checker {
+        let mut checker = Checker {
+            subst: HashMap::new(),
+            classes: HashMap::new(),
+            next_var: 0,
+            scope: ModuleScope::default(),
+            prelude: HashMap::new(),
+            modules: HashMap::new(),
+            locals: vec![],
+            rigid: vec![],
+        };
+
+        for builtin in prelude::builtins() {
+            // The builtins all number their type variables from 0, so give
+            // them fresh ones that won't clash.
+            let mut vars = vec![];
+            builtin.typ.vars(&mut vars);
+
+            let mut fresh = HashMap::new();
+            for var in vars.iter() {
+                fresh.insert(*var, checker.fresh());
+            }
+
+            let typ = substitute(&builtin.typ, &fresh);
+            let scheme = checker.generalise(&typ);
+            checker.prelude.insert(builtin.native.name.to_string(), scheme);
+        }
+
+        checker.scope.globals = checker.prelude.clone();
+        checker
+    }
+
+    // Start checking a new module, which only has the builtins in scope.
+    // The scope of the module that was being checked is given back, to be
+    // passed to `finish_module`.
+    pub fn begin_module(&mut self) -> ModuleScope {
+        let scope = ModuleScope { globals: self.prelude.clone(), hidden: HashMap::new() };
+        std::mem::replace(&mut self.scope, scope)
+    }
+
+    // Finish checking the module in `file`, so that it can be imported, and
+    // go back to checking the module that `previous` is the scope of.
+    pub fn finish_module(&mut self, file: FileId, name: &str, ast: &[Decl], previous: ModuleScope) {
+        let mut exports = Exports { name: name.to_string(), public: HashMap::new(), private: vec![] };
+
+        for decl in ast.iter() {
+            if let Decl::Constant(constant) = decl {
+                let name = constant.name.lexeme.clone();
+                if !constant.is_public() {
+                    exports.private.push(name);
+                } else if let Some(scheme) = self.scope.globals.get(&name) {
+                    exports.public.insert(name, scheme.clone());
+                }
+            }
+        }
+
+        self.modules.insert(file, exports);
+        self.scope = previous;
+    }
+
+    // Bring the public constants of a module that has been checked into
+    // scope.
+    pub fn import(&mut self, file: FileId) {
+        let exports = &self.modules[&file];
+
+        for (name, scheme) in exports.public.iter() {
+            self.scope.globals.insert(name.clone(), scheme.clone());
+        }
+
+        for name in exports.private.iter() {
+            self.scope.hidden.insert(name.clone(), exports.name.clone());
+        }
+    }
+
+    // Check every declaration, filling in the type of each expression. A
+    // declaration with an error is skipped, and checking carries on with
+    // the rest, so all of the errors found are returned.
+    //
+    // A constant may be used before it's declared, but only inside of a
+    // function, which can't be called until the constant has a value. So
+    // that functions can call each other, the constants are checked in the
+    // order that they use each other rather than the order they're written
+    // in, with those that use each other checked together.
+    pub fn check(&mut self, ast: &mut [Decl]) -> Vec<TypeError> {
+        let mut errors = vec![];
+
+        // Which declaration each constant comes from
+        let mut declared = HashMap::new();
+        for (i, decl) in ast.iter().enumerate() {
+            if let Decl::Constant(constant) = decl {
+                let name = &constant.name;
+                if declared.contains_key(&name.lexeme) {
+                    errors.push((i, make_error_at(name.span, format!("'{}' is already declared.", name.lexeme))));
+                } else {
+                    declared.insert(name.lexeme.clone(), i);
+                }
+            }
+        }
+
+        // The constants that each declaration refers to, and whether each
+        // reference is inside of a function
+        let mut references = vec![vec![]; ast.len()];
+        for (i, decl) in ast.iter().enumerate() {
+            let expr = match decl {
+                Decl::Constant(constant) if declared[&constant.name.lexeme] == i => &constant.value,
+                Decl::Constant(_) => continue,
+                Decl::Evaluated(evaluated) => &evaluated.expr,
+
+                // Imports have already been loaded by the time a module is
+                // checked, see `module::Loader`.
+                Decl::Import(_) => continue,
+            };
+
+            collect_references(expr, &mut vec![], false, &mut references[i]);
+        }
+
+        // The constants that each declaration uses
+        let mut uses = vec![vec![]; ast.len()];
+        for (i, refs) in references.iter().enumerate() {
+            for (name, delayed) in refs.iter() {
+                let used = match declared.get(&name.lexeme) {
+                    Some(used) => *used,
+                    None => continue,
+                };
+
+                if !delayed {
+                    if used >= i {
+                        let msg = format!("'{}' is used before it has a value.", name.lexeme);
+                        errors.push((i, make_error_at(name.span, msg)));
+                        break;
+                    }
+
+                    // A function that's used here may be called here, so
+                    // the constants it uses need a value by now as well.
+                    if let Some(later) = used_later(used, i, ast, &references, &declared) {
+                        let msg = format!("'{}' is used through '{}' before it has a value.", later, name.lexeme);
+                        errors.push((i, make_error_at(name.span, msg)));
+                        break;
+                    }
+                }
+
+                uses[i].push(used);
+            }
+        }
+
+        let failed: Vec<usize> = errors.iter().map(|(i, _)| *i).collect();
+        let constants: Vec<usize> = declared.values().copied().collect();
+
+        for group in components(&constants, &uses) {
+            errors.extend(self.check_constants(ast, &group, &failed));
+            self.locals.clear();
+        }
+
+        for (i, decl) in ast.iter_mut().enumerate() {
+            if let (Decl::Evaluated(decl), false) = (decl, failed.contains(&i)) {
+                if let Err(err) = self.check_evaluated(decl) {
+                    errors.push((i, err));
+                }
+
+                self.locals.clear();
+            }
+        }
+
+        errors.sort_by_key(|(i, _)| *i);
+        errors.into_iter().map(|(_, err)| err).collect()
+    }
+
+    // Check a group of constants that use each other. Inside of their
+    // values they're in scope but not generalised yet, so that they can
+    // only be used at one type there. Those in `failed` already have an
+    // error, and aren't checked.
+    fn check_constants(&mut self, ast: &mut [Decl], group: &[usize], failed: &[usize]) -> Vec<(usize, TypeError)> {
+        let mut decls = vec![];
+        for (i, decl) in ast.iter_mut().enumerate() {
+            if let (Decl::Constant(decl), true) = (decl, group.contains(&i)) {
+                let typ = match &decl.annotation {
+                    Some(annotation) => self.rigid_instance(annotation),
+                    None => self.fresh(),
+                };
+                self.scope.globals.insert(decl.name.lexeme.clone(), Scheme{vars: vec![], typ: typ.clone()});
+                decls.push((i, decl, typ));
+            }
+        }
+
+        let mut errors = vec![];
+        for (i, decl, typ) in decls.iter_mut() {
+            if failed.contains(i) {
+                continue;
+            }
+
+            let result = self.infer(&mut decl.value)
+                .and_then(|value| self.unify_at(decl.value.span(), typ, &value));
+
+            if let Err(err) = result {
+                errors.push((*i, err));
+            }
+        }
+        self.rigid.clear();
+
+        for (i, decl, typ) in decls.iter_mut() {
+            let mut names = HashMap::new();
+            let typ = self.resolve(typ);
+            self.rename(&typ, &mut names);
+            self.fill(&mut decl.value, &mut names);
+
+            let scheme = if failed.contains(i) || errors.iter().any(|(failed, _)| failed == i) {
+                // Let the constant be used as any type from now on, so that
+                // this error doesn't cause more errors wherever it is used.
+                let var = self.next_var;
+                self.next_var += 1;
+                Scheme{vars: vec![var], typ: Typ::Var(var)}
+            } else {
+                self.generalise(&typ)
+            };
+
+            self.scope.globals.insert(decl.name.lexeme.clone(), scheme);
+        }
+
+        errors
+    }
+
+    // Give the variables in a type annotation fresh numbers, and make them
+    // rigid, so that the annotation `a -> a` can't be given to a function
+    // that only works on Ints.
+    fn rigid_instance(&mut self, annotation: &Typ) -> Typ {
+        let mut vars = vec![];
+        annotation.vars(&mut vars);
+
+        let mut fresh = HashMap::new();
+        for var in vars {
+            let rigid = self.fresh();
+            if let Typ::Var(id) = rigid {
+                self.rigid.push(id);
+            }
+            fresh.insert(var, rigid);
+        }
+
+        substitute(annotation, &fresh)
+    }
+
+    fn check_evaluated(&mut self, decl: &mut EvaluatedDecl) -> Result<(), TypeError> {
+        let result = self.infer(&mut decl.expr);
+
+        let mut names = HashMap::new();
+        self.fill(&mut decl.expr, &mut names);
+
+        result.map(|_| ())
+    }
+
+    // Work out the type of an expression, recording it in `expr.typ`.
+    fn infer(&mut self, expr: &mut Expr) -> Result<Typ, TypeError> {
+        let span = expr.span();
+        let typ = match &mut expr.node {
+            ExprKind::Literal(literal) => literal_typ(literal),
+
+            ExprKind::Constant(constant) => self.lookup(&constant.name)?,
+
+            ExprKind::Unary(unary) => {
+                let operand = self.infer(&mut unary.operand)?;
+
+                if unary.operator.kind == TokenKind::Not {
+                    self.unify_at(unary.operand.span(), &Typ::Bool, &operand)?;
+                    Typ::Bool
+                } else {
+                    self.restrict_at(unary.operand.span(), &operand, Class::Num)?;
+                    operand
+                }
+            },
+
+            ExprKind::Binary(binary) => {
+                let left = self.infer(&mut binary.left)?;
+                let right = self.infer(&mut binary.right)?;
+
+                match binary.operator.kind {
+                    // Cons, e.g. `1 : [2, 3]`
+                    TokenKind::Colon => {
+                        let list = Typ::List(Box::new(left));
+                        self.unify_at(binary.right.span(), &list, &right)?;
+                        list
+                    },
+
+                    TokenKind::EqualEqual | TokenKind::BangEqual => {
+                        self.unify_at(span, &left, &right)?;
+                        Typ::Bool
+                    },
+
+                    TokenKind::Less | TokenKind::LessEqual |
+                    TokenKind::Greater | TokenKind::GreaterEqual => {
+                        self.unify_at(span, &left, &right)?;
+                        self.restrict_at(span, &left, Class::Ord)?;
+                        Typ::Bool
+                    },
+
+                    TokenKind::PlusPlus => {
+                        self.unify_at(span, &left, &right)?;
+                        self.restrict_at(span, &left, Class::Concat)?;
+                        left
+                    },
+
+                    // Arithmetic, e.g. `+`, `%`, `^`
+                    _ => {
+                        self.unify_at(span, &left, &right)?;
+                        self.restrict_at(span, &left, Class::Num)?;
+                        left
+                    },
+                }
+            },
+
+            ExprKind::Logical(logical) => {
+                let left = self.infer(&mut logical.left)?;
+                self.unify_at(logical.left.span(), &Typ::Bool, &left)?;
+
+                let right = self.infer(&mut logical.right)?;
+                self.unify_at(logical.right.span(), &Typ::Bool, &right)?;
+
+                Typ::Bool
+            },
+
+            ExprKind::Apply(apply) => {
+                let func = self.infer(&mut apply.func)?;
+                let arg = self.infer(&mut apply.arg)?;
+
+                let param = self.fresh();
+                let ret = self.fresh();
+                let expected = Typ::Func(Box::new(param.clone()), Box::new(ret.clone()));
+
+                if self.unify(&expected, &func).is_err() {
+                    let msg = format!("A value of type '{}' can't be applied like a function.", self.show(&[&func])[0]);
+                    return Err(make_error_at(apply.operator.span, msg));
+                }
+
+                match self.unify(&param, &arg) {
+                    Err(UnifyError::Mismatch) => {
+                        let shown = self.show(&[&param, &arg]);
+                        let msg = format!("Expected an argument of type '{}', found '{}'.", shown[0], shown[1]);
+                        return Err(make_error_at(apply.arg.span(), msg));
+                    },
+                    Err(err) => return Err(make_error_at(apply.arg.span(), self.describe_error(err, &param, &arg))),
+                    Ok(()) => {},
+                }
+
+                ret
+            },
+
+            ExprKind::Func(func) => {
+                // The function literal is only shared if the AST has been
+                // cloned, in which case it's copied rather than changing
+                // the clone too.
+                let func = Rc::make_mut(func);
+                self.infer_func(func)?
+            },
+
+            ExprKind::Tuple(tuple) => {
+                let mut elements = vec![];
+                for element in tuple.elements.iter_mut() {
+                    elements.push(self.infer(element)?);
+                }
+
+                Typ::Tuple(elements)
+            },
+
+            ExprKind::List(list) => {
+                let elem = self.fresh();
+                for element in list.elements.iter_mut() {
+                    let typ = self.infer(element)?;
+                    self.unify_at(element.span(), &elem, &typ)?;
+                }
+
+                Typ::List(Box::new(elem))
+            },
+
+            // Anything can be turned into a string, so the holes can be of
+            // any type.
+            ExprKind::InterpolatedString(string) => {
+                for part in string.parts.iter_mut() {
+                    if let InterpolatedPart::Hole(hole) = part {
+                        self.infer(hole)?;
+                    }
+                }
+
+                Typ::String
+            },
+
+            ExprKind::Range(range) => {
+                let elem = self.fresh();
+                self.restrict_at(span, &elem, Class::Num)?;
+
+                let bounds = Some(&mut range.start).into_iter()
+                    .chain(range.next.as_mut())
+                    .chain(range.end.as_mut());
+
+                for bound in bounds {
+                    let typ = self.infer(bound)?;
+                    self.unify_at(bound.span(), &elem, &typ)?;
+                }
+
+                Typ::List(Box::new(elem))
+            },
+        };
+
+        expr.typ = typ.clone();
+        Ok(typ)
+    }
+
+    // Every case of a function must have the same type. With several
+    // parameters the function is curried, so `{x y | x + y}` has the type
+    // `a -> a -> a`.
+    fn infer_func(&mut self, func: &mut FuncExpr) -> Result<Typ, TypeError> {
+        let typ = self.fresh();
+
+        for case in func.cases.iter_mut() {
+            let scope = self.locals.len();
+
+            let mut params = vec![];
+            for param in case.params.iter() {
+                params.push(self.bind_pattern(param, scope)?);
+            }
+
+            let ret = self.fresh();
+            for guard in case.guards.iter_mut() {
+                let condition = self.infer(&mut guard.condition)?;
+                self.unify_at(guard.condition.span(), &Typ::Bool, &condition)?;
+
+                let value = self.infer(&mut guard.value)?;
+                self.unify_at(guard.value.span(), &ret, &value)?;
+            }
+
+            self.locals.truncate(scope);
+
+            let case_typ = params.into_iter().rev()
+                .fold(ret, |ret, param| Typ::Func(Box::new(param), Box::new(ret)));
+            self.unify_at(case.brace.span, &typ, &case_typ)?;
+        }
+
+        Ok(typ)
+    }
+
+    // Bring the names in a pattern into scope, returning the type of value
+    // the pattern matches.
+    fn bind_pattern(&mut self, pattern: &Pattern, scope: usize) -> Result<Typ, TypeError> {
+        match &pattern.node {
+            PatternKind::Literal(literal) => Ok(literal_typ(literal)),
+
+            PatternKind::Identifier(name) => {
+                if self.locals[scope..].iter().any(|(local, _)| *local == name.lexeme) {
+                    return Err(make_error_at(pattern.span(), format!("'{}' is bound more than once in the same case.", name.lexeme)));
+                }
+
+                let typ = self.fresh();
+                self.locals.push((name.lexeme.clone(), typ.clone()));
+                Ok(typ)
+            },
+
+            PatternKind::Wildcard => Ok(self.fresh()),
+
+            PatternKind::Tuple(elements) => {
+                let mut types = vec![];
+                for element in elements.iter() {
+                    types.push(self.bind_pattern(element, scope)?);
+                }
+
+                Ok(Typ::Tuple(types))
+            },
+
+            PatternKind::Cons(head, tail_pattern) => {
+                let head = self.bind_pattern(head, scope)?;
+                let tail = self.bind_pattern(tail_pattern, scope)?;
+
+                let list = Typ::List(Box::new(head));
+                self.unify_at(tail_pattern.span(), &list, &tail)?;
+                Ok(list)
+            },
+
+            PatternKind::List(elements) => {
+                let elem = self.fresh();
+                for element in elements.iter() {
+                    let typ = self.bind_pattern(element, scope)?;
+                    self.unify_at(element.span(), &elem, &typ)?;
+                }
+
+                Ok(Typ::List(Box::new(elem)))
+            },
+        }
+    }
+
+    fn lookup(&mut self, name: &Token) -> Result<Typ, TypeError> {
+        if let Some((_, typ)) = self.locals.iter().rev().find(|(local, _)| *local == name.lexeme) {
+            return Ok(typ.clone());
+        }
+
+        if let Some(scheme) = self.scope.globals.get(&name.lexeme).cloned() {
+            return Ok(self.instantiate(&scheme));
+        }
+
+        if let Some(module) = self.scope.hidden.get(&name.lexeme) {
+            return Err(make_error_at(name.span, format!("'{}' is private to the module '{}'.", name.lexeme, module)));
+        }
+
+        Err(make_error_at(name.span, format!("Undefined constant '{}'.", name.lexeme)))
+    }
+
+    fn fresh(&mut self) -> Typ {
+        let var = self.next_var;
+        self.next_var += 1;
+        Typ::Var(var)
+    }
+
+    // Replace each of a scheme's variables with a fresh one, so that it can
+    // be used at a different type than everywhere else.
+    fn instantiate(&mut self, scheme: &Scheme) -> Typ {
+        let mut vars = HashMap::new();
+
+        for var in scheme.vars.iter() {
+            let fresh = self.next_var;
+            self.next_var += 1;
+
+            if let Some(classes) = self.classes.get(var).cloned() {
+                self.classes.insert(fresh, classes);
+            }
+
+            vars.insert(*var, Typ::Var(fresh));
+        }
+
+        substitute(&scheme.typ, &vars)
+    }
+
+    // Turn a type into a scheme over all of its unsolved variables. This is
+    // only done for top-level constants, where nothing else in scope could
+    // refer to those variables.
+    fn generalise(&self, typ: &Typ) -> Scheme {
+        let typ = self.resolve(typ);
+        let mut vars = vec![];
+        typ.vars(&mut vars);
+
+        Scheme{vars, typ}
+    }
+
+    // Replace solved type variables with their solutions, all the way down.
+    fn resolve(&self, typ: &Typ) -> Typ {
+        match typ {
+            Typ::Var(var) => match self.subst.get(var) {
+                Some(solved) => self.resolve(solved),
+                None => typ.clone(),
+            },
+            Typ::List(elem) => Typ::List(Box::new(self.resolve(elem))),
+            Typ::Tuple(elems) => Typ::Tuple(elems.iter().map(|e| self.resolve(e)).collect()),
+            Typ::Func(param, ret) => Typ::Func(Box::new(self.resolve(param)), Box::new(self.resolve(ret))),
+            _ => typ.clone(),
+        }
+    }
+
+    fn unify_at(&mut self, span: Span, expected: &Typ, found: &Typ) -> Result<(), TypeError> {
+        self.unify(expected, found)
+            .map_err(|err| make_error_at(span, self.describe_error(err, expected, found)))
+    }
+
+    fn describe_error(&self, err: UnifyError, expected: &Typ, found: &Typ) -> String {
+        match err {
+            UnifyError::Mismatch => {
+                let shown = self.show(&[expected, found]);
+                format!("Expected type '{}', found '{}'.", shown[0], shown[1])
+            },
+            UnifyError::Infinite(var, typ) => {
+                let shown = self.show(&[&Typ::Var(var), &typ]);
+                format!("Infinite type: '{}' can't be equal to '{}'.", shown[0], shown[1])
+            },
+            UnifyError::NotInClass(class, typ) => {
+                format!("Expected {}, found '{}'.", class.describe(), self.show(&[&typ])[0])
+            },
+        }
+    }
+
+    // Display some types for an error message, naming their variables
+    // `a`, `b`, etc.
+    fn show(&self, types: &[&Typ]) -> Vec<String> {
+        let mut names = HashMap::new();
+        types.iter()
+            .map(|typ| self.rename(&self.resolve(typ), &mut names).to_string())
+            .collect()
+    }
+
+    fn unify(&mut self, a: &Typ, b: &Typ) -> Result<(), UnifyError> {
+        let a = self.shallow_resolve(a);
+        let b = self.shallow_resolve(b);
+
+        match (a, b) {
+            (Typ::Var(x), Typ::Var(y)) if x == y => Ok(()),
+            (Typ::Var(var), typ) if !self.rigid.contains(&var) => self.bind(var, typ),
+            (typ, Typ::Var(var)) if !self.rigid.contains(&var) => self.bind(var, typ),
+
+            (Typ::Int, Typ::Int) | (Typ::Float, Typ::Float) |
+            (Typ::Bool, Typ::Bool) | (Typ::String, Typ::String) => Ok(()),
+
+            (Typ::List(a), Typ::List(b)) => self.unify(&a, &b),
+
+            (Typ::Tuple(a), Typ::Tuple(b)) if a.len() == b.len() => {
+                for (a, b) in a.iter().zip(b.iter()) {
+                    self.unify(a, b)?;
+                }
+                Ok(())
+            },
+
+            (Typ::Func(a_param, a_ret), Typ::Func(b_param, b_ret)) => {
+                self.unify(&a_param, &b_param)?;
+                self.unify(&a_ret, &b_ret)
+            },
+
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+
+    // Solve the type variable `var` to `typ`.
+    fn bind(&mut self, var: u32, typ: Typ) -> Result<(), UnifyError> {
+        let resolved = self.resolve(&typ);
+        if resolved.contains_var(var) {
+            return Err(UnifyError::Infinite(var, resolved));
+        }
+
+        let classes = self.classes.remove(&var).unwrap_or_default();
+        self.subst.insert(var, typ.clone());
+
+        for class in classes {
+            self.restrict(&typ, class)?;
+        }
+
+        Ok(())
+    }
+
+    fn restrict_at(&mut self, span: Span, typ: &Typ, class: Class) -> Result<(), TypeError> {
+        self.restrict(typ, class).map_err(|_| {
+            make_error_at(span, format!("Expected {}, found '{}'.", class.describe(), self.show(&[typ])[0]))
+        })
+    }
+
+    // Restrict a type to a class, e.g. Num.
+    fn restrict(&mut self, typ: &Typ, class: Class) -> Result<(), UnifyError> {
+        match self.shallow_resolve(typ) {
+            Typ::Var(var) if !self.rigid.contains(&var) => {
+                let classes = self.classes.entry(var).or_default();
+                if !classes.contains(&class) {
+                    classes.push(class);
+                }
+                Ok(())
+            },
+
+            typ if class.admits(&typ) => Ok(()),
+            typ => Err(UnifyError::NotInClass(class, typ)),
+        }
+    }
+
+    // Follow solved type variables until reaching either an unsolved
+    // variable or some other type.
+    fn shallow_resolve(&self, typ: &Typ) -> Typ {
+        let mut typ = typ;
+        while let Typ::Var(var) = typ {
+            match self.subst.get(var) {
+                Some(solved) => typ = solved,
+                None => break,
+            }
+        }
+
+        typ.clone()
+    }
+
+    // Give the unsolved variables in a type nice names. Each declaration
+    // has its own set of names, starting at `a`.
+    fn rename(&self, typ: &Typ, names: &mut HashMap<u32, Typ>) -> Typ {
+        let mut vars = vec![];
+        typ.vars(&mut vars);
+
+        for var in vars {
+            let next = names.len() as u32;
+            names.entry(var).or_insert(Typ::Var(next));
+        }
+
+        substitute(typ, names)
+    }
+
+    // Write the solved types back into an expression and everything in it.
+    fn fill(&self, expr: &mut Expr, names: &mut HashMap<u32, Typ>) {
+        expr.typ = self.rename(&self.resolve(&expr.typ), names);
+
+        match &mut expr.node {
+            ExprKind::Literal(_) | ExprKind::Constant(_) => {},
+            ExprKind::Unary(unary) => self.fill(&mut unary.operand, names),
+            ExprKind::Binary(binary) => {
+                self.fill(&mut binary.left, names);
+                self.fill(&mut binary.right, names);
+            },
+            ExprKind::Logical(logical) => {
+                self.fill(&mut logical.left, names);
+                self.fill(&mut logical.right, names);
+            },
+            ExprKind::Apply(apply) => {
+                self.fill(&mut apply.func, names);
+                self.fill(&mut apply.arg, names);
+            },
+            ExprKind::Func(func) => {
+                let func = Rc::make_mut(func);
+                for case in func.cases.iter_mut() {
+                    for guard in case.guards.iter_mut() {
+                        self.fill(&mut guard.condition, names);
+                        self.fill(&mut guard.value, names);
+                    }
+                }
+            },
+            ExprKind::Tuple(tuple) => tuple.elements.iter_mut().for_each(|e| self.fill(e, names)),
+            ExprKind::List(list) => list.elements.iter_mut().for_each(|e| self.fill(e, names)),
+            ExprKind::InterpolatedString(string) => {
+                for part in string.parts.iter_mut() {
+                    if let InterpolatedPart::Hole(hole) = part {
+                        self.fill(hole, names);
+                    }
+                }
+            },
+            ExprKind::Range(range) => {
+                self.fill(&mut range.start, names);
+                if let Some(next) = &mut range.next {
+                    self.fill(next, names);
+                }
+                if let Some(end) = &mut range.end {
+                    self.fill(end, names);
+                }
+            },
+        }
+    }
+}
+
+// Collect the names of the constants that an expression uses, along with
+// whether each is inside of a function, where using it is put off until the
+// function is called. `locals` are the parameters in scope, which hide
+// constants with the same name.
+fn collect_references<'a>(expr: &'a Expr, locals: &mut Vec<&'a str>, delayed: bool, out: &mut Vec<(&'a Token, bool)>) {
+    match &expr.node {
+        ExprKind::Literal(_) => {},
+        ExprKind::Constant(constant) => {
+            if !locals.contains(&&constant.name.lexeme[..]) {
+                out.push((&constant.name, delayed));
+            }
+        },
+        ExprKind::Unary(unary) => collect_references(&unary.operand, locals, delayed, out),
+        ExprKind::Binary(binary) => {
+            collect_references(&binary.left, locals, delayed, out);
+            collect_references(&binary.right, locals, delayed, out);
+        },
+        ExprKind::Logical(logical) => {
+            collect_references(&logical.left, locals, delayed, out);
+            collect_references(&logical.right, locals, delayed, out);
+        },
+        ExprKind::Apply(apply) => {
+            collect_references(&apply.func, locals, delayed, out);
+            collect_references(&apply.arg, locals, delayed, out);
+        },
+        ExprKind::Func(func) => {
+            for case in func.cases.iter() {
+                let scope = locals.len();
+                for param in case.params.iter() {
+                    pattern_names(param, locals);
+                }
+
+                for guard in case.guards.iter() {
+                    collect_references(&guard.condition, locals, true, out);
+                    collect_references(&guard.value, locals, true, out);
+                }
+
+                locals.truncate(scope);
+            }
+        },
+        ExprKind::Tuple(tuple) => tuple.elements.iter().for_each(|e| collect_references(e, locals, delayed, out)),
+        ExprKind::List(list) => list.elements.iter().for_each(|e| collect_references(e, locals, delayed, out)),
+        ExprKind::InterpolatedString(string) => {
+            for part in string.parts.iter() {
+                if let InterpolatedPart::Hole(hole) = part {
+                    collect_references(hole, locals, delayed, out);
+                }
+            }
+        },
+        ExprKind::Range(range) => {
+            let bounds = Some(&range.start).into_iter()
+                .chain(range.next.as_ref())
+                .chain(range.end.as_ref());
+
+            for bound in bounds {
+                collect_references(bound, locals, delayed, out);
+            }
+        },
+    }
+}
+
+// Find a constant declared at or after `before` that may be used by calling
+// the constant declared at `start`, following the functions that it uses in
+// turn. Constants that aren't functions can't be called, so aren't followed.
+fn used_later<'a>(start: usize, before: usize, ast: &[Decl], references: &[Vec<(&'a Token, bool)>], declared: &HashMap<String, usize>) -> Option<&'a str> {
+    let is_function = |i: usize| matches!(&ast[i], Decl::Constant(constant) if matches!(constant.value.node, ExprKind::Func(_)));
+
+    if !is_function(start) {
+        return None;
+    }
+
+    let mut seen = vec![start];
+    let mut pending = vec![start];
+
+    while let Some(decl) = pending.pop() {
+        for (name, _) in references[decl].iter() {
+            let used = match declared.get(&name.lexeme) {
+                Some(used) => *used,
+                None => continue,
+            };
+
+            if used >= before {
+                return Some(&name.lexeme);
+            }
+
+            if is_function(used) && !seen.contains(&used) {
+                seen.push(used);
+                pending.push(used);
+            }
+        }
+    }
+
+    None
+}
+
+// Push the names that a pattern binds on to `names`.
+fn pattern_names<'a>(pattern: &'a Pattern, names: &mut Vec<&'a str>) {
+    match &pattern.node {
+        PatternKind::Identifier(name) => names.push(&name.lexeme),
+        PatternKind::Literal(_) | PatternKind::Wildcard => {},
+        PatternKind::Tuple(elements) | PatternKind::List(elements) => {
+            elements.iter().for_each(|element| pattern_names(element, names));
+        },
+        PatternKind::Cons(head, tail) => {
+            pattern_names(head, names);
+            pattern_names(tail, names);
+        },
+    }
+}
+
+// Split the declarations in `nodes` into groups that use each other, using
+// Tarjan's algorithm. `uses` gives the declarations that each one uses. A
+// group only uses those in the groups before it, or itself.
+fn components(nodes: &[usize], uses: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        uses: &'a [Vec<usize>],
+        index: HashMap<usize, usize>,
+        low: HashMap<usize, usize>,
+        stack: Vec<usize>,
+        groups: Vec<Vec<usize>>,
+    }
+
+    fn visit(state: &mut State, node: usize) {
+        let index = state.index.len();
+        state.index.insert(node, index);
+        state.low.insert(node, index);
+        state.stack.push(node);
+
+        for &used in state.uses[node].iter() {
+            if !state.index.contains_key(&used) {
+                visit(state, used);
+                let low = state.low[&node].min(state.low[&used]);
+                state.low.insert(node, low);
+            } else if state.stack.contains(&used) {
+                let low = state.low[&node].min(state.index[&used]);
+                state.low.insert(node, low);
+            }
+        }
+
+        if state.low[&node] == state.index[&node] {
+            let start = state.stack.iter().position(|n| *n == node).unwrap();
+            let mut group = state.stack.split_off(start);
+            group.sort_unstable();
+            state.groups.push(group);
+        }
+    }
+
+    let mut state = State { uses, index: HashMap::new(), low: HashMap::new(), stack: vec![], groups: vec![] };
+
+    let mut nodes = nodes.to_vec();
+    nodes.sort_unstable();
+    for node in nodes {
+        if !state.index.contains_key(&node) {
+            visit(&mut state, node);
+        }
+    }
+
+    state.groups
+}
+
+fn literal_typ(literal: &Literal) -> Typ {
+    match literal {
+        Literal::Int(_) => Typ::Int,
+        Literal::Float(_) => Typ::Float,
+        Literal::Bool(_) => Typ::Bool,
+        Literal::String(_) => Typ::String,
+    }
+}
+
+// Replace type variables according to `vars`.
+fn substitute(typ: &Typ, vars: &HashMap<u32, Typ>) -> Typ {
+    match typ {
+        Typ::Var(var) => vars.get(var).cloned().unwrap_or_else(|| typ.clone()),
+        Typ::List(elem) => Typ::List(Box::new(substitute(elem, vars))),
+        Typ::Tuple(elems) => Typ::Tuple(elems.iter().map(|e| substitute(e, vars)).collect()),
+        Typ::Func(param, ret) => Typ::Func(Box::new(substitute(param, vars)), Box::new(substitute(ret, vars))),
+        _ => typ.clone(),
+    }
+}
+
+fn make_error_at(span: Span, msg: String) -> TypeError {
+    TypeError {
+        span,
+        msg
+    }
+}
+
+#[derive(Debug)]
+pub struct TypeError {
+    span: Span,
+    msg: String,
+}
+
+impl Report for TypeError {
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error("E0003", self.msg.clone())
+            .with_label(Label::primary(self.span, ""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::span::FileId;
+
+    // Check some source, giving back the type of each constant, or the
+    // error messages.
+    fn check(source: &str) -> Result<Vec<String>, Vec<String>> {
+        let (mut ast, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "{:?} doesn't parse", source);
+        let errors = Checker::new().check(&mut ast);
+        if !errors.is_empty() {
+            return Err(errors.into_iter().map(|err| err.msg).collect());
+        }
+
+        Ok(ast.iter()
+            .filter_map(|decl| match decl {
+                Decl::Constant(constant) => Some(constant.value.typ.to_string()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    #[test]
+    fn literals_and_tuples() {
+        assert_eq!(check("a = (1, 2.5, \"s\", true, [1, 2], [1..])"), Ok(vec![
+            "(Int, Float, String, Bool, [Int], [Int])".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn unification() {
+        assert_eq!(check("f = {x y | x + y}\ng = {x | x + 1}\nh = {x | [x, 1.5]}\nl = {x y | x < y}"), Ok(vec![
+            "a -> a -> a".to_string(),
+            "Int -> Int".to_string(),
+            "Float -> [Float]".to_string(),
+            "a -> a -> Bool".to_string(),
+        ]));
+
+        assert_eq!(check("a = 1 + \"s\""), Err(vec!["Expected type 'Int', found 'String'.".to_string()]));
+        assert_eq!(check("t = (1, 2) == (1, \"a\")"), Err(vec![
+            "Expected type '(Int, Int)', found '(Int, String)'.".to_string(),
+        ]));
+        assert_eq!(check("s = \"a\" - \"b\""), Err(vec!["Expected a number, found 'String'.".to_string()]));
+    }
+
+    #[test]
+    fn occurs_check() {
+        assert_eq!(check("f = {x | x <- x}"), Err(vec![
+            "Infinite type: 'a' can't be equal to 'a -> b'.".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn constants_are_generalised() {
+        assert_eq!(check("id = {x | x}\npair = (id <- 1, id <- \"a\")"), Ok(vec![
+            "a -> a".to_string(),
+            "(Int, String)".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn constants_can_be_used_before_they_are_declared_in_functions() {
+        let source = "isEven = {0 | true}, {n | isOdd <- (n - 1)}\nisOdd = {0 | false}, {n | isEven <- (n - 1)}";
+        assert_eq!(check(source), Ok(vec!["Int -> Bool".to_string(), "Int -> Bool".to_string()]));
+
+        // `id` is checked first, so it's generalised by the time it's used
+        assert_eq!(check("pair = {x | (id <- x, id <- \"a\")}\nid = {a | a}"), Ok(vec![
+            "a -> (a, String)".to_string(),
+            "a -> a".to_string(),
+        ]));
+
+        assert_eq!(check("a = b + 1\nb = 2"), Err(vec!["'b' is used before it has a value.".to_string()]));
+        assert_eq!(check("print = {x | x}\nprint <- later\nlater = 1"), Err(vec![
+            "'later' is used before it has a value.".to_string(),
+        ]));
+
+        // A function that's called may use a constant that doesn't have a value yet
+        assert_eq!(check("f = {x | later}\nprint = {x | x}\nprint <- (f <- 1)\nlater = 2"), Err(vec![
+            "'later' is used through 'f' before it has a value.".to_string(),
+        ]));
+        assert_eq!(check("f = {x | g <- x}\ng = {x | later}\nf <- 1\nlater = 2"), Err(vec![
+            "'later' is used through 'f' before it has a value.".to_string(),
+        ]));
+        assert_eq!(check("f = {x | later}\nlater = 2\nf <- 1"), Ok(vec!["a -> Int".to_string(), "Int".to_string()]));
+
+        assert_eq!(check("a = 1\na = 2"), Err(vec!["'a' is already declared.".to_string()]));
+    }
+
+    #[test]
+    fn holes_in_strings_can_be_of_any_type() {
+        assert_eq!(check("s = {x | \"{x}, {[x]} and {(1, true)}\"}"), Ok(vec!["a -> String".to_string()]));
+        assert_eq!(check("s = \"{1 + \"a\"}\""), Err(vec!["Expected type 'Int', found 'String'.".to_string()]));
+
+        // Constants used in holes are declared before they're needed, as anywhere else
+        assert_eq!(check("s = \"{n}\"\nn = 1"), Err(vec!["'n' is used before it has a value.".to_string()]));
+        assert_eq!(check("f = {x | \"{g <- x}\"}\ng = {x | x + 1}"), Ok(vec![
+            "Int -> String".to_string(),
+            "Int -> Int".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn annotations_are_rigid() {
+        assert_eq!(check("id: a -> a = {x | x}\nn: Int = 1\nf: Int -> [Int] = {x | [x]}"), Ok(vec![
+            "a -> a".to_string(),
+            "Int".to_string(),
+            "Int -> [Int]".to_string(),
+        ]));
+
+        // An annotation can make a type less general, but not more
+        assert_eq!(check("inc: Float -> Float = {x | x + 1.0}\nsame: Int -> Int = {x | x}"), Ok(vec![
+            "Float -> Float".to_string(),
+            "Int -> Int".to_string(),
+        ]));
+        assert_eq!(check("inc: a -> a = {x | x + 1}"), Err(vec![
+            "Expected type 'a -> a', found 'Int -> Int'.".to_string(),
+        ]));
+        assert_eq!(check("swap: a -> b -> (a, b) = {x y | (y, x)}"), Err(vec![
+            "Expected type 'a -> b -> (a, b)', found 'a -> b -> (b, a)'.".to_string(),
+        ]));
+        assert_eq!(check("n: String = 1"), Err(vec!["Expected type 'String', found 'Int'.".to_string()]));
+
+        // Annotated constants can still use each other before they're declared
+        let source = "isEven: Int -> Bool = {0 | true}, {n | isOdd <- (n - 1)}\nisOdd: Int -> Bool = {0 | false}, {n | isEven <- (n - 1)}";
+        assert_eq!(check(source), Ok(vec!["Int -> Bool".to_string(), "Int -> Bool".to_string()]));
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(check("fst = {(a, _) | a}\nlen = {[] | 0}, {_ : xs | 1 + (len <- xs)}\nneg = {-1 | true}, {_ | false}"), Ok(vec![
+            "(a, b) -> a".to_string(),
+            "[a] -> Int".to_string(),
+            "Int -> Bool".to_string(),
+        ]));
+        assert_eq!(check("pair = {[a, b] | (a, b)}"), Ok(vec!["[a] -> (a, a)".to_string()]));
+
+        // A name bound by a pattern hides a constant of the same name
+        assert_eq!(check("f = {(xs, x : _) | x : xs}\nxs = 1"), Ok(vec!["([a], [a]) -> [a]".to_string(), "Int".to_string()]));
+
+        assert_eq!(check("f = {(a, b) | a}, {[] | 0}"), Err(vec![
+            "Expected type '(a, b) -> a', found '[c] -> Int'.".to_string(),
+        ]));
+    }
+}
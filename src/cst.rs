@@ -0,0 +1,273 @@
+// gwn::cst: A concrete syntax tree, which keeps every byte of the source.
+//
+// Unlike the AST, the CST holds on to whitespace, comments and newlines,
+// and the exact text of each token, so printing it gives back the source
+// it came from byte for byte. This is what tools that rewrite source, like
+// a formatter, work from. The tree is shallow: the file is split into its
+// declarations the same way that the parser does it, and the tokens inside
+// of each pair of brackets are grouped together. It can be turned into
+// declarations with `Cst::to_ast`.
+
+use crate::parser::{Layout, Parser};
+use crate::scanner::{Scanner, ScannerError, Token, TokenKind};
+use crate::error::Report;
+use crate::diagnostic::Diagnostic;
+use crate::span::FileId;
+use crate::ParseResult;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    // The whole source, holding its declarations and the trivia between
+    // them, then the `Eof` token
+    File,
+
+    // A declaration, up to and including the newline that ends it
+    Decl,
+
+    // A pair of brackets and everything in between them
+    Group,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Element {
+    Node(Node),
+    Token(CstToken),
+}
+
+#[derive(Debug, Clone)]
+pub struct CstToken {
+    pub token: Token,
+
+    // The source that the token was scanned from. This isn't always its
+    // lexeme, e.g. a string's lexeme has had its escapes replaced.
+    pub text: String,
+
+    // Why the token is an `Error` token, if it is one
+    error: Option<ScannerError>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cst {
+    pub root: Node,
+}
+
+impl Cst {
+    // Build the CST of `source`. This never fails: source that can't be
+    // scanned becomes `Error` tokens, see `Cst::errors`.
+    pub fn new(source: &str, file: FileId) -> Cst {
+        let mut scanner = Scanner::new(source.to_string(), file).with_trivia();
+        let mut layout = Layout::new();
+
+        // The nodes that are still open, outermost first. The file is
+        // always at the bottom.
+        let mut open = vec![Node::new(NodeKind::File)];
+
+        loop {
+            let (token, error) = match scanner.scan_token() {
+                Ok(token) => (token, None),
+                Err(err) => (err.token(), Some(err)),
+            };
+
+            let kind = token.kind;
+            let text = source.get(token.span.start..token.span.end).unwrap_or("").to_string();
+            let token = Element::Token(CstToken { token, text, error });
+            let admitted = layout.admits(kind);
+
+            match kind {
+                TokenKind::Eof => {
+                    close_until(&mut open, NodeKind::File);
+                    open[0].children.push(token);
+                    break;
+                },
+
+                // Trivia belongs to whichever node it's in the middle of
+                TokenKind::Whitespace | TokenKind::Comment => top(&mut open).children.push(token),
+
+                // A newline that the parser sees ends the declaration
+                TokenKind::Newline if admitted => {
+                    top(&mut open).children.push(token);
+                    close_until(&mut open, NodeKind::File);
+                },
+
+                TokenKind::Newline => top(&mut open).children.push(token),
+
+                TokenKind::LeftBrace | TokenKind::LeftSquare | TokenKind::LeftParen => {
+                    open_decl(&mut open);
+                    open.push(Node::new(NodeKind::Group));
+                    top(&mut open).children.push(token);
+                },
+
+                TokenKind::RightBrace | TokenKind::RightSquare | TokenKind::RightParen => {
+                    open_decl(&mut open);
+                    top(&mut open).children.push(token);
+                    if top(&mut open).kind == NodeKind::Group {
+                        close(&mut open);
+                    }
+                },
+
+                _ => {
+                    open_decl(&mut open);
+                    top(&mut open).children.push(token);
+                },
+            }
+        }
+
+        Cst { root: open.remove(0) }
+    }
+
+    // All of the tokens in the tree, in the order they are in the source.
+    pub fn tokens(&self) -> Vec<&CstToken> {
+        let mut tokens = vec![];
+        self.root.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    // An error for each part of the source that couldn't be scanned.
+    pub fn errors(&self) -> Vec<Diagnostic> {
+        self.tokens().iter()
+            .filter_map(|token| token.error.as_ref())
+            .map(Report::diagnostic)
+            .collect()
+    }
+
+    // Parse the tree's tokens into declarations, just as if its source had
+    // been parsed.
+    pub fn to_ast(&self) -> ParseResult {
+        let tokens = self.tokens().into_iter()
+            .map(|token| match &token.error {
+                Some(err) => Err(err.clone()),
+                None => Ok(token.token.clone()),
+            })
+            .collect();
+
+        let (decls, errors) = Parser::from_tokens(tokens).parse_all();
+
+        ParseResult {
+            decls,
+            errors: errors.iter().map(Report::diagnostic).collect(),
+        }
+    }
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Node {
+        Node { kind, children: vec![] }
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a CstToken>) {
+        for child in self.children.iter() {
+            match child {
+                Element::Node(node) => node.collect_tokens(tokens),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+    }
+}
+
+// Printing a node gives back exactly the source it was built from.
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for child in self.children.iter() {
+            match child {
+                Element::Node(node) => write!(f, "{}", node)?,
+                Element::Token(token) => write!(f, "{}", token.text)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.root)
+    }
+}
+
+fn top(open: &mut [Node]) -> &mut Node {
+    open.last_mut().expect("The file node is never closed")
+}
+
+// Close the innermost open node, adding it to the node around it.
+fn close(open: &mut Vec<Node>) {
+    if open.len() > 1 {
+        let node = open.pop().unwrap();
+        top(open).children.push(Element::Node(node));
+    }
+}
+
+fn close_until(open: &mut Vec<Node>, kind: NodeKind) {
+    while open.len() > 1 && top(open).kind != kind {
+        close(open);
+    }
+}
+
+// Start a new declaration, unless the token is already inside of one.
+fn open_decl(open: &mut Vec<Node>) {
+    if top(open).kind == NodeKind::File {
+        open.push(Node::new(NodeKind::Decl));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    fn assert_round_trips(source: &str) {
+        assert_eq!(Cst::new(source, FileId::default()).to_string(), source);
+    }
+
+    #[test]
+    fn examples_round_trip() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "gwn") {
+                assert_round_trips(&fs::read_to_string(&path).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_source_round_trips() {
+        let sources = [
+            "x = \"a\\qbc\" + 1\n",
+            "x = \"a{}b\" ++ \"c\"\n",
+            "x = \"a{1 +}b\"\n",
+            "x = \"unterminated\n",
+            "x = \"\"\"\n    a\\q\n    \"\"\" ++ y\n",
+            "x = 0xfg + 1\n",
+            "x = (1 +\n  # comment\n  2\ny = ]]\n",
+            "f = {x | x $ 1} @\n\n\t\n",
+            "",
+        ];
+
+        for source in sources.iter() {
+            assert_round_trips(source);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_fault() {
+        let cst = Cst::new("x = \"a\\qbc\" + 1\n", FileId::default());
+        let errors = cst.errors();
+        assert_eq!(errors.len(), 1);
+
+        // The escape is the fault, but the error token is the whole string
+        let label = &errors[0].labels[0];
+        assert_eq!(label.span.end, 8);
+        let error = cst.tokens().into_iter().find(|token| token.token.kind == TokenKind::Error).unwrap();
+        assert_eq!(error.text, "\"a\\qbc\"");
+    }
+}
@@ -0,0 +1,460 @@
+// gwn::diagnostic: Describing a problem with a program, so that it can be
+// shown to whoever wrote it, either as a code frame for a person to read
+// or as JSON for an editor or other tool.
+
+use crate::span::{FileId, SourceMap, Span};
+
+use serde_json::{json, Value as Json};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    // Something that stops the program from being run
+    Error,
+
+    // Something that is probably a mistake, but doesn't stop the program
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// A place in the source, by line and column. Both count from 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: u32,
+    pub col: u32,
+}
+
+impl Position {
+    // Find the line and column of a byte offset into `source`.
+    pub fn of(source: &str, offset: usize) -> Position {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        let before = &source[..offset];
+        let line = before.matches('\n').count() as u32 + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let col = before[line_start..].chars().count() as u32 + 1;
+
+        Position { line, col }
+    }
+}
+
+// A span of source with a message about it. The primary label is where the
+// problem is, while secondary labels point out other places that have
+// something to do with it.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+impl Label {
+    pub fn primary(span: Span, message: &str) -> Label {
+        Label { span, message: message.to_string(), primary: true }
+    }
+
+    pub fn secondary(span: Span, message: &str) -> Label {
+        Label { primary: false, ..Label::primary(span, message) }
+    }
+
+    // The positions of the first and last characters in the label. An empty
+    // span, such as the end of the source, still takes up one character.
+    fn range(&self, source: &str) -> (Position, Position) {
+        let start = Position::of(source, self.span.start);
+        if self.span.end <= self.span.start {
+            return (start, start);
+        }
+
+        let last = source.get(..self.span.end.min(source.len()))
+            .and_then(|before| before.char_indices().next_back())
+            .map_or(self.span.start, |(i, _)| i.max(self.span.start));
+
+        (start, Position::of(source, last))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    // A short code that identifies the kind of problem, e.g. `E0002`
+    pub code: &'static str,
+
+    pub message: String,
+    pub labels: Vec<Label>,
+
+    // Extra information about the problem, and how it might be fixed
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message,
+            labels: vec![],
+            notes: vec![],
+            help: None,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: String) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, ..Diagnostic::error(code, message) }
+    }
+
+    pub fn with_label(mut self, label: Label) -> Diagnostic {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_help(mut self, help: String) -> Diagnostic {
+        self.help = Some(help);
+        self
+    }
+
+    // The label that shows where the problem is.
+    fn main_label(&self) -> Option<&Label> {
+        self.labels.iter()
+            .find(|label| label.primary)
+            .or_else(|| self.labels.first())
+    }
+
+    // Render the diagnostic as a code frame, showing the lines of each file
+    // that it points at, with each label underlined. For example:
+    // ```
+    // error[E0002]: Expected expression.
+    //  --> main.gwn:1:12
+    //   |
+    // 1 | foo = (1 + )
+    //   |            ^ expected an expression
+    // ```
+    // If `colour` is set, ANSI escape codes are used to colour it in.
+    pub fn render(&self, sources: &SourceMap, colour: bool) -> String {
+        let style = Style { colour };
+        let severity_colour = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+
+        let mut out = format!("{}{}\n",
+            style.paint(&format!("{}[{}]", self.severity, self.code), severity_colour),
+            style.paint(&format!(": {}", self.message), BOLD));
+
+        // The file with the primary label comes first, then any others that
+        // the labels point into, in the order they're labelled.
+        let mut files: Vec<FileId> = self.main_label().map(|label| label.span.file).into_iter().collect();
+        for label in self.labels.iter() {
+            if !files.contains(&label.span.file) {
+                files.push(label.span.file);
+            }
+        }
+
+        let ranges: Vec<(Position, Position)> = self.labels.iter()
+            .map(|label| label.range(sources.source(label.span.file)))
+            .collect();
+        let gutter = shown_lines(&ranges).iter().map(|line| line.to_string().len()).max().unwrap_or(1);
+        let bar = |s: &str| style.paint(s, BLUE);
+
+        for (i, &file) in files.iter().enumerate() {
+            let labels: Vec<(&Label, &(Position, Position))> = self.labels.iter().zip(ranges.iter())
+                .filter(|(label, _)| label.span.file == file)
+                .collect();
+
+            let first = if i == 0 { self.main_label() } else { labels.first().map(|(label, _)| *label) };
+            if let Some(first) = first {
+                let position = Position::of(sources.source(file), first.span.start);
+                let arrow = if i == 0 { "-->" } else { ":::" };
+                out.push_str(&format!("{}{} {}:{}:{}\n",
+                    " ".repeat(gutter), bar(arrow), sources.name(file), position.line, position.col));
+                out.push_str(&format!("{} {}\n", " ".repeat(gutter), bar("|")));
+            }
+
+            let lines: Vec<&str> = sources.source(file).lines().collect();
+            let shown = shown_lines(&labels.iter().map(|(_, range)| **range).collect::<Vec<_>>());
+
+            let mut previous: Option<u32> = None;
+            for &line in shown.iter() {
+                if matches!(previous, Some(previous) if line > previous + 1) {
+                    out.push_str(&format!("{}\n", bar("...")));
+                }
+                previous = Some(line);
+
+                let text = lines.get(line as usize - 1).copied().unwrap_or("");
+                let (expanded, columns) = expand_tabs(text);
+                let number = line.to_string();
+                out.push_str(&format!("{}{} {} {}\n", " ".repeat(gutter - number.len()), bar(&number), bar("|"), expanded));
+
+                let labels = labels.iter()
+                    .filter(|(_, (start, end))| start.line <= line && line <= end.line);
+
+                for (label, (start_pos, end_pos)) in labels {
+                    let display_col = |col: u32| columns.get(col as usize - 1).copied()
+                        .unwrap_or_else(|| expanded.chars().count() + col as usize - text.chars().count() - 1);
+
+                    // Lines in the middle of a multi-line label are underlined
+                    // from the start of the line to the end.
+                    let start = if line == start_pos.line { display_col(start_pos.col) } else { 0 };
+                    let end = if line == end_pos.line {
+                        display_col(end_pos.col)
+                    } else {
+                        expanded.chars().count().max(1) - 1
+                    };
+
+                    let (marker, marker_colour) = if label.primary { ('^', severity_colour) } else { ('-', BLUE) };
+                    let mut underline = marker.to_string().repeat(end.max(start) - start + 1);
+                    if line == end_pos.line && !label.message.is_empty() {
+                        underline.push(' ');
+                        underline.push_str(&label.message);
+                    }
+
+                    out.push_str(&format!("{} {} {}{}\n",
+                        " ".repeat(gutter), bar("|"), " ".repeat(start), style.paint(&underline, marker_colour)));
+                }
+            }
+        }
+
+        for note in self.notes.iter() {
+            out.push_str(&format!("{} {} {}\n", " ".repeat(gutter), bar("="), style.paint(&format!("note: {}", note), BOLD)));
+        }
+
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{} {} {}\n", " ".repeat(gutter), bar("="), style.paint(&format!("help: {}", help), BOLD)));
+        }
+
+        out
+    }
+
+    // Give the diagnostic as JSON. Each label has the file it's in, and both
+    // the byte offsets of its span and the line and column they fall on.
+    pub fn to_json(&self, sources: &SourceMap) -> Json {
+        let position = |file: FileId, offset: usize| {
+            let position = Position::of(sources.source(file), offset);
+            json!({"offset": offset, "line": position.line, "col": position.col})
+        };
+
+        json!({
+            "severity": self.severity.to_string(),
+            "code": self.code,
+            "message": self.message,
+            "labels": self.labels.iter().map(|label| json!({
+                "file": sources.name(label.span.file),
+                "start": position(label.span.file, label.span.start),
+                "end": position(label.span.file, label.span.end),
+                "message": label.message,
+                "primary": label.primary,
+            })).collect::<Vec<Json>>(),
+            "notes": self.notes,
+            "help": self.help,
+        })
+    }
+}
+
+// The lines that the labels cover, in order and without repeats.
+fn shown_lines(ranges: &[(Position, Position)]) -> Vec<u32> {
+    let mut lines: Vec<u32> = ranges.iter()
+        .flat_map(|(start, end)| start.line..=end.line)
+        .collect();
+
+    lines.sort_unstable();
+    lines.dedup();
+    lines
+}
+
+const TAB_WIDTH: usize = 4;
+
+// Replace the tabs in a line with spaces, so that underlines line up with
+// it. Also gives back the column each character ends up at.
+fn expand_tabs(line: &str) -> (String, Vec<usize>) {
+    let mut expanded = String::new();
+    let mut columns = vec![];
+
+    for c in line.chars() {
+        columns.push(expanded.chars().count());
+        if c == '\t' {
+            expanded.push_str(&" ".repeat(TAB_WIDTH));
+        } else {
+            expanded.push(c);
+        }
+    }
+
+    (expanded, columns)
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+struct Style {
+    colour: bool,
+}
+
+impl Style {
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.colour {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Report;
+    use crate::parser::Parser;
+
+    // A source map with `source` as the file `main.gwn`
+    fn sources(source: &str) -> SourceMap {
+        let mut sources = SourceMap::new();
+        sources.add("main.gwn".to_string(), source.to_string());
+        sources
+    }
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(FileId(0), start, end)
+    }
+
+    fn parse_error(source: &str) -> Diagnostic {
+        let (_, errors) = Parser::new(source.to_string(), FileId(0)).parse_all();
+        errors.first().expect("Expected a syntax error.").diagnostic()
+    }
+
+    #[test]
+    fn code_frame() {
+        let source = "foo = (1 + )\n";
+        assert_eq!(parse_error(source).render(&sources(source), false), concat!(
+            "error[E0002]: Expected expression.\n",
+            " --> main.gwn:1:12\n",
+            "  |\n",
+            "1 | foo = (1 + )\n",
+            "  |            ^\n",
+        ));
+    }
+
+    #[test]
+    fn labels_notes_and_help() {
+        let source = "a = 1\nb = 2\nc = 3\nd = a\n";
+        let diagnostic = Diagnostic::warning("W0000", "Something's up.".to_string())
+            .with_label(Label::primary(span(22, 23), "used here"))
+            .with_label(Label::secondary(span(0, 1), "declared here"))
+            .with_label(Label::secondary(span(10, 11), ""))
+            .with_note("A note.".to_string())
+            .with_help("Some help.".to_string());
+
+        assert_eq!(diagnostic.render(&sources(source), false), concat!(
+            "warning[W0000]: Something's up.\n",
+            " --> main.gwn:4:5\n",
+            "  |\n",
+            "1 | a = 1\n",
+            "  | - declared here\n",
+            "2 | b = 2\n",
+            "  |     -\n",
+            "...\n",
+            "4 | d = a\n",
+            "  |     ^ used here\n",
+            "  = note: A note.\n",
+            "  = help: Some help.\n",
+        ));
+    }
+
+    #[test]
+    fn labels_in_other_files() {
+        let mut sources = sources("import ./lib\nx = _y\n");
+        let lib = sources.add("lib.gwn".to_string(), "_y = 1\n".to_string());
+
+        let diagnostic = Diagnostic::error("E0003", "'_y' is private to the module 'lib.gwn'.".to_string())
+            .with_label(Label::primary(span(17, 19), "used here"))
+            .with_label(Label::secondary(Span::new(lib, 0, 2), "declared here"));
+
+        assert_eq!(diagnostic.render(&sources, false), concat!(
+            "error[E0003]: '_y' is private to the module 'lib.gwn'.\n",
+            " --> main.gwn:2:5\n",
+            "  |\n",
+            "2 | x = _y\n",
+            "  |     ^^ used here\n",
+            " ::: lib.gwn:1:1\n",
+            "  |\n",
+            "1 | _y = 1\n",
+            "  | -- declared here\n",
+        ));
+    }
+
+    #[test]
+    fn underlines_line_up_with_tabs() {
+        let source = "\tfoo = bar\n";
+        let diagnostic = Diagnostic::error("E0000", "Oops.".to_string())
+            .with_label(Label::primary(span(7, 10), "here"));
+
+        assert_eq!(diagnostic.render(&sources(source), false), concat!(
+            "error[E0000]: Oops.\n",
+            " --> main.gwn:1:8\n",
+            "  |\n",
+            "1 |     foo = bar\n",
+            "  |           ^^^ here\n",
+        ));
+    }
+
+    #[test]
+    fn errors_at_the_end_of_the_source() {
+        let source = "foo = (1 +";
+        let rendered = parse_error(source).render(&sources(source), false);
+        assert!(rendered.contains(" --> main.gwn:1:11\n"));
+        assert!(rendered.ends_with("1 | foo = (1 +\n  |           ^\n"));
+    }
+
+    #[test]
+    fn colour() {
+        let source = "foo = (1 + )\n";
+        let rendered = parse_error(source).render(&sources(source), true);
+        assert!(rendered.starts_with("\x1b[1;31merror[E0002]\x1b[0m\x1b[1m: Expected expression.\x1b[0m\n"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+
+    #[test]
+    fn json() {
+        let source = "f =\n    {0 | 1}\n";
+        let diagnostic = Diagnostic::warning("W0001", "Not every input is matched.".to_string())
+            .with_label(Label::primary(span(8, 9), "`1` isn't matched"))
+            .with_help("Add a case.".to_string());
+
+        assert_eq!(diagnostic.to_json(&sources(source)), json!({
+            "severity": "warning",
+            "code": "W0001",
+            "message": "Not every input is matched.",
+            "labels": [{
+                "file": "main.gwn",
+                "start": {"offset": 8, "line": 2, "col": 5},
+                "end": {"offset": 9, "line": 2, "col": 6},
+                "message": "`1` isn't matched",
+                "primary": true,
+            }],
+            "notes": [],
+            "help": "Add a case.",
+        }));
+
+        let source = "a = )\n";
+        assert_eq!(parse_error(source).to_json(&sources(source))["help"], Json::Null);
+    }
+}
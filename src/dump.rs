@@ -0,0 +1,403 @@
+// gwn::dump: Writing out tokens and declarations for other tools to read,
+// for `gwn tokens --format json` and `gwn ast --format json|sexpr`. Unlike
+// the Debug output of the AST, these formats are stable, so that they can
+// be diffed in golden tests.
+//
+// # JSON
+//
+// A span is `{"start": P, "end": P}`, where each position `P` is
+// `{"offset": O, "line": L, "col": C}`: `O` is a byte offset and `L` and
+// `C` count from 1, as in the JSON that errors are reported in.
+//
+// `gwn tokens` gives an array of tokens, each of which is
+// `{"kind": K, "lexeme": S, "span": SPAN}`, where `K` is the name of the
+// token's kind, e.g. `"Identifier"`. A string with holes in it also has
+// `"parts"`, which are each `{"text": S}` or `{"hole": S, "span": SPAN}`.
+//
+// `gwn ast` gives an array of declarations. Every declaration, expression
+// and pattern is an object with a `"kind"` and a `"span"`. Expressions also
+// have a `"typ"`, which is the type the checker gave them, written as in
+// GWN, or null if it never got that far. The other fields are:
+// - Declarations:
+//   - `constant`: `name`, `annotation` (a type or null), `value`
+//   - `evaluated`: `expr`
+//   - `import`: `path`
+// - Expressions:
+//   - `constant`: `name`
+//   - `unary`: `operator`, `operand`
+//   - `binary` and `logical`: `operator`, `left`, `right`
+//   - `apply`: `operator` (`"<-"` or `"->"`), `func`, `arg`
+//   - `func`: `cases`, each of which is `{"params": [PATTERN],
+//     "guards": [{"condition": EXPR, "value": EXPR}]}`. A guard without a
+//     condition has the condition `true`.
+//   - `tuple` and `list`: `elements`
+//   - `range`: `start`, `next` and `end`, the last two of which may be null
+//   - `interpolated`: `parts`, each `{"text": S}` or `{"hole": EXPR}`
+//   - `int`, `float`, `bool` and `string`: `value`. An int's value is a
+//     string of its decimal digits, so that big integers stay exact.
+// - Patterns:
+//   - `int`, `float`, `bool` and `string`: `value`, as for expressions
+//   - `identifier`: `name`
+//   - `wildcard`
+//   - `tuple` and `list`: `elements`
+//   - `cons`: `head`, `tail`
+//
+// # S-expressions
+//
+// Each node is a list of its kind, its span as `start..end` byte offsets,
+// for expressions its type in quotes (`"?"` if unknown), and then its
+// fields in the order given above, e.g. `(int 4..5 "Int" 1)`. A missing
+// field is `nil`. Names and operators are written as they are, strings are
+// quoted with the same escapes as GWN, and each function case is written
+// `(case (params PATTERN...) (guard CONDITION VALUE)...)`. Lists that
+// don't fit on one line are broken up, one field per line.
+
+use crate::ast::{Decl, Expr, ExprKind, FuncCase, InterpolatedPart, Literal, Pattern, PatternKind};
+use crate::diagnostic::Position;
+use crate::scanner::{StringPart, Token, TokenKind};
+use crate::span::Span;
+use crate::typ::Typ;
+
+use serde_json::{json, Value as Json};
+
+use std::fmt;
+
+pub fn tokens_json(tokens: &[Token], source: &str) -> Json {
+    tokens.iter()
+        .map(|token| {
+            let mut json = json!({
+                "kind": format!("{:?}", token.kind),
+                "lexeme": token.lexeme,
+                "span": span_json(token.span, source),
+            });
+
+            if token.kind == TokenKind::InterpolatedString {
+                json["parts"] = token.parts.iter()
+                    .map(|part| match part {
+                        StringPart::Text(text) => json!({"text": text}),
+                        StringPart::Hole{source: hole, span, ..} => json!({"hole": hole, "span": span_json(*span, source)}),
+                    })
+                    .collect();
+            }
+
+            json
+        })
+        .collect()
+}
+
+pub fn decls_json(decls: &[Decl], source: &str) -> Json {
+    decls.iter().map(|decl| decl_json(decl, source)).collect()
+}
+
+fn span_json(span: Span, source: &str) -> Json {
+    let position = |offset: usize| {
+        let position = Position::of(source, offset);
+        json!({"offset": offset, "line": position.line, "col": position.col})
+    };
+
+    json!({"start": position(span.start), "end": position(span.end)})
+}
+
+fn typ_json(typ: &Typ) -> Json {
+    match typ {
+        Typ::Unknown => Json::Null,
+        typ => Json::from(typ.to_string()),
+    }
+}
+
+fn decl_json(decl: &Decl, source: &str) -> Json {
+    let span = span_json(decl.span(), source);
+
+    match decl {
+        Decl::Constant(constant) => json!({
+            "kind": "constant",
+            "span": span,
+            "name": constant.name.lexeme,
+            "annotation": constant.annotation.as_ref().map(Typ::to_string),
+            "value": expr_json(&constant.value, source),
+        }),
+        Decl::Evaluated(evaluated) => json!({
+            "kind": "evaluated",
+            "span": span,
+            "expr": expr_json(&evaluated.expr, source),
+        }),
+        Decl::Import(import) => json!({
+            "kind": "import",
+            "span": span,
+            "path": import.path.lexeme,
+        }),
+    }
+}
+
+fn expr_json(expr: &Expr, source: &str) -> Json {
+    let mut json = match &expr.node {
+        ExprKind::Constant(constant) => json!({"kind": "constant", "name": constant.name.lexeme}),
+        ExprKind::Unary(unary) => json!({
+            "kind": "unary",
+            "operator": unary.operator.lexeme,
+            "operand": expr_json(&unary.operand, source),
+        }),
+        ExprKind::Binary(binary) => json!({
+            "kind": "binary",
+            "operator": binary.operator.lexeme,
+            "left": expr_json(&binary.left, source),
+            "right": expr_json(&binary.right, source),
+        }),
+        ExprKind::Logical(logical) => json!({
+            "kind": "logical",
+            "operator": logical.operator.lexeme,
+            "left": expr_json(&logical.left, source),
+            "right": expr_json(&logical.right, source),
+        }),
+        ExprKind::Apply(apply) => json!({
+            "kind": "apply",
+            "operator": apply.operator.lexeme,
+            "func": expr_json(&apply.func, source),
+            "arg": expr_json(&apply.arg, source),
+        }),
+        ExprKind::Func(func) => json!({
+            "kind": "func",
+            "cases": func.cases.iter().map(|case| case_json(case, source)).collect::<Vec<Json>>(),
+        }),
+        ExprKind::Tuple(tuple) => json!({
+            "kind": "tuple",
+            "elements": tuple.elements.iter().map(|e| expr_json(e, source)).collect::<Vec<Json>>(),
+        }),
+        ExprKind::List(list) => json!({
+            "kind": "list",
+            "elements": list.elements.iter().map(|e| expr_json(e, source)).collect::<Vec<Json>>(),
+        }),
+        ExprKind::Range(range) => json!({
+            "kind": "range",
+            "start": expr_json(&range.start, source),
+            "next": range.next.as_ref().map(|next| expr_json(next, source)),
+            "end": range.end.as_ref().map(|end| expr_json(end, source)),
+        }),
+        ExprKind::InterpolatedString(string) => json!({
+            "kind": "interpolated",
+            "parts": string.parts.iter().map(|part| match part {
+                InterpolatedPart::Text(text) => json!({"text": text}),
+                InterpolatedPart::Hole(hole) => json!({"hole": expr_json(hole, source)}),
+            }).collect::<Vec<Json>>(),
+        }),
+        ExprKind::Literal(literal) => literal_json(literal),
+    };
+
+    json["typ"] = typ_json(&expr.typ);
+    json["span"] = span_json(expr.span, source);
+    json
+}
+
+fn case_json(case: &FuncCase, source: &str) -> Json {
+    json!({
+        "params": case.params.iter().map(|p| pattern_json(p, source)).collect::<Vec<Json>>(),
+        "guards": case.guards.iter().map(|guard| json!({
+            "condition": expr_json(&guard.condition, source),
+            "value": expr_json(&guard.value, source),
+        })).collect::<Vec<Json>>(),
+    })
+}
+
+fn literal_json(literal: &Literal) -> Json {
+    match literal {
+        Literal::Int(i) => json!({"kind": "int", "value": i.to_string()}),
+        Literal::Float(x) => json!({"kind": "float", "value": x}),
+        Literal::Bool(b) => json!({"kind": "bool", "value": b}),
+        Literal::String(s) => json!({"kind": "string", "value": s}),
+    }
+}
+
+fn pattern_json(pattern: &Pattern, source: &str) -> Json {
+    let patterns = |patterns: &[Pattern]| patterns.iter().map(|p| pattern_json(p, source)).collect::<Vec<Json>>();
+
+    let mut json = match &pattern.node {
+        PatternKind::Literal(literal) => literal_json(literal),
+        PatternKind::Identifier(name) => json!({"kind": "identifier", "name": name.lexeme}),
+        PatternKind::Wildcard => json!({"kind": "wildcard"}),
+        PatternKind::Tuple(elements) => json!({"kind": "tuple", "elements": patterns(elements)}),
+        PatternKind::List(elements) => json!({"kind": "list", "elements": patterns(elements)}),
+        PatternKind::Cons(head, tail) => json!({
+            "kind": "cons",
+            "head": pattern_json(head, source),
+            "tail": pattern_json(tail, source),
+        }),
+    };
+
+    json["span"] = span_json(pattern.span, source);
+    json
+}
+
+// An S-expression, which is either an atom or a list of S-expressions
+enum Sexpr {
+    Atom(String),
+    List(Vec<Sexpr>),
+}
+
+// How wide a line of S-expressions may get before a list is broken up
+const WIDTH: usize = 80;
+
+pub fn decls_sexpr(decls: &[Decl]) -> String {
+    let mut out = String::new();
+    for decl in decls {
+        decl_sexpr(decl).write(&mut out, 0);
+        out.push('\n');
+    }
+    out
+}
+
+impl Sexpr {
+    fn atom(atom: impl ToString) -> Sexpr {
+        Sexpr::Atom(atom.to_string())
+    }
+
+    fn quoted(text: &str) -> Sexpr {
+        Sexpr::Atom(format!("{:?}", text))
+    }
+
+    // Write the S-expression indented by `indent`, breaking up lists that
+    // would go past `WIDTH`. The leading atoms of a broken list stay on its
+    // first line, and the rest of its elements get a line each.
+    fn write(&self, out: &mut String, indent: usize) {
+        let flat = self.to_string();
+        let elements = match self {
+            Sexpr::List(elements) if indent + flat.len() > WIDTH => elements,
+            _ => {
+                out.push_str(&flat);
+                return;
+            },
+        };
+
+        let head = elements.iter().take_while(|e| matches!(e, Sexpr::Atom(_))).count().max(1);
+
+        out.push('(');
+        for (i, element) in elements.iter().enumerate() {
+            if i == 0 {
+                element.write(out, indent + 1);
+            } else if i < head {
+                out.push(' ');
+                element.write(out, indent + 1);
+            } else {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent + 2));
+                element.write(out, indent + 2);
+            }
+        }
+        out.push(')');
+    }
+}
+
+impl fmt::Display for Sexpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sexpr::Atom(atom) => write!(f, "{}", atom),
+            Sexpr::List(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+fn span_sexpr(span: Span) -> Sexpr {
+    Sexpr::Atom(format!("{}..{}", span.start, span.end))
+}
+
+fn node(kind: &str, span: Span, fields: Vec<Sexpr>) -> Sexpr {
+    let mut elements = vec![Sexpr::atom(kind), span_sexpr(span)];
+    elements.extend(fields);
+    Sexpr::List(elements)
+}
+
+fn decl_sexpr(decl: &Decl) -> Sexpr {
+    match decl {
+        Decl::Constant(constant) => node("constant", decl.span(), vec![
+            Sexpr::atom(&constant.name.lexeme),
+            constant.annotation.as_ref().map_or_else(|| Sexpr::atom("nil"), |typ| Sexpr::quoted(&typ.to_string())),
+            expr_sexpr(&constant.value),
+        ]),
+        Decl::Evaluated(evaluated) => node("evaluated", decl.span(), vec![expr_sexpr(&evaluated.expr)]),
+        Decl::Import(import) => node("import", decl.span(), vec![Sexpr::quoted(&import.path.lexeme)]),
+    }
+}
+
+fn expr_sexpr(expr: &Expr) -> Sexpr {
+    let exprs = |exprs: &[Expr]| exprs.iter().map(expr_sexpr).collect::<Vec<Sexpr>>();
+    let optional = |expr: &Option<Expr>| expr.as_ref().map_or_else(|| Sexpr::atom("nil"), expr_sexpr);
+
+    let (kind, fields) = match &expr.node {
+        ExprKind::Constant(constant) => ("constant", vec![Sexpr::atom(&constant.name.lexeme)]),
+        ExprKind::Unary(unary) => ("unary", vec![Sexpr::atom(&unary.operator.lexeme), expr_sexpr(&unary.operand)]),
+        ExprKind::Binary(binary) => ("binary", vec![
+            Sexpr::atom(&binary.operator.lexeme),
+            expr_sexpr(&binary.left),
+            expr_sexpr(&binary.right),
+        ]),
+        ExprKind::Logical(logical) => ("logical", vec![
+            Sexpr::atom(&logical.operator.lexeme),
+            expr_sexpr(&logical.left),
+            expr_sexpr(&logical.right),
+        ]),
+        ExprKind::Apply(apply) => ("apply", vec![
+            Sexpr::atom(&apply.operator.lexeme),
+            expr_sexpr(&apply.func),
+            expr_sexpr(&apply.arg),
+        ]),
+        ExprKind::Func(func) => ("func", func.cases.iter().map(case_sexpr).collect()),
+        ExprKind::Tuple(tuple) => ("tuple", exprs(&tuple.elements)),
+        ExprKind::List(list) => ("list", exprs(&list.elements)),
+        ExprKind::Range(range) => ("range", vec![expr_sexpr(&range.start), optional(&range.next), optional(&range.end)]),
+        ExprKind::InterpolatedString(string) => ("interpolated", string.parts.iter()
+            .map(|part| match part {
+                InterpolatedPart::Text(text) => Sexpr::quoted(text),
+                InterpolatedPart::Hole(hole) => expr_sexpr(hole),
+            })
+            .collect()),
+        ExprKind::Literal(literal) => literal_sexpr(literal),
+    };
+
+    let mut elements = vec![Sexpr::atom(kind), span_sexpr(expr.span), Sexpr::quoted(&expr.typ.to_string())];
+    elements.extend(fields);
+    Sexpr::List(elements)
+}
+
+fn case_sexpr(case: &FuncCase) -> Sexpr {
+    let mut params = vec![Sexpr::atom("params")];
+    params.extend(case.params.iter().map(pattern_sexpr));
+
+    let mut elements = vec![Sexpr::atom("case"), Sexpr::List(params)];
+    elements.extend(case.guards.iter().map(|guard| Sexpr::List(vec![
+        Sexpr::atom("guard"),
+        expr_sexpr(&guard.condition),
+        expr_sexpr(&guard.value),
+    ])));
+
+    Sexpr::List(elements)
+}
+
+fn literal_sexpr(literal: &Literal) -> (&'static str, Vec<Sexpr>) {
+    match literal {
+        Literal::Int(i) => ("int", vec![Sexpr::atom(i)]),
+        Literal::Float(x) => ("float", vec![Sexpr::atom(format!("{:?}", x))]),
+        Literal::Bool(b) => ("bool", vec![Sexpr::atom(b)]),
+        Literal::String(s) => ("string", vec![Sexpr::quoted(s)]),
+    }
+}
+
+fn pattern_sexpr(pattern: &Pattern) -> Sexpr {
+    let (kind, fields) = match &pattern.node {
+        PatternKind::Literal(literal) => literal_sexpr(literal),
+        PatternKind::Identifier(name) => ("identifier", vec![Sexpr::atom(&name.lexeme)]),
+        PatternKind::Wildcard => ("wildcard", vec![]),
+        PatternKind::Tuple(elements) => ("tuple", elements.iter().map(pattern_sexpr).collect()),
+        PatternKind::List(elements) => ("list", elements.iter().map(pattern_sexpr).collect()),
+        PatternKind::Cons(head, tail) => ("cons", vec![pattern_sexpr(head), pattern_sexpr(tail)]),
+    };
+
+    node(kind, pattern.span, fields)
+}
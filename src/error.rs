@@ -1,23 +1,7 @@
-pub trait Report {
-    fn position(&self) -> (u32, u32);
-    fn message(&self) -> &String;
-    fn place(&self) -> String;
-    fn report_in(&self, source: &String) {
-        let (line_number, col_number) = self.position();
-        let mut line_contents = "";
-
-        for (i, line) in source.lines().enumerate() {
-            line_contents = line;
-            if i == line_number as usize {
-                break;
-            }
-        }
+use crate::diagnostic::Diagnostic;
 
-        let mut col_space = String::new();
-        for i in (1..col_number) {
-            col_space.push(' ');
-        }
-
-        eprintln!("[line {}] Error{}:\n    {}\n    {}^\n{}\n", line_number, self.place(), line_contents, col_space, *self.message())
-    }
-}
\ No newline at end of file
+// Anything that can go wrong with a program, from a stray character to a
+// runtime error, is reported by turning it into a Diagnostic.
+pub trait Report {
+    fn diagnostic(&self) -> Diagnostic;
+}
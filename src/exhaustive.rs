@@ -0,0 +1,472 @@
+// gwn::exhaustive: Checking that the cases of each function match every
+// input, and that every case and guard can be reached.
+//
+// The parameters of a function's cases make up a matrix of patterns, with
+// a row for each case. This works out whether a row of patterns is 'useful'
+// against the rows above it, i.e. whether there is any input that it
+// matches and they don't, following Maranget's "Warnings for pattern
+// matching". A case is unreachable if it isn't useful, and the cases are
+// missing something if a row of wildcards is useful against all of them.
+//
+// Only cases that are sure to give a value once they match, i.e. ones with
+// an `else` guard or no guards at all, count towards covering the input.
+
+use crate::ast::{Decl, Expr, ExprKind, FuncExpr, FuncCase, FuncGuard, InterpolatedPart, Literal, Pattern, PatternKind};
+use crate::span::Span;
+use crate::int::Int;
+use crate::diagnostic::{Diagnostic, Label};
+
+// Check every function in `decls`, giving back a warning for each problem.
+pub fn check(decls: &[Decl]) -> Vec<Diagnostic> {
+    let mut warnings = vec![];
+
+    for decl in decls {
+        match decl {
+            Decl::Constant(constant) => check_expr(&constant.value, &mut warnings),
+            Decl::Evaluated(evaluated) => check_expr(&evaluated.expr, &mut warnings),
+            Decl::Import(_) => {},
+        }
+    }
+
+    warnings
+}
+
+fn check_expr(expr: &Expr, warnings: &mut Vec<Diagnostic>) {
+    match &expr.node {
+        ExprKind::Literal(_) | ExprKind::Constant(_) => {},
+        ExprKind::Unary(unary) => check_expr(&unary.operand, warnings),
+        ExprKind::Binary(binary) => {
+            check_expr(&binary.left, warnings);
+            check_expr(&binary.right, warnings);
+        },
+        ExprKind::Logical(logical) => {
+            check_expr(&logical.left, warnings);
+            check_expr(&logical.right, warnings);
+        },
+        ExprKind::Apply(apply) => {
+            check_expr(&apply.func, warnings);
+            check_expr(&apply.arg, warnings);
+        },
+        ExprKind::Tuple(tuple) => tuple.elements.iter().for_each(|e| check_expr(e, warnings)),
+        ExprKind::List(list) => list.elements.iter().for_each(|e| check_expr(e, warnings)),
+        ExprKind::InterpolatedString(string) => {
+            for part in string.parts.iter() {
+                if let InterpolatedPart::Hole(hole) = part {
+                    check_expr(hole, warnings);
+                }
+            }
+        },
+        ExprKind::Range(range) => {
+            check_expr(&range.start, warnings);
+            range.next.iter().chain(range.end.iter()).for_each(|e| check_expr(e, warnings));
+        },
+        ExprKind::Func(func) => {
+            check_func(func, warnings);
+
+            for guard in func.cases.iter().flat_map(|case| case.guards.iter()) {
+                check_expr(&guard.condition, warnings);
+                check_expr(&guard.value, warnings);
+            }
+        },
+    }
+}
+
+fn check_func(func: &FuncExpr, warnings: &mut Vec<Diagnostic>) {
+    let arity = func.cases[0].params.len();
+
+    // The rows of the cases seen so far that always give a value once they
+    // match, and the rows of every case seen so far.
+    let mut covering: Vec<Vec<Pat>> = vec![];
+    let mut all: Vec<Vec<Pat>> = vec![];
+
+    for case in func.cases.iter() {
+        let row: Vec<Pat> = case.params.iter().map(Pat::from_pattern).collect();
+
+        if !useful(&covering, &row) {
+            warnings.push(Diagnostic::warning("W0002", "This case can never be reached.".to_string())
+                .with_label(Label::primary(case_span(case), "the cases before this one match everything it does")));
+        }
+
+        check_guards(case, warnings);
+
+        if is_covering(case) {
+            covering.push(row.clone());
+        }
+        all.push(row);
+    }
+
+    if let Some(witness) = missing(&covering, arity) {
+        let example = show_params(&witness);
+        let mut warning = Diagnostic::warning("W0001", "The cases of this function don't match every input.".to_string())
+            .with_label(Label::primary(func.brace.span, &format!("`{}` isn't matched", example)));
+
+        // If one of the cases does match the example, it must be that all
+        // of its guards can fail.
+        warning = if useful(&all, &witness) {
+            warning.with_help(format!("Add a case that matches `{}`.", example))
+        } else {
+            warning
+                .with_note(format!("A case matches `{}`, but none of its guards might hold.", example))
+                .with_help("Add an `else` guard to the case.".to_string())
+        };
+
+        warnings.push(warning);
+    }
+}
+
+// Whether a case is sure to give a value once its parameters match.
+fn is_covering(case: &FuncCase) -> bool {
+    case.guards.iter().any(|guard| is_irrefutable(&guard.condition))
+}
+
+// Whether a guard's condition always holds, as with `else` or a case with
+// no guards at all.
+fn is_irrefutable(condition: &Expr) -> bool {
+    matches!(condition.node, ExprKind::Literal(Literal::Bool(true)))
+}
+
+// Any guards after one that always holds can never be reached.
+fn check_guards(case: &FuncCase, warnings: &mut Vec<Diagnostic>) {
+    let first = case.guards.iter().position(|guard| is_irrefutable(&guard.condition));
+
+    if let Some(first) = first {
+        for guard in case.guards.iter().skip(first + 1) {
+            warnings.push(Diagnostic::warning("W0003", "This guard can never be reached.".to_string())
+                .with_label(Label::primary(guard_span(guard), ""))
+                .with_label(Label::secondary(guard_span(&case.guards[first]), "this guard always holds")));
+        }
+    }
+}
+
+// The opening brace and parameters of a case.
+fn case_span(case: &FuncCase) -> Span {
+    case.params.iter().fold(case.brace.span, |span, param| span.to(param.span()))
+}
+
+fn guard_span(guard: &FuncGuard) -> Span {
+    guard.condition.span().to(guard.value.span())
+}
+
+// A pattern, boiled down to either something that matches anything, or a
+// constructor applied to some sub-patterns. List patterns like `[a, b]`
+// become `a : b : []`.
+#[derive(Debug, Clone, PartialEq)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Bool(bool),
+    Int(Int),
+    Float(f64),
+    String(String),
+    Tuple(usize),
+    Nil,
+    Cons,
+}
+
+impl Ctor {
+    // The number of sub-patterns the constructor takes.
+    fn arity(&self) -> usize {
+        match self {
+            Ctor::Tuple(size) => *size,
+            Ctor::Cons => 2,
+            _ => 0,
+        }
+    }
+}
+
+impl Pat {
+    fn from_pattern(pattern: &Pattern) -> Pat {
+        match &pattern.node {
+            PatternKind::Identifier(_) | PatternKind::Wildcard => Pat::Wild,
+            PatternKind::Literal(literal) => Pat::Ctor(literal_ctor(literal), vec![]),
+            PatternKind::Tuple(elements) =>
+                Pat::Ctor(Ctor::Tuple(elements.len()), elements.iter().map(Pat::from_pattern).collect()),
+            PatternKind::Cons(head, tail) =>
+                Pat::Ctor(Ctor::Cons, vec![Pat::from_pattern(head), Pat::from_pattern(tail)]),
+            PatternKind::List(elements) => elements.iter().rev()
+                .fold(Pat::Ctor(Ctor::Nil, vec![]), |tail, head| {
+                    Pat::Ctor(Ctor::Cons, vec![Pat::from_pattern(head), tail])
+                }),
+        }
+    }
+}
+
+fn literal_ctor(literal: &Literal) -> Ctor {
+    match literal {
+        Literal::Int(i) => Ctor::Int(i.clone()),
+        Literal::Float(x) => Ctor::Float(*x),
+        Literal::Bool(b) => Ctor::Bool(*b),
+        Literal::String(s) => Ctor::String(s.clone()),
+    }
+}
+
+// Whether there is any input that `row` matches but none of `rows` do.
+fn useful(rows: &[Vec<Pat>], row: &[Pat]) -> bool {
+    let (first, rest) = match row.split_first() {
+        Some(split) => split,
+        None => return rows.is_empty(),
+    };
+
+    match first {
+        Pat::Ctor(ctor, args) => {
+            let row: Vec<Pat> = args.iter().chain(rest.iter()).cloned().collect();
+            useful(&specialise(rows, ctor), &row)
+        },
+        Pat::Wild => match complete_signature(&head_ctors(rows)) {
+            Some(ctors) => ctors.iter().any(|ctor| {
+                let row: Vec<Pat> = wildcards(ctor.arity()).into_iter().chain(rest.iter().cloned()).collect();
+                useful(&specialise(rows, ctor), &row)
+            }),
+            None => useful(&default(rows), rest),
+        },
+    }
+}
+
+// Find a row of `width` patterns that none of `rows` match, if there is one.
+fn missing(rows: &[Vec<Pat>], width: usize) -> Option<Vec<Pat>> {
+    if width == 0 {
+        return if rows.is_empty() { Some(vec![]) } else { None };
+    }
+
+    let ctors = head_ctors(rows);
+
+    match complete_signature(&ctors) {
+        Some(all) => all.into_iter().find_map(|ctor| {
+            let arity = ctor.arity();
+            let mut witness = missing(&specialise(rows, &ctor), arity + width - 1)?;
+            let rest = witness.split_off(arity);
+
+            let mut row = vec![Pat::Ctor(ctor, witness)];
+            row.extend(rest);
+            Some(row)
+        }),
+        None => {
+            let mut row = missing(&default(rows), width - 1)?;
+
+            // Anything would do when no constructors appear in this column,
+            // but otherwise give one that the rows don't mention.
+            let first = match unused_ctor(&ctors) {
+                Some(ctor) => Pat::Ctor(ctor.clone(), wildcards(ctor.arity())),
+                None => Pat::Wild,
+            };
+
+            row.insert(0, first);
+            Some(row)
+        },
+    }
+}
+
+// The rows that match an input starting with `ctor`, with the first pattern
+// replaced by the sub-patterns under `ctor`.
+fn specialise(rows: &[Vec<Pat>], ctor: &Ctor) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| match &row[0] {
+            Pat::Ctor(c, args) if c == ctor =>
+                Some(args.iter().chain(row[1..].iter()).cloned().collect()),
+            Pat::Ctor(..) => None,
+            Pat::Wild =>
+                Some(wildcards(ctor.arity()).into_iter().chain(row[1..].iter().cloned()).collect()),
+        })
+        .collect()
+}
+
+// The rows that start with a wildcard, without it.
+fn default(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| row[0] == Pat::Wild)
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+// The constructors that the rows start with, without repeats.
+fn head_ctors(rows: &[Vec<Pat>]) -> Vec<Ctor> {
+    let mut ctors = vec![];
+
+    for row in rows {
+        if let Pat::Ctor(ctor, _) = &row[0] {
+            if !ctors.contains(ctor) {
+                ctors.push(ctor.clone());
+            }
+        }
+    }
+
+    ctors
+}
+
+// If `ctors` covers every value of its type, give back every constructor
+// of that type. The type checker has already made sure that they all
+// belong to the same type.
+fn complete_signature(ctors: &[Ctor]) -> Option<Vec<Ctor>> {
+    let all = match ctors.first()? {
+        Ctor::Bool(_) => vec![Ctor::Bool(false), Ctor::Bool(true)],
+        Ctor::Tuple(size) => vec![Ctor::Tuple(*size)],
+        Ctor::Nil | Ctor::Cons => vec![Ctor::Nil, Ctor::Cons],
+
+        // There are too many numbers and strings to list
+        Ctor::Int(_) | Ctor::Float(_) | Ctor::String(_) => return None,
+    };
+
+    if all.iter().all(|ctor| ctors.contains(ctor)) {
+        Some(all)
+    } else {
+        None
+    }
+}
+
+// A constructor of the same type as `ctors` that isn't one of them.
+fn unused_ctor(ctors: &[Ctor]) -> Option<Ctor> {
+    let unused = |candidate: &Ctor| !ctors.contains(candidate);
+    let unused_of = |candidates: Vec<Ctor>| candidates.into_iter().find(|c| unused(c));
+
+    match ctors.first()? {
+        Ctor::Bool(_) => unused_of(vec![Ctor::Bool(false), Ctor::Bool(true)]),
+        Ctor::Nil | Ctor::Cons => unused_of(vec![Ctor::Nil, Ctor::Cons]),
+        Ctor::Tuple(_) => None,
+        Ctor::Int(_) => (0..).map(|i| Ctor::Int(Int::from(i))).find(unused),
+        Ctor::Float(_) => (0..).map(|i| Ctor::Float(i as f64)).find(unused),
+        Ctor::String(_) => (0..).map(|i| Ctor::String("a".repeat(i))).find(unused),
+    }
+}
+
+fn wildcards(count: usize) -> Vec<Pat> {
+    vec![Pat::Wild; count]
+}
+
+// Display a row of patterns the way they would be written as the
+// parameters of a case.
+fn show_params(row: &[Pat]) -> String {
+    let params: Vec<String> = row.iter()
+        .map(|pat| match pat {
+            Pat::Ctor(Ctor::Cons, _) if row.len() > 1 && as_list(pat).is_none() =>
+                format!("({})", show(pat)),
+            _ => show(pat),
+        })
+        .collect();
+
+    params.join(" ")
+}
+
+fn show(pat: &Pat) -> String {
+    if let Some(elements) = as_list(pat) {
+        let elements: Vec<String> = elements.iter().map(|e| show(e)).collect();
+        return format!("[{}]", elements.join(", "));
+    }
+
+    match pat {
+        Pat::Wild => "_".to_string(),
+        Pat::Ctor(ctor, args) => match ctor {
+            Ctor::Bool(b) => b.to_string(),
+            Ctor::Int(i) => i.to_string(),
+            Ctor::Float(x) => format!("{:?}", x),
+            Ctor::String(s) => format!("{:?}", s),
+            Ctor::Tuple(_) => {
+                let elements: Vec<String> = args.iter().map(show).collect();
+                format!("({})", elements.join(", "))
+            },
+            Ctor::Nil => "[]".to_string(),
+
+            // `:` is right associative, so only a list on the left needs brackets
+            Ctor::Cons => match &args[0] {
+                head @ Pat::Ctor(Ctor::Cons, _) if as_list(head).is_none() =>
+                    format!("({}) : {}", show(head), show(&args[1])),
+                head => format!("{} : {}", show(head), show(&args[1])),
+            },
+        },
+    }
+}
+
+// The elements of a pattern that matches a list of an exact length.
+fn as_list(pat: &Pat) -> Option<Vec<&Pat>> {
+    let mut elements = vec![];
+    let mut pat = pat;
+
+    loop {
+        match pat {
+            Pat::Ctor(Ctor::Nil, _) => return Some(elements),
+            Pat::Ctor(Ctor::Cons, args) => {
+                elements.push(&args[0]);
+                pat = &args[1];
+            },
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::span::FileId;
+
+    fn warnings(source: &str) -> Vec<Diagnostic> {
+        let (decls, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "{:?} doesn't parse", source);
+        check(&decls)
+    }
+
+    fn codes(source: &str) -> Vec<&'static str> {
+        warnings(source).iter().map(|warning| warning.code).collect()
+    }
+
+    // The example of an input that isn't matched, from the one warning
+    fn witness(source: &str) -> String {
+        let warnings = warnings(source);
+        assert_eq!(warnings.len(), 1, "{:?} should have one warning", source);
+        assert_eq!(warnings[0].code, "W0001");
+        warnings[0].labels[0].message.clone()
+    }
+
+    #[test]
+    fn exhaustive_functions() {
+        assert!(codes("f = {0 | 1}, {n | n}\n").is_empty());
+        assert!(codes("f = {true | 1}, {false | 0}\n").is_empty());
+        assert!(codes("f = {[] | 0}, {x : xs | 1}\n").is_empty());
+        assert!(codes("f = {(a, b) | a}\n").is_empty());
+        assert!(codes("f = {[] _ | 0}, {_ [] | 1}, {(x : xs) (y : ys) | 2}\n").is_empty());
+        assert!(codes("f = {x | x > 0 ? 1 | else ? 0}\n").is_empty());
+    }
+
+    #[test]
+    fn missing_cases_have_a_witness() {
+        assert_eq!(witness("f = {0 | 1}\n"), "`1` isn't matched");
+        assert_eq!(witness("f = {true | 1}\n"), "`false` isn't matched");
+        assert_eq!(witness("f = {[] | 0}\n"), "`_ : _` isn't matched");
+        assert_eq!(witness("f = {x : xs | 1}\n"), "`[]` isn't matched");
+        assert_eq!(witness("f = {[] | 0}, {[x] | 1}\n"), "`_ : _ : _` isn't matched");
+        assert_eq!(witness("f = {(true, x) | 0}\n"), "`(false, _)` isn't matched");
+        assert_eq!(witness("f = {[] _ | 0}, {_ [] | 1}\n"), "`(_ : _) (_ : _)` isn't matched");
+    }
+
+    #[test]
+    fn guards_that_can_fail_leave_inputs_unmatched() {
+        let warnings = warnings("f = {x | x > 0 ? 1}\n");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].help.as_deref(), Some("Add an `else` guard to the case."));
+    }
+
+    #[test]
+    fn unreachable_cases() {
+        assert_eq!(codes("f = {n | n}, {0 | 1}\n"), ["W0002"]);
+        assert_eq!(codes("f = {_ : _ | 0}, {[x] | 1}, {[] | 2}\n"), ["W0002"]);
+        assert_eq!(codes("f = {true | 1}, {false | 0}, {_ | 2}\n"), ["W0002"]);
+
+        // A case whose guards can fail doesn't hide the ones after it
+        assert!(codes("f = {n | n > 0 ? n}, {0 | 1}, {_ | 2}\n").is_empty());
+    }
+
+    #[test]
+    fn unreachable_guards() {
+        assert_eq!(codes("f = {x | else ? 1 | x > 0 ? 2}\n"), ["W0003"]);
+        assert_eq!(codes("f = {x | 1 | 2 | 3}\n"), ["W0003", "W0003"]);
+    }
+
+    #[test]
+    fn nested_functions_are_checked() {
+        assert_eq!(codes("f = {x | {0 | x}}\n"), ["W0001"]);
+        assert_eq!(codes("print <- [1, 2] -> map <- {n | n}, {0 | 0}\n"), ["W0002"]);
+    }
+}
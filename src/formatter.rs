@@ -0,0 +1,660 @@
+// gwn::formatter: Formatting source the one canonical way, for `gwn fmt`.
+//
+// The formatter works from the CST, so comments are kept where they were.
+// Line breaks are mostly left as they were written too, apart from the
+// cases of a function that goes over several lines, which each get a line
+// of their own. Everything else is redone: the spacing between tokens, the
+// indentation of each line and the alignment of guards, e.g.
+// ```
+// fizzBuzz =
+//     {x | x % 15 == 0 ? "FizzBuzz"
+//        | x % 5 == 0  ? "Buzz"
+//        | else        ? x -> toString}
+// ```
+// Formatting source that is already formatted doesn't change it.
+
+use crate::cst::{Cst, Element, Node};
+use crate::scanner::{Token, TokenKind};
+use crate::diagnostic::Diagnostic;
+use crate::span::FileId;
+
+use std::collections::HashMap;
+
+// How much further a line is indented when it continues the one before it
+const INDENT: usize = 4;
+
+// The source couldn't be formatted, through no fault of its own
+const FORMATTER_BUG: &str = "E0006";
+
+// Format `source`, or give back the errors that stopped it from parsing.
+// Source with errors isn't formatted, as the formatter could easily make
+// things worse.
+pub fn format(source: &str, file: FileId) -> Result<String, Vec<Diagnostic>> {
+    let cst = Cst::new(source, file);
+
+    let result = cst.to_ast();
+    if !result.is_ok() {
+        return Err(result.errors);
+    }
+
+    let items = items(&cst.root);
+
+    // The first pass works out where the guards go, and the second lines
+    // them up. Where a guard would go doesn't depend on how the others are
+    // lined up, so the second pass should find them all where the first
+    // one did.
+    let unaligned = HashMap::new();
+    let mut measure = Printer::new(&unaligned);
+    measure.print(&items);
+    let targets = measure.alignments();
+
+    let mut printer = Printer::new(&targets);
+    printer.print(&items);
+
+    if printer.alignments() != targets {
+        return Err(vec![Diagnostic::error(FORMATTER_BUG, "The guards in this file can't be lined up.".to_string())
+            .with_note("This is a bug in the formatter, so the file has been left as it was.".to_string())]);
+    }
+
+    Ok(printer.out)
+}
+
+// The source as the formatter sees it: the tokens that matter, and where
+// the lines break.
+#[derive(Debug, Clone, Copy)]
+enum Item<'a> {
+    // A token and the source it was scanned from. Whitespace and newlines
+    // are left out.
+    Token(&'a Token, &'a str),
+
+    // The end of a line, and whether a blank line should follow it
+    Break(bool),
+
+    // The start and end of a declaration. A declaration starts with
+    // whether it's a constant with a type, e.g. `inc: Int -> Int = ...`,
+    // rather than an expression such as `x : xs`.
+    Decl(bool),
+    End,
+}
+
+fn items(root: &Node) -> Vec<Item<'_>> {
+    let mut items = vec![];
+
+    for child in root.children.iter() {
+        match child {
+            Element::Node(decl) => {
+                let mut decl_items = vec![];
+                collect_items(decl, &mut decl_items);
+                lay_out_cases(&mut decl_items);
+
+                // The declaration is over before the newline that ends it
+                let last = match decl_items.last() {
+                    Some(Item::Break(_)) => decl_items.pop(),
+                    _ => None,
+                };
+
+                // As in the parser, a name and `:` only start a type if
+                // there's an `=` after them
+                let annotated = decl_items.iter().any(|item| matches!(item, Item::Token(token, _) if token.kind == TokenKind::Equal));
+
+                items.push(Item::Decl(annotated));
+                items.append(&mut decl_items);
+                items.push(Item::End);
+                items.extend(last);
+            },
+
+            Element::Token(token) => push_token(&mut items, &token.token, &token.text),
+        }
+    }
+
+    items
+}
+
+fn collect_items<'a>(node: &'a Node, items: &mut Vec<Item<'a>>) {
+    for child in node.children.iter() {
+        match child {
+            Element::Node(node) => collect_items(node, items),
+            Element::Token(token) => push_token(items, &token.token, &token.text),
+        }
+    }
+}
+
+fn push_token<'a>(items: &mut Vec<Item<'a>>, token: &'a Token, text: &'a str) {
+    match token.kind {
+        TokenKind::Whitespace | TokenKind::Eof => {},
+
+        // However many blank lines there are, only one is kept
+        TokenKind::Newline => match items.last_mut() {
+            Some(Item::Break(blank)) => *blank = true,
+            _ => items.push(Item::Break(false)),
+        },
+
+        _ => items.push(Item::Token(token, text)),
+    }
+}
+
+// Give each case of a function that goes over several lines a line of its
+// own, including the first one if it comes straight after the `=` of the
+// declaration, e.g.
+// ```
+// f = {0 | 1}, {n | n *
+//     f <- (n - 1)}
+// ```
+// becomes
+// ```
+// f =
+//     {0 | 1},
+//     {n | n *
+//          f <- (n - 1)}
+// ```
+fn lay_out_cases(items: &mut Vec<Item>) {
+    // Where each token other than a comment is
+    let tokens: Vec<usize> = items.iter()
+        .enumerate()
+        .filter(|(_, item)| matches!(item, Item::Token(token, _) if token.kind != TokenKind::Comment))
+        .map(|(i, _)| i)
+        .collect();
+
+    let kind = |n: usize| match items[tokens[n]] {
+        Item::Token(token, _) => token.kind,
+        _ => TokenKind::None,
+    };
+
+    let has_break = |from: usize, to: usize| items[from..to].iter().any(|item| matches!(item, Item::Break(_)));
+
+    // The closing bracket of each opening bracket, both as indices into
+    // `tokens`, and the kind of bracket that each token is inside of
+    let mut closes = HashMap::new();
+    let mut inside = vec![];
+    let mut open = vec![];
+    for n in 0..tokens.len() {
+        inside.push(open.last().map_or(TokenKind::None, |opening| kind(*opening)));
+
+        if is_opening(kind(n)) {
+            open.push(n);
+        } else if is_closing(kind(n)) {
+            if let Some(opening) = open.pop() {
+                closes.insert(opening, n);
+            }
+        }
+    }
+
+    let mut breaks = vec![];
+
+    for n in 0..tokens.len() {
+        // Only start from the first case of each function. As in the
+        // parser, a comma followed by a brace continues the cases, except
+        // between the elements of a tuple or list.
+        if kind(n) != TokenKind::LeftBrace
+            || matches!(inside[n], TokenKind::LeftParen | TokenKind::LeftSquare)
+            || (n >= 2 && kind(n - 1) == TokenKind::Comma && kind(n - 2) == TokenKind::RightBrace) {
+            continue;
+        }
+
+        let mut separators = vec![];
+        let mut close = closes[&n];
+        while close + 2 < tokens.len() && kind(close + 1) == TokenKind::Comma && kind(close + 2) == TokenKind::LeftBrace {
+            separators.push(close + 1);
+            close = closes[&(close + 2)];
+        }
+
+        if separators.is_empty() || !has_break(tokens[n], tokens[close]) {
+            continue;
+        }
+
+        if n >= 1 && kind(n - 1) == TokenKind::Equal && !has_break(tokens[n - 1], tokens[n]) {
+            breaks.push(tokens[n]);
+        }
+
+        for separator in separators {
+            if !has_break(tokens[separator], tokens[separator + 1]) {
+                breaks.push(tokens[separator + 1]);
+            }
+        }
+    }
+
+    breaks.sort_unstable();
+    for position in breaks.into_iter().rev() {
+        items.insert(position, Item::Break(false));
+    }
+}
+
+// A bracket that is still open, while printing
+struct Group {
+    // Where the opening bracket is
+    line: usize,
+    col: usize,
+
+    // The indentation of the line that the bracket is on
+    indent: usize,
+
+    // Where the first token inside of the brackets is, if it is on the
+    // same line as the opening bracket. The lines after it line up with
+    // it, otherwise they are indented.
+    content: Option<usize>,
+    seen_content: bool,
+
+    // For the case of a function, which case it is and the line and column
+    // of its '|'
+    case: Option<usize>,
+    pipe: Option<(usize, usize)>,
+
+    // The last line that a guard's '?' was lined up on
+    row: Option<usize>,
+}
+
+// A guard's '?' that has been lined up with the others of its case
+struct Guard {
+    col: usize,
+    case: usize,
+
+    // Where it would be without being lined up, see `Printer::token`, and
+    // how far it was moved to line it up
+    natural: usize,
+    shift: usize,
+}
+
+struct Printer<'a> {
+    out: String,
+
+    // Where the next character will go, counting from 0
+    line: usize,
+    col: usize,
+
+    // Whether anything has been printed on the current line, and its
+    // indentation if so
+    line_empty: bool,
+    indent: usize,
+
+    // Whether there should be a blank line before the next line
+    blank: bool,
+
+    // Whether a declaration is being printed, and if it's still on its
+    // first line
+    in_decl: bool,
+    first_line: bool,
+
+    groups: Vec<Group>,
+
+    // The last token of the declaration printed, other than comments, and
+    // whether it was a '-' that negates what comes after it
+    prev: Option<TokenKind>,
+    unary: bool,
+
+    // How many tokens of the declaration have been printed, other than
+    // comments
+    count: usize,
+
+    // Whether the declaration is a constant with a type
+    annotated: bool,
+
+    // How many function cases have been printed, which numbers them
+    cases: usize,
+
+    // The column to line up the guards of each case at, from the first
+    // pass, and the columns that each guard's '?' would be at otherwise.
+    // Both count from the case's '|'.
+    targets: &'a HashMap<usize, usize>,
+    naturals: HashMap<usize, Vec<usize>>,
+
+    // The guards lined up on the current line so far
+    guards: Vec<Guard>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(targets: &'a HashMap<usize, usize>) -> Printer<'a> {
+        Printer {
+            out: String::new(),
+            line: 0,
+            col: 0,
+            line_empty: true,
+            indent: 0,
+            blank: false,
+            in_decl: false,
+            first_line: false,
+            groups: vec![],
+            prev: None,
+            unary: false,
+            count: 0,
+            annotated: false,
+            cases: 0,
+            targets,
+            naturals: HashMap::new(),
+            guards: vec![],
+        }
+    }
+
+    fn print(&mut self, items: &[Item]) {
+        for item in items {
+            match *item {
+                Item::Token(token, text) => self.token(token.kind, text),
+                Item::Break(blank) => self.newline(blank),
+
+                Item::Decl(annotated) => {
+                    self.annotated = annotated;
+                    self.in_decl = true;
+                    self.first_line = true;
+                    self.groups.clear();
+                    self.prev = None;
+                    self.unary = false;
+                    self.count = 0;
+                },
+
+                Item::End => self.in_decl = false,
+            }
+        }
+
+        if !self.line_empty {
+            self.out.push('\n');
+        }
+    }
+
+    // The columns that the guards of each case should be lined up at,
+    // counting from its '|'. Only cases with guards on more than one line
+    // are lined up.
+    fn alignments(&self) -> HashMap<usize, usize> {
+        self.naturals.iter()
+            .filter(|(_, cols)| cols.len() > 1)
+            .map(|(case, cols)| (*case, *cols.iter().max().unwrap()))
+            .collect()
+    }
+
+    fn newline(&mut self, blank: bool) {
+        if !self.line_empty {
+            self.out.push('\n');
+            self.line += 1;
+            self.col = 0;
+            self.line_empty = true;
+            self.guards.clear();
+        }
+
+        // Blank lines at the very start are dropped
+        if !self.out.is_empty() {
+            self.blank |= blank;
+        }
+    }
+
+    fn token(&mut self, kind: TokenKind, text: &str) {
+        // Comments run up to the end of the line, but trailing whitespace
+        // is dropped.
+        let text = if kind == TokenKind::Comment { text.trim_end() } else { text };
+        let unary = kind == TokenKind::Minus && self.expects_operand();
+        let starts_line = self.line_empty;
+
+        let mut space = if starts_line {
+            if self.blank {
+                self.out.push('\n');
+                self.line += 1;
+                self.blank = false;
+            }
+
+            self.indent = if self.in_decl && !self.first_line { self.line_indent(kind) } else { 0 };
+            self.line_empty = false;
+            self.first_line = false;
+            self.indent
+        } else {
+            self.space(kind)
+        };
+
+        // Line up the first guard on each line of a case with the others
+        if kind == TokenKind::Question && !starts_line {
+            space += self.line_up_guard(space);
+        }
+
+        let start = (self.line, self.col + space);
+        self.out.push_str(&" ".repeat(space));
+        self.out.push_str(text);
+
+        // Tokens such as triple-quoted strings may go over several lines
+        match text.rfind('\n') {
+            Some(newline) => {
+                self.line += text.matches('\n').count();
+                self.col = text[newline + 1..].chars().count();
+                self.guards.clear();
+            },
+            None => self.col = start.1 + text.chars().count(),
+        }
+
+        if kind == TokenKind::Comment {
+            return;
+        }
+
+        if let Some(group) = self.groups.last_mut() {
+            if !group.seen_content && !is_closing(kind) {
+                group.seen_content = true;
+                if group.line == start.0 {
+                    group.content = Some(start.1);
+                }
+            }
+
+            if kind == TokenKind::Pipe && group.case.is_some() && group.pipe.is_none() {
+                group.pipe = Some(start);
+            }
+        }
+
+        if is_opening(kind) {
+            let case = if kind == TokenKind::LeftBrace {
+                self.cases += 1;
+                Some(self.cases - 1)
+            } else {
+                None
+            };
+
+            self.groups.push(Group {
+                line: start.0,
+                col: start.1,
+                indent: self.indent,
+                content: None,
+                seen_content: false,
+                case,
+                pipe: None,
+                row: None,
+            });
+        } else if is_closing(kind) {
+            self.groups.pop();
+        }
+
+        self.prev = Some(kind);
+        self.unary = unary;
+        self.count += 1;
+    }
+
+    // How far to indent a line of a declaration after its first, which
+    // starts with a token of this kind.
+    fn line_indent(&self, kind: TokenKind) -> usize {
+        let group = match self.groups.last() {
+            Some(group) => group,
+            None => return INDENT,
+        };
+
+        if is_closing(kind) {
+            return if group.content.is_some() { group.col } else { group.indent };
+        }
+
+        // The guards of a function line up with the '|' after its
+        // parameters, and everything else lines up with the first guard.
+        match (group.case, group.pipe) {
+            (Some(_), Some((_, pipe))) if kind == TokenKind::Pipe || kind == TokenKind::Comment => pipe,
+            (Some(_), Some((_, pipe))) => pipe + 2,
+            _ => group.content.unwrap_or(group.indent + INDENT),
+        }
+    }
+
+    // Line up a guard's '?', which would otherwise go `space` after the
+    // last token printed, giving back how much further it has to go.
+    //
+    // The lines of a case start at its '|', so a guard is lined up by
+    // where it is from there. Lining up the guards of a case can't move
+    // the guards of the cases around it then, except for the guards of a
+    // case inside of it that come before it on the line. Those cases have
+    // been printed in full, so where they will be lined up to is already
+    // known, and this guard is measured from where it will be after that.
+    fn line_up_guard(&mut self, space: usize) -> usize {
+        let line = self.line;
+        let (case, (pipe_line, pipe)) = match self.groups.last_mut() {
+            Some(group) if group.row != Some(line) => match (group.case, group.pipe) {
+                (Some(case), Some(pipe)) => {
+                    group.row = Some(line);
+                    (case, pipe)
+                },
+                _ => return 0,
+            },
+            _ => return 0,
+        };
+
+        let inside = self.guards.iter().filter(|guard| pipe_line != line || guard.col > pipe);
+        let (to_come, shifted) = inside.fold((0, 0), |(to_come, shifted), guard| {
+            let target = self.naturals[&guard.case].iter().max().unwrap();
+            (to_come + target - guard.natural, shifted + guard.shift)
+        });
+
+        let natural = (self.col + space + to_come) - (pipe + shifted);
+        self.naturals.entry(case).or_default().push(natural);
+
+        let shift = self.targets.get(&case).map_or(0, |target| target.saturating_sub(natural));
+        self.guards.push(Guard { col: self.col + space + shift, case, natural, shift });
+        shift
+    }
+
+    // How many spaces go between the last token printed and one of this
+    // kind on the same line.
+    fn space(&self, kind: TokenKind) -> usize {
+        if kind == TokenKind::Comment {
+            return 1;
+        }
+
+        match self.prev {
+            _ if self.unary => 0,
+            Some(prev) if is_opening(prev) => 0,
+            _ if is_closing(kind) || kind == TokenKind::Comma => 0,
+            Some(TokenKind::DotDot) => 0,
+            _ if kind == TokenKind::DotDot => 0,
+
+            // The annotation of a constant, e.g. `inc: Int -> Int`
+            Some(TokenKind::Identifier) if kind == TokenKind::Colon && self.count == 1 && self.annotated => 0,
+
+            _ => 1,
+        }
+    }
+
+    // Whether a '-' here would negate what comes after it, rather than
+    // subtract it from what came before.
+    fn expects_operand(&self) -> bool {
+        // Function parameters are patterns, where '-' is only ever part of
+        // a negative number
+        if let Some(Group { case: Some(_), pipe: None, .. }) = self.groups.last() {
+            return true;
+        }
+
+        match self.prev {
+            None => true,
+            Some(prev) => is_opening(prev) || matches!(prev,
+                TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash |
+                TokenKind::Percent | TokenKind::Carat | TokenKind::PlusPlus |
+                TokenKind::EqualEqual | TokenKind::BangEqual |
+                TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual |
+                TokenKind::And | TokenKind::Or | TokenKind::Not | TokenKind::Else |
+                TokenKind::Colon | TokenKind::LeftArrow | TokenKind::RightArrow | TokenKind::DotDot |
+                TokenKind::Equal | TokenKind::Comma | TokenKind::Pipe | TokenKind::Question),
+        }
+    }
+}
+
+fn is_opening(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::LeftBrace | TokenKind::LeftSquare | TokenKind::LeftParen)
+}
+
+fn is_closing(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::RightBrace | TokenKind::RightSquare | TokenKind::RightParen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    fn format_ok(source: &str) -> String {
+        format(source, FileId::default()).unwrap_or_else(|errors| panic!("{:?} doesn't parse: {:?}", source, errors))
+    }
+
+    // The kind and text of every token other than whitespace and newlines
+    fn significant(source: &str) -> Vec<(TokenKind, String)> {
+        Cst::new(source, FileId::default()).tokens().into_iter()
+            .filter(|token| !matches!(token.token.kind, TokenKind::Whitespace | TokenKind::Newline))
+            .map(|token| (token.token.kind, token.text.clone()))
+            .collect()
+    }
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format_ok(source);
+        assert_eq!(formatted, expected);
+        assert_eq!(format_ok(&formatted), formatted, "formatting {:?} again changes it", formatted);
+        assert_eq!(significant(&formatted), significant(source));
+    }
+
+    #[test]
+    fn examples_are_formatted() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "gwn") {
+                let source = fs::read_to_string(&path).unwrap();
+                assert_eq!(format_ok(&source), source, "{} isn't formatted", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn spacing() {
+        assert_formats("x=1+2*  3\n", "x = 1 + 2 * 3\n");
+        assert_formats("x = [ 1 .. 10 ]\n", "x = [1..10]\n");
+        assert_formats("x = ( 1 ,2 )\n", "x = (1, 2)\n");
+        assert_formats("x = - 1\n", "x = -1\n");
+        assert_formats("inc : Int -> Int = {x|x+1}\n", "inc: Int -> Int = {x | x + 1}\n");
+        assert_formats("x:xs -> print\n", "x : xs -> print\n");
+    }
+
+    #[test]
+    fn guards_are_aligned() {
+        assert_formats(
+            "sign = {x | x < 0 ? -1\n| x == 0 ? 0\n| else ? 1}\n",
+            "sign = {x | x < 0  ? -1\n          | x == 0 ? 0\n          | else   ? 1}\n",
+        );
+
+        // Lining up the guards of a function moves any functions in them
+        assert_formats(
+            "f = {x | x == 0 ? {y | y > 0 ? 1\n| else ? 0}\n| else ? {y | y ? 2\n| else ? 3}}\n",
+            "f = {x | x == 0 ? {y | y > 0 ? 1\n                     | else  ? 0}\n       | else   ? {y | y    ? 2\n                     | else ? 3}}\n",
+        );
+
+        // and functions before a guard on its line move it
+        assert_formats(
+            "f = {x | (g <- {y | y > 0 ? 1\n| else ? 0}) == 1 ? \"a\"\n| else ? \"b\"}\n",
+            "f = {x | (g <- {y | y > 0 ? 1\n                  | else  ? 0}) == 1 ? \"a\"\n       | else                        ? \"b\"}\n",
+        );
+    }
+
+    #[test]
+    fn cases_get_a_line_each() {
+        assert_formats(
+            "f = {0 | 0}, {n |\nn - 1}\n",
+            "f =\n    {0 | 0},\n    {n |\n         n - 1}\n",
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        assert_formats(
+            "# A constant\nx = 1   # one\n\n\n# Another\ny = [1,  # first\n2]\n",
+            "# A constant\nx = 1 # one\n\n# Another\ny = [1, # first\n     2]\n",
+        );
+    }
+
+    #[test]
+    fn errors_stop_formatting() {
+        assert!(format("x = (1 +\n", FileId::default()).is_err());
+        assert!(format("x = \"a\\q\"\n", FileId::default()).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+// gwn::int: Integers of any size.
+//
+// Most integers in a program are small, so they are kept as an i64 and
+// only moved over to a BigInt when an operation would overflow. Results
+// are moved back to an i64 whenever they fit, so that a big intermediate
+// value doesn't slow down everything after it.
+
+use num::{BigInt, Integer, Signed, ToPrimitive};
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
+pub enum Int {
+    Small(i64),
+
+    // Only ever holds integers that don't fit in an i64
+    Big(Rc<BigInt>),
+}
+
+impl Int {
+    // Parse a string of decimal digits, with an optional leading '-'.
+    pub fn parse(digits: &str) -> Option<Int> {
+        Int::parse_radix(digits, 10)
+    }
+
+    // Parse a string of digits in the given base, e.g. `ff` in base 16.
+    pub fn parse_radix(digits: &str, radix: u32) -> Option<Int> {
+        match i64::from_str_radix(digits, radix) {
+            Ok(i) => Some(Int::Small(i)),
+            Err(_) => BigInt::parse_bytes(digits.as_bytes(), radix).map(Int::from),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        matches!(self, Int::Small(0))
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Int::Small(i) => *i < 0,
+            Int::Big(i) => i.is_negative(),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Int::Small(i) => *i as f64,
+            Int::Big(i) => i.to_f64().unwrap_or(if i.is_negative() { f64::NEG_INFINITY } else { f64::INFINITY }),
+        }
+    }
+
+    pub fn to_u32(&self) -> Option<u32> {
+        match self {
+            Int::Small(i) => u32::try_from(*i).ok(),
+            Int::Big(_) => None,
+        }
+    }
+
+    fn to_big(&self) -> BigInt {
+        match self {
+            Int::Small(i) => BigInt::from(*i),
+            Int::Big(i) => (**i).clone(),
+        }
+    }
+
+    pub fn neg(&self) -> Int {
+        match self {
+            Int::Small(i) => i.checked_neg().map_or_else(|| Int::from(-self.to_big()), Int::Small),
+            Int::Big(i) => Int::from(-(**i).clone()),
+        }
+    }
+
+    pub fn add(&self, other: &Int) -> Int {
+        self.small_or_big(other, i64::checked_add, |a, b| a + b)
+    }
+
+    pub fn sub(&self, other: &Int) -> Int {
+        self.small_or_big(other, i64::checked_sub, |a, b| a - b)
+    }
+
+    pub fn mul(&self, other: &Int) -> Int {
+        self.small_or_big(other, i64::checked_mul, |a, b| a * b)
+    }
+
+    // Division rounding down, so the remainder has the sign of `other`.
+    // `other` must not be zero.
+    pub fn div_floor(&self, other: &Int) -> Int {
+        self.small_or_big(other, checked_div_floor, |a, b| a.div_floor(&b))
+    }
+
+    // The remainder of `div_floor`, which has the sign of `other`. `other`
+    // must not be zero.
+    pub fn mod_floor(&self, other: &Int) -> Int {
+        self.small_or_big(other, checked_mod_floor, |a, b| a.mod_floor(&b))
+    }
+
+    pub fn pow(&self, exponent: u32) -> Int {
+        match self {
+            Int::Small(i) => match i.checked_pow(exponent) {
+                Some(result) => Int::Small(result),
+                None => Int::from(num::pow(self.to_big(), exponent as usize)),
+            },
+            Int::Big(i) => Int::from(num::pow((**i).clone(), exponent as usize)),
+        }
+    }
+
+    // Work out an operation on two i64s if neither is big and it doesn't
+    // overflow, and on BigInts otherwise.
+    fn small_or_big(
+        &self,
+        other: &Int,
+        small: fn(i64, i64) -> Option<i64>,
+        big: fn(BigInt, BigInt) -> BigInt,
+    ) -> Int {
+        if let (Int::Small(a), Int::Small(b)) = (self, other) {
+            if let Some(result) = small(*a, *b) {
+                return Int::Small(result);
+            }
+        }
+
+        Int::from(big(self.to_big(), other.to_big()))
+    }
+}
+
+// i64::MIN / -1 is the only division by a non-zero i64 that overflows.
+fn checked_div_floor(a: i64, b: i64) -> Option<i64> {
+    a.checked_div(b).map(|_| Integer::div_floor(&a, &b))
+}
+
+fn checked_mod_floor(a: i64, b: i64) -> Option<i64> {
+    a.checked_rem(b).map(|_| Integer::mod_floor(&a, &b))
+}
+
+impl From<i64> for Int {
+    fn from(i: i64) -> Int {
+        Int::Small(i)
+    }
+}
+
+impl From<BigInt> for Int {
+    fn from(i: BigInt) -> Int {
+        match i.to_i64() {
+            Some(i) => Int::Small(i),
+            None => Int::Big(Rc::new(i)),
+        }
+    }
+}
+
+// Since big integers never fit in an i64, a small and a big integer are
+// never equal.
+impl PartialEq for Int {
+    fn eq(&self, other: &Int) -> bool {
+        match (self, other) {
+            (Int::Small(a), Int::Small(b)) => a == b,
+            (Int::Big(a), Int::Big(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Int {}
+
+impl Ord for Int {
+    fn cmp(&self, other: &Int) -> Ordering {
+        match (self, other) {
+            (Int::Small(a), Int::Small(b)) => a.cmp(b),
+            (Int::Big(a), Int::Big(b)) => a.cmp(b),
+            (Int::Small(_), Int::Big(b)) => if b.is_negative() { Ordering::Greater } else { Ordering::Less },
+            (Int::Big(a), Int::Small(_)) => if a.is_negative() { Ordering::Less } else { Ordering::Greater },
+        }
+    }
+}
+
+impl PartialOrd for Int {
+    fn partial_cmp(&self, other: &Int) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Int {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Int::Small(i) => write!(f, "{}", i),
+            Int::Big(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(digits: &str) -> Int {
+        Int::parse(digits).unwrap()
+    }
+
+    fn is_small(i: &Int) -> bool {
+        matches!(i, Int::Small(_))
+    }
+
+    #[test]
+    fn overflow_moves_to_big_ints() {
+        let max = Int::from(i64::MAX);
+        let one = Int::from(1);
+
+        let sum = max.add(&one);
+        assert!(!is_small(&sum));
+        assert_eq!(sum.to_string(), "9223372036854775808");
+
+        assert_eq!(Int::from(i64::MIN).neg().to_string(), "9223372036854775808");
+        assert_eq!(Int::from(i64::MIN).sub(&one).to_string(), "-9223372036854775809");
+        assert_eq!(max.mul(&Int::from(2)).to_string(), "18446744073709551614");
+        assert_eq!(Int::from(2).pow(100).to_string(), "1267650600228229401496703205376");
+    }
+
+    #[test]
+    fn results_that_fit_move_back_to_small_ints() {
+        let big = int("9223372036854775808");
+        assert!(!is_small(&big));
+
+        let back = big.sub(&Int::from(1));
+        assert!(is_small(&back));
+        assert_eq!(back, Int::from(i64::MAX));
+
+        assert!(is_small(&big.div_floor(&Int::from(2))));
+        assert!(is_small(&int("100000000000000000000").mod_floor(&Int::from(7))));
+        assert!(is_small(&int("-9223372036854775808")));
+    }
+
+    #[test]
+    fn floor_division() {
+        let cases = [(7, 2, 3, 1), (-7, 2, -4, 1), (7, -2, -4, -1), (-7, -2, 3, -1)];
+        for &(a, b, quotient, remainder) in cases.iter() {
+            assert_eq!(Int::from(a).div_floor(&Int::from(b)), Int::from(quotient));
+            assert_eq!(Int::from(a).mod_floor(&Int::from(b)), Int::from(remainder));
+
+            // Big integers agree with small ones
+            let shift = Int::from(2).pow(80);
+            let big_a = Int::from(a).mul(&shift);
+            assert_eq!(big_a.div_floor(&Int::from(b).mul(&shift)), Int::from(quotient));
+            assert_eq!(big_a.mod_floor(&Int::from(b).mul(&shift)), Int::from(remainder).mul(&shift));
+        }
+
+        // i64::MIN / -1 doesn't fit in an i64
+        assert_eq!(Int::from(i64::MIN).div_floor(&Int::from(-1)).to_string(), "9223372036854775808");
+    }
+
+    #[test]
+    fn comparison() {
+        let big = int("100000000000000000000");
+        let negative = int("-100000000000000000000");
+
+        assert!(Int::from(i64::MAX) < big);
+        assert!(negative < Int::from(i64::MIN));
+        assert!(negative < big);
+        assert_eq!(big, int("100000000000000000000"));
+        assert!(!big.is_zero() && !Int::from(1).is_zero() && Int::from(0).is_zero());
+        assert!(negative.is_negative() && !big.is_negative());
+    }
+}
@@ -0,0 +1,757 @@
+// gwn::interpreter: A tree-walking evaluator over the AST.
+
+use crate::ast::{Decl, Expr, ExprKind, Literal, Pattern, PatternKind, FuncExpr, RangeExpr, InterpolatedPart};
+use crate::scanner::{Token, TokenKind};
+use crate::span::{FileId, Span};
+use crate::value::{Value, List, Closure, Callable};
+use crate::int::Int;
+use crate::error::Report;
+use crate::diagnostic::{Diagnostic, Label};
+use crate::prelude;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// How deeply functions may call each other before giving up, rather than
+// overflowing the stack. This needs a bigger stack than the default for the
+// main thread, see `main`.
+const MAX_CALL_DEPTH: u32 = 10000;
+
+thread_local! {
+    static CALL_DEPTH: Cell<u32> = const { Cell::new(0) };
+}
+
+// The constants that are in scope. Function parameters are kept in a chain
+// of local scopes, which ends in the global scope of top-level constants.
+#[derive(Clone)]
+pub struct Env(Rc<Scope>);
+
+enum Scope {
+    Global(RefCell<HashMap<String, Value>>),
+    Local(Vec<(String, Value)>, Env),
+}
+
+impl Env {
+    fn global() -> Env {
+        Env(Rc::new(Scope::Global(RefCell::new(HashMap::new()))))
+    }
+
+    fn with_locals(&self, bindings: Vec<(String, Value)>) -> Env {
+        Env(Rc::new(Scope::Local(bindings, self.clone())))
+    }
+
+    fn define(&self, name: String, value: Value) {
+        match &*self.0 {
+            Scope::Global(constants) => {
+                constants.borrow_mut().insert(name, value);
+            },
+            Scope::Local(_, parent) => parent.define(name, value),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Value> {
+        match &*self.0 {
+            Scope::Global(constants) => constants.borrow().get(name).cloned(),
+            Scope::Local(bindings, parent) => {
+                match bindings.iter().rev().find(|(local, _)| local == name) {
+                    Some((_, value)) => Some(value.clone()),
+                    None => parent.lookup(name),
+                }
+            },
+        }
+    }
+}
+
+pub struct Interpreter {
+    // The top-level constants of the module being run
+    globals: Env,
+
+    // The public constants of the modules that have been run, by the file
+    // they're in
+    modules: HashMap<FileId, Vec<(String, Value)>>,
+
+    // The arguments that the program was given, which every module sees
+    // as `args`
+    args: Value,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new(vec![])
+    }
+}
+
+impl Interpreter {
+    pub fn new(args: Vec<String>) -> Interpreter {
+        let args = Value::List(List::from_vec(args.iter().map(|arg| Value::string(arg)).collect()));
+
+        Interpreter {
+            globals: prelude_env(&args),
+            modules: HashMap::new(),
+            args,
+        }
+    }
+
+    // Start running a new module, which only has the builtins in scope.
+    // The scope of the module that was being run is given back, to be
+    // passed to `finish_module`.
+    pub fn begin_module(&mut self) -> Env {
+        std::mem::replace(&mut self.globals, prelude_env(&self.args))
+    }
+
+    // Finish running the module in `file`, so that it can be imported, and
+    // go back to running the module that `previous` is the scope of.
+    pub fn finish_module(&mut self, file: FileId, ast: &[Decl], previous: Env) {
+        let exports = ast.iter()
+            .filter_map(|decl| match decl {
+                Decl::Constant(constant) if constant.is_public() => Some(&constant.name.lexeme),
+                _ => None,
+            })
+            .filter_map(|name| self.globals.lookup(name).map(|value| (name.clone(), value)))
+            .collect();
+
+        self.modules.insert(file, exports);
+        self.globals = previous;
+    }
+
+    // Bring the public constants of a module that has been run into scope.
+    pub fn import(&mut self, file: FileId) {
+        for (name, value) in self.modules[&file].iter() {
+            self.globals.define(name.clone(), value.clone());
+        }
+    }
+
+    // Evaluate a declaration. A constant declaration defines the constant,
+    // while for an evaluated declaration the value is given back.
+    pub fn run(&mut self, decl: &Decl) -> Result<Option<Value>, RuntimeError> {
+        match decl {
+            Decl::Constant(constant) => {
+                let value = eval(&constant.value, &self.globals).map_err(|err| err.or_in(decl.span()))?;
+                self.globals.define(constant.name.lexeme.clone(), value);
+                Ok(None)
+            },
+
+            Decl::Evaluated(evaluated) => eval(&evaluated.expr, &self.globals)
+                .map(Some)
+                .map_err(|err| err.or_in(decl.span())),
+
+            Decl::Import(_) => Ok(None),
+        }
+    }
+}
+
+// A global scope with just the builtins in it, where `args` is the list of
+// the program's arguments.
+fn prelude_env(args: &Value) -> Env {
+    let globals = Env::global();
+
+    for builtin in prelude::builtins() {
+        let closure = Closure{func: Callable::Native(builtin.native), args: vec![]};
+        globals.define(builtin.native.name.to_string(), Value::Closure(Rc::new(closure)));
+    }
+
+    globals.define("args".to_string(), args.clone());
+    globals
+}
+
+pub fn eval(expr: &Expr, env: &Env) -> Result<Value, RuntimeError> {
+    match &expr.node {
+        ExprKind::Literal(literal) => Ok(literal_value(literal)),
+
+        ExprKind::Constant(constant) => {
+            let value = env.lookup(&constant.name.lexeme).ok_or_else(|| {
+                RuntimeError::at(&constant.name, format!("Undefined constant '{}'.", constant.name.lexeme))
+            })?;
+
+            // A builtin that takes no arguments, like `prompt`, is worked
+            // out each time it is used.
+            if let Value::Closure(closure) = &value {
+                if let Callable::Native(native) = &closure.func {
+                    if native.arity == 0 {
+                        return (native.func)(&[]).map_err(|err| err.or_in(constant.name.span));
+                    }
+                }
+            }
+
+            Ok(value)
+        },
+
+        ExprKind::Unary(unary) => {
+            let operand = eval(&unary.operand, env)?;
+
+            match (unary.operator.kind, operand) {
+                (TokenKind::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                (TokenKind::Minus, Value::Int(i)) => Ok(Value::Int(i.neg())),
+                (TokenKind::Minus, Value::Float(x)) => Ok(Value::Float(-x)),
+                (_, operand) => Err(RuntimeError::at(&unary.operator,
+                    format!("Can't apply '{}' to {}.", unary.operator.lexeme, operand.show()?))),
+            }
+        },
+
+        ExprKind::Binary(binary) => {
+            let left = eval(&binary.left, env)?;
+            let right = eval(&binary.right, env)?;
+            binary_operation(binary.operator.kind, &binary.operator, left, right)
+        },
+
+        ExprKind::Logical(logical) => {
+            let left = as_bool(eval(&logical.left, env)?, &logical.operator)?;
+
+            // Short-circuit
+            match logical.operator.kind {
+                TokenKind::And if !left => Ok(Value::Bool(false)),
+                TokenKind::Or if left => Ok(Value::Bool(true)),
+                _ => Ok(Value::Bool(as_bool(eval(&logical.right, env)?, &logical.operator)?)),
+            }
+        },
+
+        ExprKind::Apply(apply) => {
+            let func = eval(&apply.func, env)?;
+            let arg = eval(&apply.arg, env)?;
+            call(func, arg).map_err(|err| err.or_in(expr.span()))
+        },
+
+        ExprKind::Func(func) => {
+            let closure = Closure{func: Callable::Func(func.clone(), env.clone()), args: vec![]};
+            Ok(Value::Closure(Rc::new(closure)))
+        },
+
+        ExprKind::Tuple(tuple) => {
+            let elements = tuple.elements.iter()
+                .map(|e| eval(e, env))
+                .collect::<Result<Vec<Value>, RuntimeError>>()?;
+            Ok(Value::Tuple(Rc::new(elements)))
+        },
+
+        ExprKind::List(list) => {
+            let elements = list.elements.iter()
+                .map(|e| eval(e, env))
+                .collect::<Result<Vec<Value>, RuntimeError>>()?;
+            Ok(Value::List(List::from_vec(elements)))
+        },
+
+        ExprKind::Range(range) => eval_range(range, env),
+
+        ExprKind::InterpolatedString(string) => {
+            let mut text = String::new();
+            for part in string.parts.iter() {
+                match part {
+                    InterpolatedPart::Text(part) => text.push_str(part),
+                    InterpolatedPart::Hole(hole) => text.push_str(&eval(hole, env)?.to_text()?),
+                }
+            }
+            Ok(Value::string(&text))
+        },
+    }
+}
+
+// Give an argument to a function. If the function now has all of its
+// arguments it is called, otherwise the partially applied function is
+// given back.
+pub fn call(func: Value, arg: Value) -> Result<Value, RuntimeError> {
+    let closure = match func {
+        Value::Closure(closure) => closure,
+        _ => return Err(RuntimeError::new(format!("Can't apply {} like a function.", func.show()?))),
+    };
+
+    let mut args = closure.args.clone();
+    args.push(arg);
+
+    if args.len() < closure.arity() {
+        let func = match &closure.func {
+            Callable::Func(func, env) => Callable::Func(func.clone(), env.clone()),
+            Callable::Native(native) => Callable::Native(*native),
+        };
+
+        return Ok(Value::Closure(Rc::new(Closure{func, args})));
+    }
+
+    let depth = CALL_DEPTH.with(|depth| depth.get());
+    if depth >= MAX_CALL_DEPTH {
+        return Err(RuntimeError::new("Too much recursion.".to_string()));
+    }
+
+    CALL_DEPTH.with(|d| d.set(depth + 1));
+    let result = match &closure.func {
+        Callable::Func(func, env) => call_func(func, env, &args),
+        Callable::Native(native) => (native.func)(&args),
+    };
+    CALL_DEPTH.with(|d| d.set(depth));
+
+    result
+}
+
+// Try each case of a function in turn. The first case whose patterns match
+// the arguments and which has a guard that passes gives the result.
+fn call_func(func: &FuncExpr, env: &Env, args: &[Value]) -> Result<Value, RuntimeError> {
+    for case in func.cases.iter() {
+        let mut bindings = vec![];
+        let mut matched = true;
+
+        for (param, arg) in case.params.iter().zip(args.iter()) {
+            if !match_pattern(param, arg, &mut bindings)? {
+                matched = false;
+                break;
+            }
+        }
+
+        if !matched {
+            continue;
+        }
+
+        let env = env.with_locals(bindings);
+        for guard in case.guards.iter() {
+            let condition = eval(&guard.condition, &env)?;
+            if as_bool(condition, &func.brace)? {
+                return eval(&guard.value, &env);
+            }
+        }
+    }
+
+    let args = args.iter()
+        .map(Value::show)
+        .collect::<Result<Vec<String>, RuntimeError>>()?;
+    Err(RuntimeError::at(&func.brace, format!("No case of the function matches {}.", args.join(" "))))
+}
+
+// Check whether a value matches a pattern, binding any names in the pattern.
+fn match_pattern(pattern: &Pattern, value: &Value, bindings: &mut Vec<(String, Value)>) -> Result<bool, RuntimeError> {
+    match (&pattern.node, value) {
+        (PatternKind::Literal(literal), _) => literal_value(literal).equals(value),
+
+        (PatternKind::Identifier(name), _) => {
+            bindings.push((name.lexeme.clone(), value.clone()));
+            Ok(true)
+        },
+
+        (PatternKind::Wildcard, _) => Ok(true),
+
+        (PatternKind::Tuple(patterns), Value::Tuple(values)) if patterns.len() == values.len() => {
+            for (pattern, value) in patterns.iter().zip(values.iter()) {
+                if !match_pattern(pattern, value, bindings)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        },
+
+        (PatternKind::Cons(head, tail), Value::List(list)) => match list.uncons()? {
+            Some((first, rest)) =>
+                Ok(match_pattern(head, &first, bindings)? && match_pattern(tail, &Value::List(rest), bindings)?),
+            None => Ok(false),
+        },
+
+        (PatternKind::List(patterns), Value::List(list)) => {
+            let mut list = list.clone();
+            for pattern in patterns.iter() {
+                match list.uncons()? {
+                    Some((first, rest)) => {
+                        if !match_pattern(pattern, &first, bindings)? {
+                            return Ok(false);
+                        }
+                        list = rest;
+                    },
+                    None => return Ok(false),
+                }
+            }
+
+            // The list must not have any more elements than the pattern
+            Ok(list.uncons()?.is_none())
+        },
+
+        _ => Ok(false),
+    }
+}
+
+fn eval_range(range: &RangeExpr, env: &Env) -> Result<Value, RuntimeError> {
+    let start = eval(&range.start, env)?;
+    let next = match &range.next {
+        Some(next) => Some(eval(next, env)?),
+        None => None,
+    };
+    let end = match &range.end {
+        Some(end) => Some(eval(end, env)?),
+        None => None,
+    };
+
+    // Without a step, count down if the end is below the start.
+    let step = match (&next, &end) {
+        (Some(next), _) => binary_operation(TokenKind::Minus, &range.square, next.clone(), start.clone())?,
+        (None, Some(end)) if compare(&range.square, end, &start)?.is_lt() => cast_like(Value::Int(Int::from(-1)), &start),
+        _ => cast_like(Value::Int(Int::from(1)), &start),
+    };
+
+    // A step of 0 would repeat the start forever.
+    let ordering = compare(&range.square, &step, &cast_like(Value::Int(Int::from(0)), &step))?;
+    if let (Some(next), true) = (&range.next, ordering.is_eq()) {
+        return Err(RuntimeError::new("A range's step can't be 0.".to_string()).or_in(next.span()));
+    }
+
+    let ascending = !ordering.is_lt();
+    Ok(Value::List(range_from(start, step, end, ascending, range.square.clone())))
+}
+
+fn range_from(from: Value, step: Value, end: Option<Value>, ascending: bool, square: Token) -> List {
+    List::lazy(move || {
+        if let Some(end) = &end {
+            let ordering = compare(&square, &from, end)?;
+            let past_end = if ascending { ordering.is_gt() } else { ordering.is_lt() };
+
+            if past_end {
+                return Ok(None);
+            }
+        }
+
+        let next = binary_operation(TokenKind::Plus, &square, from.clone(), step.clone())?;
+        Ok(Some((from, range_from(next, step, end, ascending, square))))
+    })
+}
+
+// Convert an Int to a Float if `other` is a Float.
+fn cast_like(value: Value, other: &Value) -> Value {
+    match (value, other) {
+        (Value::Int(i), Value::Float(_)) => Value::Float(i.to_f64()),
+        (value, _) => value,
+    }
+}
+
+// Apply the binary operator `kind`, reporting any error at `operator`.
+fn binary_operation(kind: TokenKind, operator: &Token, left: Value, right: Value) -> Result<Value, RuntimeError> {
+    let result = match (kind, &left, &right) {
+        (TokenKind::EqualEqual, _, _) => Some(Value::Bool(left.equals(&right)?)),
+        (TokenKind::BangEqual, _, _) => Some(Value::Bool(!left.equals(&right)?)),
+
+        (TokenKind::Less, _, _) => Some(Value::Bool(compare(operator, &left, &right)?.is_lt())),
+        (TokenKind::LessEqual, _, _) => Some(Value::Bool(compare(operator, &left, &right)?.is_le())),
+        (TokenKind::Greater, _, _) => Some(Value::Bool(compare(operator, &left, &right)?.is_gt())),
+        (TokenKind::GreaterEqual, _, _) => Some(Value::Bool(compare(operator, &left, &right)?.is_ge())),
+
+        (TokenKind::PlusPlus, Value::String(a), Value::String(b)) => Some(Value::string(&format!("{}{}", a, b))),
+        (TokenKind::PlusPlus, Value::List(a), Value::List(b)) => Some(Value::List(a.append(b.clone()))),
+
+        (TokenKind::Colon, _, Value::List(tail)) => Some(Value::List(List::cons(left.clone(), tail.clone()))),
+
+        (_, Value::Int(a), Value::Int(b)) => return int_arithmetic(kind, operator, a, b),
+        (_, Value::Float(a), Value::Float(b)) => float_arithmetic(kind, *a, *b),
+
+        _ => None,
+    };
+
+    match result {
+        Some(value) => Ok(value),
+        None => Err(RuntimeError::at(operator, format!("Can't apply '{}' to {} and {}.",
+            operator.lexeme, left.show()?, right.show()?))),
+    }
+}
+
+// Ints never overflow, as they grow into BigInts instead.
+fn int_arithmetic(kind: TokenKind, operator: &Token, a: &Int, b: &Int) -> Result<Value, RuntimeError> {
+    let result = match kind {
+        TokenKind::Plus => a.add(b),
+        TokenKind::Minus => a.sub(b),
+        TokenKind::Star => a.mul(b),
+        TokenKind::Slash | TokenKind::Percent if b.is_zero() =>
+            return Err(RuntimeError::at(operator, "Division by zero.".to_string())),
+        TokenKind::Slash => a.div_floor(b),
+        TokenKind::Percent => a.mod_floor(b),
+        TokenKind::Carat if b.is_negative() =>
+            return Err(RuntimeError::at(operator, "Negative exponent for an Int.".to_string())),
+        TokenKind::Carat => match b.to_u32() {
+            Some(b) => a.pow(b),
+            None => return Err(RuntimeError::at(operator, "Exponent is too large.".to_string())),
+        },
+        _ => return Err(RuntimeError::at(operator, format!("Can't apply '{}' to {} and {}.", operator.lexeme, a, b))),
+    };
+
+    Ok(Value::Int(result))
+}
+
+fn float_arithmetic(operator: TokenKind, a: f64, b: f64) -> Option<Value> {
+    let result = match operator {
+        TokenKind::Plus => a + b,
+        TokenKind::Minus => a - b,
+        TokenKind::Star => a * b,
+        TokenKind::Slash => a / b,
+        TokenKind::Percent => float_mod(a, b),
+        TokenKind::Carat => a.powf(b),
+        _ => return None,
+    };
+
+    Some(Value::Float(result))
+}
+
+// Like `%` on Ints, the result has the sign of `b`.
+fn float_mod(a: f64, b: f64) -> f64 {
+    let remainder = a % b;
+
+    if remainder != 0.0 && (remainder < 0.0) != (b < 0.0) {
+        remainder + b
+    } else {
+        remainder
+    }
+}
+
+fn compare(operator: &Token, left: &Value, right: &Value) -> Result<std::cmp::Ordering, RuntimeError> {
+    let ordering = match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+
+    ordering.ok_or_else(|| RuntimeError::at(operator,
+        format!("Can't compare {} and {}.", left, right)))
+}
+
+fn as_bool(value: Value, token: &Token) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        _ => Err(RuntimeError::at(token, format!("Expected a Bool, found {}.", value.show()?))),
+    }
+}
+
+fn literal_value(literal: &Literal) -> Value {
+    match literal {
+        Literal::Int(i) => Value::Int(i.clone()),
+        Literal::Float(x) => Value::Float(*x),
+        Literal::Bool(b) => Value::Bool(*b),
+        Literal::String(s) => Value::string(s),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    // Where the error happened. Errors from built-in functions don't know
+    // this, and are given the span of the application that called them.
+    span: Option<Span>,
+    msg: String,
+}
+
+impl RuntimeError {
+    pub fn new(msg: String) -> RuntimeError {
+        RuntimeError {
+            span: None,
+            msg
+        }
+    }
+
+    pub fn at(token: &Token, msg: String) -> RuntimeError {
+        RuntimeError {
+            span: Some(token.span),
+            msg
+        }
+    }
+
+    // Give the error a position, unless it already has one.
+    fn or_in(mut self, span: Span) -> RuntimeError {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+
+        self
+    }
+}
+
+impl Report for RuntimeError {
+    fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error("E0004", self.msg.clone());
+        match self.span {
+            Some(span) => diagnostic.with_label(Label::primary(span, "")),
+            None => diagnostic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::parser::Parser;
+    use crate::span::FileId;
+
+    use std::thread;
+
+    // Check and run some source, giving back the value of the last evaluated
+    // declaration, or the message of the error that stopped it.
+    fn run(source: &str) -> Result<Value, String> {
+        let (mut ast, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "{:?} doesn't parse", source);
+        assert!(Checker::new().check(&mut ast).is_empty(), "{} doesn't type check", source);
+
+        let mut interpreter = Interpreter::new(vec![]);
+        let mut last = None;
+        for decl in ast.iter() {
+            last = interpreter.run(decl).map_err(|err| err.msg)?.or(last);
+        }
+
+        Ok(last.expect("Expected an evaluated declaration."))
+    }
+
+    // The source that the runtime error from running `source` points at
+    fn error_at(source: &str) -> &str {
+        let (mut ast, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "{:?} doesn't parse", source);
+        assert!(Checker::new().check(&mut ast).is_empty(), "{} doesn't type check", source);
+
+        let mut interpreter = Interpreter::new(vec![]);
+        let err = ast.iter()
+            .find_map(|decl| interpreter.run(decl).err())
+            .expect("Expected a runtime error.");
+
+        let span = err.span.expect("Expected the error to know where it happened.");
+        &source[span.start..span.end]
+    }
+
+    fn show(source: &str) -> Result<String, String> {
+        run(source)?.show().map_err(|err| err.msg)
+    }
+
+    // The first `n` elements of a list, which may be infinite.
+    fn take(value: Value, n: usize) -> Vec<String> {
+        let mut list = match value {
+            Value::List(list) => list,
+            value => panic!("Expected a list, found {}", value),
+        };
+
+        let mut shown = vec![];
+        while shown.len() < n {
+            let (head, tail) = list.uncons().unwrap().expect("The list ended early.");
+            shown.push(head.show().unwrap());
+            list = tail;
+        }
+
+        shown
+    }
+
+    #[test]
+    fn currying() {
+        assert_eq!(show("add = {x y | x + y}\ninc = add <- 1\ninc <- 41"), Ok("42".to_string()));
+        assert_eq!(show("add = {x y z | x + y + z}\n((add <- 1) <- 2) <- 3"), Ok("6".to_string()));
+    }
+
+    #[test]
+    fn ints_grow_without_overflowing() {
+        let source = "factorial = {0 | 1}, {n | n * (factorial <- (n - 1))}\nfactorial <- 25";
+        assert_eq!(show(source), Ok("15511210043330985984000000".to_string()));
+
+        assert_eq!(show("9223372036854775807 + 1"), Ok("9223372036854775808".to_string()));
+        assert_eq!(show("(2 ^ 64) / (2 ^ 60)"), Ok("16".to_string()));
+        assert_eq!(show("2 ^ 64 == 18446744073709551616"), Ok("true".to_string()));
+        assert_eq!(show("-(2 ^ 63) - 1 < -9223372036854775807"), Ok("true".to_string()));
+        assert_eq!(show("[9223372036854775807, 1] -> sum"), Ok("9223372036854775808".to_string()));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(show("1 / 0"), Err("Division by zero.".to_string()));
+        assert_eq!(show("1 % 0"), Err("Division by zero.".to_string()));
+        assert_eq!(show("(2 ^ 70) / 0"), Err("Division by zero.".to_string()));
+        assert_eq!(show("2 ^ -1"), Err("Negative exponent for an Int.".to_string()));
+
+        // Floats follow IEEE 754 instead
+        assert_eq!(show("1.0 / 0.0"), Ok("inf".to_string()));
+        assert_eq!(error_at("print <- (7 / (1 - 1))"), "/");
+    }
+
+    #[test]
+    fn division_rounds_down() {
+        assert_eq!(show("[7 / 2, -7 / 2, 7 / -2, -7 / -2]"), Ok("[3, -4, -4, 3]".to_string()));
+        assert_eq!(show("[7 % 2, -7 % 2, 7 % -2, -7 % -2]"), Ok("[1, 1, -1, -1]".to_string()));
+        assert_eq!(show("[7.5 % 2.0, -7.5 % 2.0, 7.5 % -2.0]"), Ok("[1.5, 0.5, -0.5]".to_string()));
+    }
+
+    #[test]
+    fn cases_and_guards() {
+        let source = "fizzBuzz = {x | x % 15 == 0 ? \"FizzBuzz\"\n    | x % 5 == 0 ? \"Buzz\"\n    | x % 3 == 0 ? \"Fizz\"\n    | else ? x -> toString}\n[15, 10, 9, 7] -> map <- fizzBuzz";
+        assert_eq!(show(source), Ok(r#"["FizzBuzz", "Buzz", "Fizz", "7"]"#.to_string()));
+
+        let source = "fib = {0 | 0}, {1 | 1}, {n | (fib <- (n - 1)) + (fib <- (n - 2))}\nfib <- 20";
+        assert_eq!(show(source), Ok("6765".to_string()));
+
+        assert_eq!(show("f = {0 | 1}\nf <- 2"), Err("No case of the function matches 2.".to_string()));
+    }
+
+    #[test]
+    fn patterns() {
+        let source = "sum = {[] | 0}, {x : xs | x + (sum <- xs)}\nsum <- [1..10]";
+        assert_eq!(show(source), Ok("55".to_string()));
+
+        let source = "f = {(0, _) | \"zero\"}, {(-1, _) | \"minus one\"}, {(_, [a, b]) | a ++ b}, {_ | \"other\"}\n[(0, []), (-1, []), (2, [\"a\", \"b\"]), (2, [\"a\"])] -> map <- f";
+        assert_eq!(show(source), Ok(r#"["zero", "minus one", "ab", "other"]"#.to_string()));
+
+        // Matching a cons pattern only forces as much of the list as it needs
+        assert_eq!(show("second = {_ : x : _ | x}\nsecond <- [1..]"), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn functions_can_use_constants_declared_after_them() {
+        let source = "isEven = {0 | true}, {n | isOdd <- (n - 1)}\nisOdd = {0 | false}, {n | isEven <- (n - 1)}\nisEven <- 10";
+        assert_eq!(show(source), Ok("true".to_string()));
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        let source = "bottles = {1 | \"1 bottle\"}, {n | \"{n} bottles\"}\n[2, 1] -> map <- bottles";
+        assert_eq!(show(source), Ok(r#"["2 bottles", "1 bottle"]"#.to_string()));
+
+        assert_eq!(run("n = 3\n\"{n - 1} of {[n, n]}, {{literally}\"").unwrap().to_text().unwrap(), "2 of [3, 3], {literally}");
+        assert_eq!(run("f = {x | \"{x} and {y}\"}\ny = \"later\"\nf <- 1.5").unwrap().to_text().unwrap(), "1.5 and later");
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(show("[1..5]"), Ok("[1, 2, 3, 4, 5]".to_string()));
+        assert_eq!(show("[5..1]"), Ok("[5, 4, 3, 2, 1]".to_string()));
+        assert_eq!(show("[1, 3 .. 9]"), Ok("[1, 3, 5, 7, 9]".to_string()));
+        assert_eq!(show("[10, 7 .. 0]"), Ok("[10, 7, 4, 1]".to_string()));
+        assert_eq!(show("[0.5 .. 2.5]"), Ok("[0.5, 1.5, 2.5]".to_string()));
+        assert_eq!(show("[1.0, 1.25 .. 2.0]"), Ok("[1.0, 1.25, 1.5, 1.75, 2.0]".to_string()));
+        assert_eq!(show("[1, 1 .. 5]"), Err("A range's step can't be 0.".to_string()));
+    }
+
+    #[test]
+    fn errors_point_at_where_they_happened() {
+        assert_eq!(error_at("print <- [1, 1 .. 5]"), "1");
+        assert_eq!(error_at("f = {0 | 1}\nprint <- (f <- 2)"), "{");
+
+        // Built-in functions are blamed on the application that called them
+        assert_eq!(error_at("print <- [] -> head"), "[] -> head");
+    }
+
+    #[test]
+    fn open_ranges_are_lazy() {
+        assert_eq!(take(run("numbers = [1..]\nnumbers").unwrap(), 3), ["1", "2", "3"]);
+        assert_eq!(take(run("[10, 8 ..]").unwrap(), 3), ["10", "8", "6"]);
+        assert_eq!(take(run("[1..] -> map <- {x | x * x}").unwrap(), 4), ["1", "4", "9", "16"]);
+    }
+
+    #[test]
+    fn each_interpreter_has_its_own_args() {
+        let (ast, _) = Parser::new("args".to_string(), FileId::default()).parse_all();
+        let mut first = Interpreter::new(vec!["a".to_string()]);
+        let mut second = Interpreter::new(vec!["b".to_string(), "c".to_string()]);
+
+        let args = |interpreter: &mut Interpreter| interpreter.run(&ast[0]).unwrap().unwrap().show().unwrap();
+        assert_eq!(args(&mut second), r#"["b", "c"]"#);
+        assert_eq!(args(&mut first), r#"["a"]"#);
+
+        // Imported modules are given the same arguments
+        let previous = first.begin_module();
+        assert_eq!(args(&mut first), r#"["a"]"#);
+        first.finish_module(FileId::default(), &[], previous);
+    }
+
+    #[test]
+    fn deep_recursion_is_an_error() {
+        // As in `main`, the interpreter needs a big stack to get this deep
+        let result = thread::Builder::new()
+            .stack_size(256 * 1024 * 1024)
+            .spawn(|| show("f = {n | f <- (n + 1)}\nf <- 0"))
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(result, Err("Too much recursion.".to_string()));
+    }
+}
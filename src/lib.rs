@@ -3,42 +3,340 @@ extern crate lazy_static;
 
 mod scanner;
 mod parser;
+pub mod cst;
+mod formatter;
+mod dump;
+mod unparse;
+pub mod span;
 mod error;
-mod ast;
-mod typ;
+pub mod diagnostic;
+pub mod ast;
+pub mod typ;
+mod checker;
+mod exhaustive;
+mod module;
+pub mod int;
+mod value;
+mod interpreter;
+mod lsp;
+mod prelude;
 
-use std::io;
-use std::io::Write;
+use std::env;
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
-use crate::scanner::{Scanner, TokenKind};
+use crate::module::Loader;
 use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::error::Report;
+use crate::ast::Decl;
+use crate::cst::Cst;
+use crate::diagnostic::Diagnostic;
+use crate::module::ModuleError;
+use crate::span::{FileId, SourceMap};
+use crate::diagnostic::Position;
 
-pub fn run(source: String) {
-    let mut parser = Parser::new(source);
-    let ast = parser.parse();
-    for node in ast {
-        println!("{:#?}", node);
+pub use crate::scanner::{Token, TokenKind, StringPart};
+
+// How errors and warnings are written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    // A code frame for a person to read, coloured in if stderr is a terminal
+    Human,
+
+    // One JSON object per line, for editors and other tools
+    Json,
+}
+
+impl ErrorFormat {
+    // Write out a diagnostic about one of `sources` to stderr.
+    fn report(self, diagnostic: &Diagnostic, sources: &SourceMap) {
+        match self {
+            ErrorFormat::Human => eprintln!("{}", diagnostic.render(sources, io::stderr().is_terminal())),
+            ErrorFormat::Json => eprintln!("{}", diagnostic.to_json(sources)),
+        }
+    }
+}
+
+// How `gwn tokens` and `gwn ast` write out what they find. See the `dump`
+// module for what the JSON and S-expressions look like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    // One token per line, with the line and column it starts at
+    Text,
+
+    Json,
+    Sexpr,
+}
+
+// Where modules imported by name are looked for: the directory of the main
+// file, then each directory in the `GWN_PATH` environment variable.
+fn search_path(dir: &Path) -> Vec<PathBuf> {
+    let mut search_path = vec![dir.to_path_buf()];
+    if let Some(paths) = env::var_os("GWN_PATH") {
+        search_path.extend(env::split_paths(&paths));
+    }
+    search_path
+}
+
+// Scan all of `source` into tokens, ending with an `Eof` token. Newlines
+// are all kept, as the layout rules are left to the parser. Any source
+// that can't be scanned becomes an `Error` token.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut scanner = Scanner::new(source.to_string(), FileId::default());
+    let mut tokens = vec![];
+
+    loop {
+        let token = scanner.scan_token().unwrap_or_else(|err| err.token());
+        let kind = token.kind;
+        tokens.push(token);
+
+        if kind == TokenKind::Eof {
+            return tokens;
+        }
+    }
+}
+
+// What parsing some source gives back: the declarations that could be
+// parsed, and an error for each problem that was found.
+#[derive(Debug, Clone)]
+pub struct ParseResult {
+    pub decls: Vec<Decl>,
+    pub errors: Vec<Diagnostic>,
+}
+
+impl ParseResult {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+// Parse `source` into declarations. The spans in them are all in the
+// file `FileId(0)`.
+pub fn parse(source: &str) -> ParseResult {
+    let (decls, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+
+    ParseResult {
+        decls,
+        errors: errors.iter().map(Report::diagnostic).collect(),
+    }
+}
+
+// Print declarations back out as source, one per line. Parsing it gives
+// back the same declarations, apart from their spans and types.
+pub fn unparse(decls: &[Decl]) -> String {
+    decls.iter().map(|decl| format!("{}\n", decl)).collect()
+}
+
+// Build the lossless concrete syntax tree of `source`, which prints back as
+// exactly `source`. Its spans are all in the file `FileId(0)`.
+pub fn parse_cst(source: &str) -> Cst {
+    Cst::new(source, FileId::default())
+}
+
+// Format `source` the canonical way, as `gwn fmt` does, or give back the
+// errors that stopped it from parsing.
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    formatter::format(source, FileId::default())
+}
+
+// The version of GWN, from the `version` file.
+pub fn version() -> &'static str {
+    include_str!("../version").lines()
+        .find_map(|line| line.strip_prefix("version:"))
+        .map_or("unknown", |version| version.trim().trim_matches('"'))
+}
+
+// Read a file into `sources`, reporting it if it can't be read.
+fn read_source(filename: &str, sources: &mut SourceMap, format: ErrorFormat) -> Option<FileId> {
+    match fs::read_to_string(filename) {
+        Ok(source) => Some(sources.add(filename.to_string(), source)),
+        Err(err) => {
+            format.report(&ModuleError::new(format!("Can't read '{}': {}.", filename, err)).diagnostic(), sources);
+            None
+        },
+    }
+}
+
+// Print the tokens that a file is scanned into, as text or JSON. Gives back
+// whether it could all be scanned.
+pub fn run_tokens(filename: &str, dump: DumpFormat, format: ErrorFormat) -> bool {
+    let mut sources = SourceMap::new();
+    let file = match read_source(filename, &mut sources, format) {
+        Some(file) => file,
+        None => return false,
+    };
+
+    let source = sources.source(file);
+    let mut scanner = Scanner::new(source.to_string(), file);
+    let mut errors = vec![];
+    let mut tokens = vec![];
+
+    loop {
+        let token = match scanner.scan_token() {
+            Ok(token) => token,
+            Err(err) => {
+                errors.push(err.diagnostic());
+                err.token()
+            },
+        };
+
+        let eof = token.kind == TokenKind::Eof;
+        tokens.push(token);
+
+        if eof {
+            break;
+        }
     }
+
+    let out = match dump {
+        DumpFormat::Json => format!("{:#}\n", dump::tokens_json(&tokens, source)),
+        _ => tokens.iter()
+            .map(|token| {
+                let position = Position::of(source, token.span.start);
+                format!("{}:{} {:?} {:?}\n", position.line, position.col, token.kind, token.lexeme)
+            })
+            .collect(),
+    };
+
+    // Whatever is reading the output may have stopped, e.g. `head`
+    let printed = io::stdout().write_all(out.as_bytes()).is_ok();
+
+    for err in errors.iter() {
+        format.report(err, &sources);
+    }
+
+    printed && errors.is_empty()
 }
 
-pub fn run_file(filename: String) {
-    let contents = 
-        fs::read_to_string(filename).unwrap();
-    
-    run(contents);
+// Print the declarations that a file is parsed into, as JSON or
+// S-expressions. The file is checked first, along with the modules it
+// imports, so that each expression has its type. Gives back whether it was
+// free of errors.
+pub fn run_ast(filename: &str, dump: DumpFormat, format: ErrorFormat) -> bool {
+    let mut sources = SourceMap::new();
+    let source = match read_source(filename, &mut sources, format) {
+        Some(file) => sources.source(file).to_string(),
+        None => return false,
+    };
+
+    let dir = module::parent_dir(Path::new(filename));
+    let mut loader = Loader::new(search_path(dir), vec![], format).checking_only();
+    let (file, decls) = loader.check_source(filename.to_string(), source, dir);
+    let ok = decls.is_ok();
+    let decls = decls.unwrap_or_else(|decls| decls);
+
+    let out = match dump {
+        DumpFormat::Json => format!("{:#}\n", dump::decls_json(&decls, loader.sources().source(file))),
+        _ => dump::decls_sexpr(&decls),
+    };
+
+    // Whatever is reading the output may have stopped, e.g. `head`
+    io::stdout().write_all(out.as_bytes()).is_ok() && ok
 }
 
-pub fn run_repl() {
+// Check each of the files, along with the modules they import, without
+// running them. Gives back whether they were all free of errors.
+pub fn run_check(filenames: &[String], format: ErrorFormat) -> bool {
+    let mut ok = true;
+
+    for filename in filenames {
+        let path = Path::new(filename);
+        let mut loader = Loader::new(search_path(module::parent_dir(path)), vec![], format).checking_only();
+        ok &= loader.run_file(path);
+    }
+
+    ok
+}
+
+// Format each of the files in place. With `check`, the files are left as
+// they are and any that aren't formatted already are listed instead. Gives
+// back whether every file could be formatted and, with `check`, was.
+pub fn run_fmt(filenames: &[String], check: bool, format: ErrorFormat) -> bool {
+    let mut sources = SourceMap::new();
+    let mut ok = true;
+
+    for filename in filenames {
+        let file = match read_source(filename, &mut sources, format) {
+            Some(file) => file,
+            None => {
+                ok = false;
+                continue;
+            },
+        };
+
+        let formatted = match formatter::format(sources.source(file), file) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for err in errors {
+                    format.report(&err, &sources);
+                }
+                ok = false;
+                continue;
+            },
+        };
+
+        if formatted == sources.source(file) {
+            continue;
+        }
+
+        if check {
+            println!("{} isn't formatted.", filename);
+            ok = false;
+        } else if let Err(err) = fs::write(filename, formatted) {
+            format.report(&ModuleError::new(format!("Can't write '{}': {}.", filename, err)).diagnostic(), &sources);
+            ok = false;
+        }
+    }
+
+    ok
+}
+
+// Run the language server over stdin and stdout, until the editor says to
+// exit. Gives back whether it asked the server to shut down first.
+pub fn run_lsp() -> bool {
+    lsp::serve()
+}
+
+// Run a program. The functions in it can get at `args` through the `args`
+// builtin. Gives back whether it ran without any errors.
+pub fn run(source: String, args: Vec<String>, format: ErrorFormat) -> bool {
+    let mut loader = Loader::new(search_path(Path::new(".")), args, format);
+    loader.run_source("<source>".to_string(), source, false)
+}
+
+pub fn run_file(filename: &str, args: Vec<String>, format: ErrorFormat) -> bool {
+    let path = Path::new(filename);
+    let mut loader = Loader::new(search_path(module::parent_dir(path)), args, format);
+    loader.run_file(path)
+}
+
+// Read and run one line at a time, printing the value of each expression,
+// until the end of the input. Gives back whether the input could be read.
+pub fn run_repl() -> bool {
+    let mut loader = Loader::new(search_path(Path::new(".")), vec![], ErrorFormat::Human);
+
     loop {
         print!("gwn > ");
-        io::stdout().flush().unwrap();
+        if io::stdout().flush().is_err() {
+            return false;
+        }
 
         let mut input = String::new();
+        match io::stdin().read_line(&mut input) {
+            // The end of the input, e.g. from Ctrl-D
+            Ok(0) => {
+                println!();
+                return true;
+            },
+            Ok(_) => {},
+            Err(err) => {
+                eprintln!("Unable to read line: {}.", err);
+                return false;
+            },
+        }
 
-        io::stdin().read_line(&mut input)
-            .expect("Unable to read line.");
-
-        run(input);
+        loader.run_source("<repl>".to_string(), input, true);
     }
-}
\ No newline at end of file
+}
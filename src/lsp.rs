@@ -0,0 +1,837 @@
+// gwn::lsp: A language server, so that editors can show errors, types and
+// more while GWN is being written. It speaks the Language Server Protocol,
+// which is JSON-RPC over stdin and stdout.
+//
+// Whenever a document changes, it's checked again from scratch, along with
+// the modules it imports. Nothing is ever run, so a program that prints or
+// asks for input can't get mixed up with the messages to the editor.
+
+use crate::ast::{Decl, Expr, ExprKind, InterpolatedPart, Literal, Pattern, PatternKind};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::module::{self, Loader};
+use crate::parser::Parser;
+use crate::scanner::{Scanner, StringPart, Token, TokenKind};
+use crate::span::{FileId, SourceMap, Span};
+use crate::typ::Typ;
+use crate::{search_path, ErrorFormat};
+
+use serde_json::{json, Value as Json};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+// Error codes from JSON-RPC
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// The kinds of semantic token, in the order that `token_type` numbers them
+const TOKEN_TYPES: [&str; 8] = ["keyword", "number", "string", "comment", "operator", "variable", "type", "namespace"];
+
+// Kinds of document symbol, as numbered by the protocol
+const FUNCTION_SYMBOL: u32 = 12;
+const CONSTANT_SYMBOL: u32 = 14;
+
+// Handle messages from the editor until it says to exit. Gives back whether
+// it asked the server to shut down first, as it should have.
+pub fn serve() -> bool {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut server = Server { documents: HashMap::new(), shutdown: false };
+
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+
+            // A message that can't be read is skipped, since the editor
+            // can't be told which one it was
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("error: Skipping a message: {}", err);
+                continue;
+            },
+
+            // The editor went away without saying to exit
+            Ok(None) => return false,
+
+            // Or where the next message starts has been lost
+            Err(err) => {
+                eprintln!("error: Can't read any more messages: {}", err);
+                return false;
+            },
+        };
+
+        let message: Json = match serde_json::from_str(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                send(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": PARSE_ERROR, "message": err.to_string()},
+                }));
+                continue;
+            },
+        };
+
+        if message["method"] == "exit" {
+            return server.shutdown;
+        }
+
+        server.handle(&message);
+    }
+}
+
+// Read a single message, which is a header giving its length, a blank line
+// and then the JSON itself. Gives back None at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().to_string());
+        }
+    }
+
+    let length = match length {
+        Some(value) => value.parse::<usize>().map_err(|_| format!("Malformed Content-Length header '{}'.", value)),
+        None => Err("Missing Content-Length header.".to_string()),
+    };
+
+    let length = match length {
+        Ok(length) => length,
+        Err(problem) => {
+            skip_body(input)?;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, problem));
+        },
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+// Skip over the body of a message without knowing its length, by reading
+// it as JSON, which every message is. If it isn't, where the next message
+// starts has been lost, and there's no reading any more of them.
+fn skip_body(input: &mut impl BufRead) -> io::Result<()> {
+    match serde_json::Deserializer::from_reader(input).into_iter::<Json>().next() {
+        Some(Ok(_)) => Ok(()),
+        Some(Err(err)) => Err(io::Error::other(format!("Can't find the end of a message: {}", err))),
+        None => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+fn send(message: Json) {
+    let body = message.to_string();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    // If the editor has gone away, there's nobody left to tell
+    let _ = write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = out.flush();
+}
+
+struct Server {
+    // The documents that are open in the editor, by URI
+    documents: HashMap<String, Document>,
+
+    // Whether the editor has asked the server to shut down
+    shutdown: bool,
+}
+
+// An open document, and everything worked out about it the last time it
+// changed
+struct Document {
+    uri: String,
+
+    // The directory that its imports are relative to
+    dir: PathBuf,
+
+    // The loader that checked it, which holds its source and the source of
+    // each module that it imports
+    loader: Loader,
+    file: FileId,
+
+    // Its declarations, with their types filled in
+    decls: Vec<Decl>,
+}
+
+impl Server {
+    fn handle(&mut self, message: &Json) {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            },
+
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                self.update(uri(params), document["text"].as_str().unwrap_or("").to_string());
+                Ok(Json::Null)
+            },
+
+            // The whole of the document is sent each time, see `capabilities`
+            "textDocument/didChange" => {
+                let text = params["contentChanges"].as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+
+                if let Some(text) = text {
+                    self.update(uri(params), text.to_string());
+                }
+                Ok(Json::Null)
+            },
+
+            "textDocument/didClose" => {
+                self.documents.remove(uri(params));
+                publish(uri(params), vec![]);
+                Ok(Json::Null)
+            },
+
+            "textDocument/hover" => Ok(self.at(params).and_then(|(document, offset)| hover(document, offset)).unwrap_or(Json::Null)),
+            "textDocument/definition" => Ok(self.at(params).and_then(|(document, offset)| definition(document, offset)).unwrap_or(Json::Null)),
+            "textDocument/documentSymbol" => Ok(self.documents.get(uri(params)).map_or(Json::Null, symbols)),
+            "textDocument/semanticTokens/full" => Ok(self.documents.get(uri(params)).map_or(Json::Null, semantic_tokens)),
+
+            _ => Err(format!("Unknown method '{}'.", method)),
+        };
+
+        // Only requests have an id, and they are the only messages that
+        // get a response
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return,
+        };
+
+        send(match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(msg) => json!({"jsonrpc": "2.0", "id": id, "error": {"code": METHOD_NOT_FOUND, "message": msg}}),
+        });
+    }
+
+    // Check the new text of a document, and tell the editor what's wrong
+    // with it.
+    fn update(&mut self, uri: &str, text: String) {
+        let path = uri_to_path(uri);
+        let dir = path.as_deref().map_or_else(|| PathBuf::from("."), |path| module::parent_dir(path).to_path_buf());
+        let name = path.map_or_else(|| uri.to_string(), |path| path.display().to_string());
+
+        let mut loader = Loader::new(search_path(&dir), vec![], ErrorFormat::Json)
+            .checking_only()
+            .collecting();
+
+        let (file, decls) = loader.check_source(name, text, &dir);
+        let decls = decls.unwrap_or_else(|decls| decls);
+        let diagnostics = loader.take_diagnostics();
+        let document = Document { uri: uri.to_string(), dir, loader, file, decls };
+
+        publish(uri, diagnostics.iter().map(|diagnostic| lsp_diagnostic(&document, diagnostic)).collect());
+
+        self.documents.insert(uri.to_string(), document);
+    }
+
+    // The document and byte offset that a request is about.
+    fn at(&self, params: &Json) -> Option<(&Document, usize)> {
+        let document = self.documents.get(uri(params))?;
+        let offset = offset(document.source(), &params["position"]);
+        Some((document, offset))
+    }
+}
+
+impl Document {
+    fn source(&self) -> &str {
+        self.loader.sources().source(self.file)
+    }
+
+    fn location(&self, span: Span) -> Json {
+        let sources = self.loader.sources();
+        let uri = if span.file == self.file {
+            self.uri.clone()
+        } else {
+            path_to_uri(Path::new(sources.name(span.file)))
+        };
+
+        json!({"uri": uri, "range": range(sources, span)})
+    }
+}
+
+fn capabilities() -> Json {
+    json!({
+        "capabilities": {
+            // The whole of a document is sent whenever it changes
+            "textDocumentSync": 1,
+            "hoverProvider": true,
+            "definitionProvider": true,
+            "documentSymbolProvider": true,
+            "semanticTokensProvider": {
+                "legend": {"tokenTypes": TOKEN_TYPES, "tokenModifiers": []},
+                "full": true,
+            },
+        },
+        "serverInfo": {"name": "gwn"},
+    })
+}
+
+fn uri(params: &Json) -> &str {
+    params["textDocument"]["uri"].as_str().unwrap_or("")
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) {
+    send(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    }));
+}
+
+fn lsp_diagnostic(document: &Document, diagnostic: &Diagnostic) -> Json {
+    let mut message = diagnostic.message.clone();
+    for note in diagnostic.notes.iter() {
+        message.push_str(&format!("\nnote: {}", note));
+    }
+    if let Some(help) = &diagnostic.help {
+        message.push_str(&format!("\nhelp: {}", help));
+    }
+
+    let primary = diagnostic.labels.iter()
+        .find(|label| label.primary)
+        .or_else(|| diagnostic.labels.first());
+
+    let mut related: Vec<Json> = diagnostic.labels.iter()
+        .filter(|label| !label.primary)
+        .map(|label| json!({"location": document.location(label.span), "message": label.message}))
+        .collect();
+
+    // A problem in another file, such as a module that is imported, is
+    // shown at the start of the document, pointing to where it really is.
+    let start = Span::new(document.file, 0, 0);
+    let span = match primary {
+        Some(label) if label.span.file == document.file => label.span,
+        Some(label) => {
+            related.insert(0, json!({"location": document.location(label.span), "message": diagnostic.message}));
+            start
+        },
+        None => start,
+    };
+
+    json!({
+        "range": range(document.loader.sources(), span),
+        "severity": match diagnostic.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        },
+        "code": diagnostic.code,
+        "source": "gwn",
+        "message": message,
+        "relatedInformation": related,
+    })
+}
+
+// The type of whatever is under the cursor.
+fn hover(document: &Document, offset: usize) -> Option<Json> {
+    for decl in document.decls.iter() {
+        if let Decl::Constant(constant) = decl {
+            if contains(constant.name.span, offset) {
+                return hover_typ(document, constant.name.span, Some(&constant.name.lexeme), &constant.value.typ);
+            }
+        }
+
+        let expr = match decl {
+            Decl::Constant(constant) => &constant.value,
+            Decl::Evaluated(evaluated) => &evaluated.expr,
+            Decl::Import(_) => continue,
+        };
+
+        if let Some(expr) = innermost(expr, offset) {
+            let name = match &expr.node {
+                ExprKind::Constant(constant) => Some(&constant.name.lexeme),
+                _ => None,
+            };
+
+            return hover_typ(document, expr.span, name, &expr.typ);
+        }
+    }
+
+    None
+}
+
+fn hover_typ(document: &Document, span: Span, name: Option<&String>, typ: &Typ) -> Option<Json> {
+    // The checker didn't get as far as this
+    if *typ == Typ::Unknown {
+        return None;
+    }
+
+    let shown = match name {
+        Some(name) => format!("{}: {}", name, typ),
+        None => typ.to_string(),
+    };
+
+    Some(json!({
+        "contents": {"kind": "markdown", "value": format!("```gwn\n{}\n```", shown)},
+        "range": range(document.loader.sources(), span),
+    }))
+}
+
+// Where the constant or parameter under the cursor is declared.
+fn definition(document: &Document, offset: usize) -> Option<Json> {
+    let (name, param) = document.decls.iter()
+        .filter_map(|decl| match decl {
+            Decl::Constant(constant) => Some(&constant.value),
+            Decl::Evaluated(evaluated) => Some(&evaluated.expr),
+            Decl::Import(_) => None,
+        })
+        .find_map(|expr| lookup(expr, offset, &mut vec![]))?;
+
+    if let Some(span) = param {
+        return Some(document.location(span));
+    }
+
+    // A constant may be declared again further on, in which case it's the
+    // last declaration before it's used that counts
+    let constants: Vec<&Token> = document.decls.iter()
+        .filter_map(|decl| match decl {
+            Decl::Constant(constant) if constant.name.lexeme == name.lexeme => Some(&constant.name),
+            _ => None,
+        })
+        .collect();
+
+    let declared = constants.iter()
+        .rev()
+        .find(|constant| constant.span.start <= offset)
+        .or_else(|| constants.first());
+
+    if let Some(constant) = declared {
+        return Some(document.location(constant.span));
+    }
+
+    // Otherwise it might be from one of the modules that are imported
+    for decl in document.decls.iter() {
+        if let Decl::Import(import) = decl {
+            let file = match document.loader.imported(&import.path.lexeme, &document.dir) {
+                Some(file) => file,
+                None => continue,
+            };
+
+            let (decls, _) = Parser::new(document.loader.sources().source(file).to_string(), file).parse_all();
+            for decl in decls {
+                if let Decl::Constant(constant) = decl {
+                    if constant.name.lexeme == name.lexeme && constant.is_public() {
+                        return Some(document.location(constant.name.span));
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// Find the name under the cursor, along with where it's bound if it's a
+// function parameter. `params` are the parameters of the functions that
+// the expression is inside of, innermost last.
+fn lookup<'a>(expr: &'a Expr, offset: usize, params: &mut Vec<&'a Pattern>) -> Option<(&'a Token, Option<Span>)> {
+    if !contains(expr.span, offset) {
+        return None;
+    }
+
+    match &expr.node {
+        ExprKind::Constant(constant) => {
+            let param = params.iter().rev().find_map(|pattern| binding(pattern, &constant.name.lexeme));
+            Some((&constant.name, param))
+        },
+
+        ExprKind::Func(func) => {
+            for case in func.cases.iter() {
+                let outer = params.len();
+                params.extend(case.params.iter());
+
+                for guard in case.guards.iter() {
+                    let found = lookup(&guard.condition, offset, params)
+                        .or_else(|| lookup(&guard.value, offset, params));
+
+                    if found.is_some() {
+                        return found;
+                    }
+                }
+
+                params.truncate(outer);
+            }
+
+            None
+        },
+
+        _ => children(expr).into_iter().find_map(|child| lookup(child, offset, params)),
+    }
+}
+
+// Where a pattern binds `name`, if it does.
+fn binding(pattern: &Pattern, name: &str) -> Option<Span> {
+    match &pattern.node {
+        PatternKind::Identifier(token) if token.lexeme == name => Some(token.span),
+        PatternKind::Tuple(patterns) | PatternKind::List(patterns) => patterns.iter().find_map(|pattern| binding(pattern, name)),
+        PatternKind::Cons(head, tail) => binding(head, name).or_else(|| binding(tail, name)),
+        _ => None,
+    }
+}
+
+// The smallest expression under the cursor.
+fn innermost(expr: &Expr, offset: usize) -> Option<&Expr> {
+    if !contains(expr.span, offset) {
+        return None;
+    }
+
+    children(expr).into_iter()
+        .find_map(|child| innermost(child, offset))
+        .or(Some(expr))
+}
+
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match &expr.node {
+        ExprKind::Literal(_) | ExprKind::Constant(_) => vec![],
+        ExprKind::Unary(unary) => vec![&unary.operand],
+        ExprKind::Binary(binary) => vec![&binary.left, &binary.right],
+        ExprKind::Logical(logical) => vec![&logical.left, &logical.right],
+        ExprKind::Apply(apply) => vec![&apply.func, &apply.arg],
+        ExprKind::Func(func) => func.cases.iter()
+            .flat_map(|case| case.guards.iter())
+            .flat_map(|guard| {
+                // A guard without a condition is given one that is always
+                // true, which takes up the same source as its value
+                let implicit = guard.condition.span == guard.value.span
+                    && matches!(guard.condition.node, ExprKind::Literal(Literal::Bool(true)));
+
+                if implicit {
+                    vec![&guard.value]
+                } else {
+                    vec![&guard.condition, &guard.value]
+                }
+            })
+            .collect(),
+        ExprKind::Tuple(tuple) => tuple.elements.iter().collect(),
+        ExprKind::List(list) => list.elements.iter().collect(),
+        ExprKind::Range(range) => std::iter::once(&range.start)
+            .chain(range.next.iter())
+            .chain(range.end.iter())
+            .collect(),
+        ExprKind::InterpolatedString(string) => string.parts.iter()
+            .filter_map(|part| match part {
+                InterpolatedPart::Hole(hole) => Some(hole),
+                InterpolatedPart::Text(_) => None,
+            })
+            .collect(),
+    }
+}
+
+// The top-level constants of a document.
+fn symbols(document: &Document) -> Json {
+    let sources = document.loader.sources();
+
+    document.decls.iter()
+        .filter_map(|decl| match decl {
+            Decl::Constant(constant) => Some(json!({
+                "name": constant.name.lexeme,
+                "detail": match constant.value.typ {
+                    Typ::Unknown => Json::Null,
+                    ref typ => Json::from(typ.to_string()),
+                },
+                "kind": match constant.value.node {
+                    ExprKind::Func(_) => FUNCTION_SYMBOL,
+                    _ => CONSTANT_SYMBOL,
+                },
+                "range": range(sources, decl.span()),
+                "selectionRange": range(sources, constant.name.span),
+            })),
+            _ => None,
+        })
+        .collect()
+}
+
+// Which of `TOKEN_TYPES` a token is highlighted as, if any.
+fn token_type(token: &Token) -> Option<u32> {
+    match token.kind {
+        TokenKind::And | TokenKind::Or | TokenKind::Not | TokenKind::True | TokenKind::False |
+        TokenKind::Else | TokenKind::Import => Some(0),
+
+        TokenKind::Number => Some(1),
+        TokenKind::String | TokenKind::InterpolatedString => Some(2),
+        TokenKind::Comment => Some(3),
+
+        TokenKind::Plus | TokenKind::PlusPlus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash |
+        TokenKind::Percent | TokenKind::Carat | TokenKind::Equal | TokenKind::EqualEqual | TokenKind::BangEqual |
+        TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual |
+        TokenKind::LeftArrow | TokenKind::RightArrow | TokenKind::DotDot | TokenKind::Colon |
+        TokenKind::Pipe | TokenKind::Question => Some(4),
+
+        // Types, e.g. in an annotation, are the only names with a capital
+        TokenKind::Identifier if token.lexeme.starts_with(char::is_uppercase) => Some(6),
+        TokenKind::Identifier => Some(5),
+
+        TokenKind::Path => Some(7),
+
+        _ => None,
+    }
+}
+
+// Every token that is highlighted, encoded as the protocol asks: five
+// numbers each, for the line and character that it starts at relative to
+// the token before it, its length, its type and its modifiers.
+fn semantic_tokens(document: &Document) -> Json {
+    json!({"data": token_data(document.source(), document.file)})
+}
+
+fn token_data(source: &str, file: FileId) -> Vec<usize> {
+    let mut highlights = vec![];
+    highlight(Scanner::new(source.to_string(), file).with_trivia(), &mut highlights);
+
+    let mut data: Vec<usize> = vec![];
+    let (mut last_line, mut last_character) = (0, 0);
+    let mut emit = |line: usize, character: usize, length: usize, typ: u32| {
+        if length > 0 {
+            let delta = if line == last_line { character - last_character } else { character };
+            data.extend([line - last_line, delta, length, typ as usize, 0]);
+            last_line = line;
+            last_character = character;
+        }
+    };
+
+    // The tokens come in order, so the source is walked through once,
+    // keeping track of the line and character along the way.
+    let (mut offset, mut line, mut character) = (0, 0, 0);
+    for (start, end, typ) in highlights {
+        if start < offset {
+            continue;
+        }
+
+        for c in source[offset..start].chars() {
+            if c == '\n' {
+                line += 1;
+                character = 0;
+            } else {
+                character += c.len_utf16();
+            }
+        }
+
+        // Editors expect each token to be on a single line, so those that
+        // aren't, such as triple-quoted strings, are split up.
+        let mut piece_start = character;
+        for c in source[start..end].chars() {
+            if c == '\n' {
+                emit(line, piece_start, character - piece_start, typ);
+                line += 1;
+                character = 0;
+                piece_start = 0;
+            } else {
+                character += c.len_utf16();
+            }
+        }
+        emit(line, piece_start, character - piece_start, typ);
+
+        offset = end;
+    }
+
+    data
+}
+
+// Add the byte range and type of every token to highlight, in order. The
+// holes of an interpolated string are highlighted like any other code, with
+// the text around them highlighted as a string.
+fn highlight(mut scanner: Scanner, highlights: &mut Vec<(usize, usize, u32)>) {
+    loop {
+        let token = scanner.scan_token().unwrap_or_else(|err| err.token());
+        if token.kind == TokenKind::Eof {
+            break;
+        }
+
+        let typ = match token_type(&token) {
+            Some(typ) => typ,
+            None => continue,
+        };
+
+        let mut start = token.span.start;
+        for part in token.parts {
+            if let StringPart::Hole { source, span, col } = part {
+                highlights.push((start, span.start, typ));
+                highlight(Scanner::at(source, span.file, span.start, col).with_trivia(), highlights);
+                start = span.end;
+            }
+        }
+        highlights.push((start, token.span.end, typ));
+    }
+}
+
+fn contains(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset < span.end
+}
+
+// The line and character of a byte offset, as the protocol counts them:
+// both from 0, with characters in UTF-16 code units.
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before = &source[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character = before[line_start..].chars().map(char::len_utf16).sum();
+
+    (line, character)
+}
+
+fn range(sources: &SourceMap, span: Span) -> Json {
+    let source = sources.source(span.file);
+    let (start_line, start_character) = position(source, span.start);
+    let (end_line, end_character) = position(source, span.end);
+
+    json!({
+        "start": {"line": start_line, "character": start_character},
+        "end": {"line": end_line, "character": end_character},
+    })
+}
+
+// The byte offset of a position from the editor.
+fn offset(source: &str, position: &Json) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+    let line_start = match line {
+        0 => 0,
+        _ => match source.match_indices('\n').nth(line - 1) {
+            Some((i, _)) => i + 1,
+            None => return source.len(),
+        },
+    };
+
+    let mut units = 0;
+    for (i, c) in source[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+
+    source.len()
+}
+
+// The path that a `file://` URI is for, with its escapes decoded.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut path = vec![];
+
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = encoded.get(i + 1..i + 3)
+            .filter(|_| encoded[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                path.push(byte);
+                i += 3;
+            },
+            None => {
+                path.push(encoded[i]);
+                i += 1;
+            },
+        }
+    }
+
+    Some(PathBuf::from(String::from_utf8_lossy(&path).into_owned()))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = "file://".to_string();
+
+    for byte in path.display().to_string().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holes_are_highlighted() {
+        let source = "a = \"é {b + 1}\"\n";
+        assert_eq!(token_data(source, FileId(0)), [
+            0, 0, 1, 5, 0,
+            0, 2, 1, 4, 0,
+            0, 2, 4, 2, 0,
+            0, 4, 1, 5, 0,
+            0, 2, 1, 4, 0,
+            0, 2, 1, 1, 0,
+            0, 1, 2, 2, 0,
+        ]);
+    }
+
+    #[test]
+    fn strings_are_split_into_lines() {
+        let source = "a = \"\"\"\n  x {b}\n  y\n  \"\"\"\n";
+        assert_eq!(token_data(source, FileId(0)), [
+            0, 0, 1, 5, 0,
+            0, 2, 1, 4, 0,
+            0, 2, 3, 2, 0,
+            1, 0, 5, 2, 0,
+            0, 5, 1, 5, 0,
+            0, 1, 1, 2, 0,
+            1, 0, 3, 2, 0,
+            1, 0, 5, 2, 0,
+        ]);
+    }
+
+    #[test]
+    fn malformed_headers_are_skipped() {
+        let input = concat!(
+            "Content-Length: ten\r\n\r\n{\n  \"text\": \"Content-Length: 4\\r\\n\\r\\n[{}]\"\n}",
+            "Content-Length: 2\r\n\r\n[]",
+            "Content-Type: application/json\r\n\r\n[\n1,\n2\n]",
+            "Content-Length: 2\r\n\r\n{}",
+            "Content-Length: 2\r\n\r\n",
+        );
+        let mut input = input.as_bytes();
+
+        assert_eq!(read_message(&mut input).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read_message(&mut input).unwrap(), Some("[]".to_string()));
+        assert_eq!(read_message(&mut input).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read_message(&mut input).unwrap(), Some("{}".to_string()));
+        assert_eq!(read_message(&mut input).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn bodies_that_cant_be_skipped_stop_reading() {
+        let mut input = "Content-Length: ten\r\n\r\nnot json\r\nContent-Length: 2\r\n\r\n{}".as_bytes();
+        let err = read_message(&mut input).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+
+        let mut input = "Content-Length: ten\r\n\r\n".as_bytes();
+        assert_eq!(read_message(&mut input).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
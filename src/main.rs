@@ -1,11 +1,189 @@
+use gwn::{DumpFormat, ErrorFormat};
+
 use std::env;
+use std::process;
+use std::thread;
+
+// The interpreter recurses for each function call, so run it on a thread
+// with plenty of stack.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+const USAGE: &str = "\
+Usage: gwn [options] <command> [-- <args>...]
+
+Commands:
+    run <file>               Run a program, giving it any <args> after `--`
+    check <files>...         Check programs for errors without running them
+    repl                     Start an interactive session
+    fmt [--check] <files>... Format files in place, or with `--check` just
+                             list those that aren't formatted
+    tokens <file>            Print the tokens that a file is scanned into
+    ast <file>               Print the declarations that a file is parsed into,
+                             with their types
+    lsp                      Run the language server over stdin and stdout
+
+`gwn <file>` is short for `gwn run <file>`, and `gwn` on its own starts the
+REPL.
+
+Options:
+    --error-format=<human|json>  How errors and warnings are written out
+    --format <format>            How `tokens` (text or json) or `ast` (sexpr or
+                                 json) write out what they find
+    -h, --help                   Print this help
+    -V, --version                Print the version
+";
+
+enum Command {
+    Run(String),
+    Check(Vec<String>),
+    Repl,
+    Fmt(Vec<String>),
+    Tokens(String),
+    Ast(String),
+    Lsp,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    // Everything after `--` is for the program being run
+    let script_args = match args.iter().position(|arg| arg == "--") {
+        Some(dashes) => args.split_off(dashes).split_off(1),
+        None => vec![],
+    };
+
+    let mut format = ErrorFormat::Human;
+    let mut check = false;
+    let mut dump = None;
+    let mut words = vec![];
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--error-format=json" => format = ErrorFormat::Json,
+            "--error-format=human" => format = ErrorFormat::Human,
+            "--check" => check = true,
+
+            "--format" => match args.next() {
+                Some(name) => dump = Some(parse_dump_format(&name)),
+                None => usage_error("'--format' needs a format."),
+            },
 
-    match args.len() {
-        1 => gwn::run_repl(),
-        2 => gwn::run_file(args[1].clone()),
-        _ => panic!("Too many arguments."), // TODO: Argv
+            _ if arg.starts_with("--format=") => dump = Some(parse_dump_format(&arg["--format=".len()..])),
+
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            },
+
+            "-V" | "--version" => {
+                println!("gwn {}", gwn::version());
+                return;
+            },
+
+            _ if arg.starts_with('-') => usage_error(&format!("Unknown option '{}'.", arg)),
+            _ => words.push(arg),
+        }
+    }
+
+    let command = parse_command(words);
+
+    if check && !matches!(command, Command::Fmt(_)) {
+        usage_error("'--check' can only be used with 'fmt'.");
     }
+
+    // Each command that prints a dump has its own formats, and default
+    let dump = match (&command, dump) {
+        (Command::Tokens(_), None) => DumpFormat::Text,
+        (Command::Tokens(_), Some(DumpFormat::Sexpr)) => usage_error("'tokens' can only be printed as text or json."),
+        (Command::Ast(_), None) => DumpFormat::Sexpr,
+        (Command::Ast(_), Some(DumpFormat::Text)) => usage_error("'ast' can only be printed as sexpr or json."),
+        (Command::Tokens(_), Some(dump)) | (Command::Ast(_), Some(dump)) => dump,
+        (_, None) => DumpFormat::Text,
+        (_, Some(_)) => usage_error("'--format' can only be used with 'tokens' or 'ast'."),
+    };
+
+    if !script_args.is_empty() && !matches!(command, Command::Run(_)) {
+        usage_error("Arguments after '--' can only be given to a program that is run.");
+    }
+
+    let execute = move || match command {
+        Command::Run(file) => gwn::run_file(&file, script_args, format),
+        Command::Check(files) => gwn::run_check(&files, format),
+        Command::Repl => gwn::run_repl(),
+        Command::Fmt(files) => gwn::run_fmt(&files, check, format),
+        Command::Tokens(file) => gwn::run_tokens(&file, dump, format),
+        Command::Ast(file) => gwn::run_ast(&file, dump, format),
+        Command::Lsp => gwn::run_lsp(),
+    };
+
+    let ok = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(execute)
+        .map(|thread| thread.join().unwrap_or(false))
+        .unwrap_or_else(|err| {
+            eprintln!("error: Unable to start the interpreter: {}.", err);
+            false
+        });
+
+    if !ok {
+        process::exit(1);
+    }
+}
+
+fn parse_command(mut words: Vec<String>) -> Command {
+    if words.is_empty() {
+        return Command::Repl;
+    }
+
+    let name = words.remove(0);
+    match name.as_str() {
+        "run" => Command::Run(one_file(&name, words)),
+        "check" => Command::Check(some_files(&name, words)),
+        "repl" => no_files(&name, words, Command::Repl),
+        "fmt" => Command::Fmt(some_files(&name, words)),
+        "tokens" => Command::Tokens(one_file(&name, words)),
+        "ast" => Command::Ast(one_file(&name, words)),
+        "lsp" => no_files(&name, words, Command::Lsp),
+
+        // Anything else is a file to run
+        _ if words.is_empty() => Command::Run(name),
+        _ => usage_error(&format!("Unknown command '{}'.", name)),
+    }
+}
+
+fn parse_dump_format(name: &str) -> DumpFormat {
+    match name {
+        "text" => DumpFormat::Text,
+        "json" => DumpFormat::Json,
+        "sexpr" => DumpFormat::Sexpr,
+        _ => usage_error(&format!("Unknown format '{}', expected 'text', 'json' or 'sexpr'.", name)),
+    }
+}
+
+fn one_file(command: &str, mut words: Vec<String>) -> String {
+    match words.len() {
+        1 => words.remove(0),
+        0 => usage_error(&format!("'{}' needs a file.", command)),
+        _ => usage_error(&format!("'{}' only takes one file.", command)),
+    }
+}
+
+fn some_files(command: &str, words: Vec<String>) -> Vec<String> {
+    if words.is_empty() {
+        usage_error(&format!("'{}' needs at least one file.", command));
+    }
+    words
+}
+
+fn no_files(command: &str, words: Vec<String>, result: Command) -> Command {
+    if let Some(word) = words.first() {
+        usage_error(&format!("'{}' doesn't take any files, but was given '{}'.", command, word));
+    }
+    result
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("error: {}\n\nSee `gwn --help` for how to use gwn.", msg);
+    process::exit(2);
 }
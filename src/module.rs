@@ -0,0 +1,355 @@
+// gwn::module: Finding, loading and running the modules that a program
+// imports.
+//
+// A module is a single source file. Its public constants, i.e. those whose
+// names don't start with `_`, can be imported by other modules with
+// `import ./util`, which is relative to the importing file, or with
+// `import List`, which looks for `List.gwn` along the search path. Each
+// module is only loaded once, however many modules import it.
+
+use crate::ast::Decl;
+use crate::parser::Parser;
+use crate::checker::Checker;
+use crate::interpreter::Interpreter;
+use crate::span::{FileId, SourceMap, Span};
+use crate::error::Report;
+use crate::diagnostic::{Diagnostic, Label};
+use crate::exhaustive;
+use crate::ErrorFormat;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Loader {
+    sources: SourceMap,
+    checker: Checker,
+    interpreter: Interpreter,
+    format: ErrorFormat,
+
+    // The directories that modules imported by name are looked for in
+    search_path: Vec<PathBuf>,
+
+    // The modules that have been loaded, by their canonical path, or None
+    // for those that couldn't be, so that their errors are only reported
+    // once
+    loaded: HashMap<PathBuf, Option<FileId>>,
+
+    // The modules that are part way through being loaded, outermost first,
+    // so that an import cycle can be caught
+    loading: Vec<(PathBuf, String)>,
+
+    // Whether modules are only checked, and not run
+    check_only: bool,
+
+    // Where errors and warnings go when they are collected rather than
+    // written out, see `Loader::collecting`
+    diagnostics: Option<Vec<Diagnostic>>,
+}
+
+impl Loader {
+    // A loader whose programs are given `args` as their arguments.
+    pub fn new(search_path: Vec<PathBuf>, args: Vec<String>, format: ErrorFormat) -> Loader {
+        Loader {
+            sources: SourceMap::new(),
+            checker: Checker::new(),
+            interpreter: Interpreter::new(args),
+            format,
+            search_path,
+            loaded: HashMap::new(),
+            loading: vec![],
+            check_only: false,
+            diagnostics: None,
+        }
+    }
+
+    // Only check modules, without running any of them.
+    pub fn checking_only(self) -> Loader {
+        Loader { check_only: true, ..self }
+    }
+
+    // Keep errors and warnings to be taken with `take_diagnostics`, rather
+    // than writing them out.
+    pub fn collecting(self) -> Loader {
+        Loader { diagnostics: Some(vec![]), ..self }
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.diagnostics.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    // Load and run the program in the file at `path`. Gives back whether it
+    // ran without any errors.
+    pub fn run_file(&mut self, path: &Path) -> bool {
+        let name = path.display().to_string();
+
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                self.report(&ModuleError::new(format!("Can't read '{}': {}.", name, err)).diagnostic());
+                return false;
+            },
+        };
+
+        if let Ok(canonical) = fs::canonicalize(path) {
+            self.loading.push((canonical, name.clone()));
+        }
+
+        let file = self.sources.add(name, source);
+        self.run_module(file, parent_dir(path), false).is_some()
+    }
+
+    // Run some source that isn't from a file, keeping any constants that it
+    // declares for the next time. If `echo` is set, the value of each
+    // evaluated declaration is printed, as in the REPL.
+    pub fn run_source(&mut self, name: String, source: String, echo: bool) -> bool {
+        let file = self.sources.add(name, source);
+        self.run_module(file, Path::new("."), echo).is_some()
+    }
+
+    // Check some source without running it, as the language server does.
+    // Imports are relative to `dir`. The declarations are given back with
+    // as many of their types filled in as could be worked out, even when
+    // there are errors, in which case they are given back as an Err.
+    pub fn check_source(&mut self, name: String, source: String, dir: &Path) -> (FileId, Result<Vec<Decl>, Vec<Decl>>) {
+        let file = self.sources.add(name, source);
+        (file, self.check_module(file, dir))
+    }
+
+    // The file of a module that has been loaded, by how it was imported
+    // from a file in `dir`.
+    pub fn imported(&self, import: &str, dir: &Path) -> Option<FileId> {
+        let path = self.resolve(import, dir).ok()?;
+        let canonical = fs::canonicalize(&path).unwrap_or(path);
+        self.loaded.get(&canonical).copied().flatten()
+    }
+
+    // Check and then run the module in `file`, after loading each of the
+    // modules it imports from `dir` or the search path. Gives back the
+    // module's declarations, or None if anything went wrong, having already
+    // reported it.
+    fn run_module(&mut self, file: FileId, dir: &Path, echo: bool) -> Option<Vec<Decl>> {
+        let ast = self.check_module(file, dir).ok()?;
+
+        if self.check_only {
+            return Some(ast);
+        }
+
+        for decl in ast.iter() {
+            let result = self.interpreter.run(decl)
+                .and_then(|value| match value {
+                    Some(value) if echo && !value.is_unit() => value.show().map(Some),
+                    _ => Ok(None),
+                });
+
+            match result {
+                Ok(Some(shown)) => println!("{}", shown),
+                Ok(None) => {},
+                Err(err) => {
+                    self.report(&err.diagnostic());
+                    return None;
+                },
+            }
+        }
+
+        Some(ast)
+    }
+
+    // Parse and check the module in `file`, after loading each of the
+    // modules it imports. If anything goes wrong, it's reported and
+    // whatever could be made of the declarations is given back as an error.
+    fn check_module(&mut self, file: FileId, dir: &Path) -> Result<Vec<Decl>, Vec<Decl>> {
+        let mut parser = Parser::new(self.sources.source(file).to_string(), file);
+        let (mut ast, errors) = parser.parse_all();
+        if !errors.is_empty() {
+            for err in errors {
+                self.report(&err.diagnostic());
+            }
+            return Err(ast);
+        }
+
+        let mut imports = vec![];
+        for decl in ast.iter() {
+            if let Decl::Import(import) = decl {
+                let span = import.keyword.span.to(import.path.span);
+                match self.load(&import.path.lexeme, dir, span) {
+                    Some(import) => imports.push(import),
+                    None => return Err(ast),
+                }
+            }
+        }
+
+        for import in imports {
+            self.checker.import(import);
+            self.interpreter.import(import);
+        }
+
+        let errors = self.checker.check(&mut ast);
+        if !errors.is_empty() {
+            for err in errors {
+                self.report(&err.diagnostic());
+            }
+            return Err(ast);
+        }
+
+        // Problems with the cases of functions are only warnings, so the
+        // program is run anyway.
+        for warning in exhaustive::check(&ast) {
+            self.report(&warning);
+        }
+
+        Ok(ast)
+    }
+
+    // Load the module that `import` names, unless it has been already,
+    // giving back the file it's in. `span` is the import, for errors.
+    fn load(&mut self, import: &str, dir: &Path, span: Span) -> Option<FileId> {
+        let path = match self.resolve(import, dir) {
+            Ok(path) => path,
+            Err(err) => {
+                self.report(&err.with_span(span).diagnostic());
+                return None;
+            },
+        };
+
+        let name = path.display().to_string();
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+
+        if let Some(start) = self.loading.iter().position(|(loading, _)| *loading == canonical) {
+            let cycle: Vec<&str> = self.loading[start..].iter()
+                .map(|(_, name)| name.as_str())
+                .chain(std::iter::once(name.as_str()))
+                .collect();
+
+            let err = ModuleError::new(format!("Modules can't import each other in a cycle: {}.", cycle.join(" -> ")))
+                .with_span(span);
+            self.report(&err.diagnostic());
+            return None;
+        }
+
+        if let Some(file) = self.loaded.get(&canonical) {
+            return *file;
+        }
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                let err = ModuleError::new(format!("Can't read '{}': {}.", name, err)).with_span(span);
+                self.report(&err.diagnostic());
+                self.loaded.insert(canonical, None);
+                return None;
+            },
+        };
+
+        let file = self.sources.add(name.clone(), source);
+        self.loading.push((canonical.clone(), name.clone()));
+
+        let scope = self.checker.begin_module();
+        let globals = self.interpreter.begin_module();
+
+        let ast = self.run_module(file, parent_dir(&path), false);
+
+        let decls = ast.as_deref().unwrap_or(&[]);
+        self.checker.finish_module(file, &name, decls, scope);
+        self.interpreter.finish_module(file, decls, globals);
+        self.loading.pop();
+
+        let file = ast.map(|_| file);
+        self.loaded.insert(canonical, file);
+        file
+    }
+
+    // Find the file of the module that `import` names. A path starting with
+    // `./`, `../` or `/` is relative to `dir`, the directory of the
+    // importing file, while a plain name is looked for along the search
+    // path. The `.gwn` extension may be left off.
+    fn resolve(&self, import: &str, dir: &Path) -> Result<PathBuf, ModuleError> {
+        let filename = if import.ends_with(".gwn") {
+            import.to_string()
+        } else {
+            format!("{}.gwn", import)
+        };
+
+        if import.starts_with("./") || import.starts_with("../") || import.starts_with('/') {
+            let path: PathBuf = dir.join(&filename).components().collect();
+            if path.is_file() {
+                Ok(path)
+            } else {
+                Err(ModuleError::new(format!("Can't find the module '{}'.", import))
+                    .with_note(format!("There is no file '{}'.", path.display())))
+            }
+        } else {
+            self.search_path.iter()
+                .map(|dir| dir.join(&filename).components().collect::<PathBuf>())
+                .find(|path| path.is_file())
+                .ok_or_else(|| {
+                    let searched: Vec<String> = self.search_path.iter()
+                        .map(|dir| format!("'{}'", dir.display()))
+                        .collect();
+
+                    ModuleError::new(format!("Can't find the module '{}'.", import))
+                        .with_note(format!("Looked for '{}' in {}.", filename, searched.join(", ")))
+                })
+        }
+    }
+
+    fn report(&mut self, diagnostic: &Diagnostic) {
+        match &mut self.diagnostics {
+            Some(diagnostics) => diagnostics.push(diagnostic.clone()),
+            None => self.format.report(diagnostic, &self.sources),
+        }
+    }
+}
+
+// The directory that a file is in, which its relative imports start from.
+pub(crate) fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    }
+}
+
+const MODULE_ERROR: &str = "E0005";
+
+#[derive(Debug)]
+pub struct ModuleError {
+    // The import that went wrong
+    span: Option<Span>,
+
+    msg: String,
+    note: Option<String>,
+}
+
+impl ModuleError {
+    pub(crate) fn new(msg: String) -> ModuleError {
+        ModuleError { span: None, msg, note: None }
+    }
+
+    fn with_span(self, span: Span) -> ModuleError {
+        ModuleError { span: Some(span), ..self }
+    }
+
+    fn with_note(self, note: String) -> ModuleError {
+        ModuleError { note: Some(note), ..self }
+    }
+}
+
+impl Report for ModuleError {
+    fn diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(MODULE_ERROR, self.msg.clone());
+
+        if let Some(span) = self.span {
+            diagnostic = diagnostic.with_label(Label::primary(span, ""));
+        }
+
+        if let Some(note) = &self.note {
+            diagnostic = diagnostic.with_note(note.clone());
+        }
+
+        diagnostic
+    }
+}
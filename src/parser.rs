@@ -1,50 +1,286 @@
-use crate::ast::{Decl, Expr, Pattern, Literal, ExprKind, ConstantExpr, UnaryExpr, BinaryExpr, LogicalExpr, ApplyExpr, TupleExpr, ListExpr};
-use crate::scanner::{Scanner, Token, TokenKind};
+use crate::ast::{Decl, ConstantDecl, EvaluatedDecl, ImportDecl, Expr, Pattern, Literal, ConstantExpr, UnaryExpr, BinaryExpr, LogicalExpr, ApplyExpr, FuncExpr, FuncCase, FuncGuard, TupleExpr, ListExpr, RangeExpr, InterpolatedStringExpr, InterpolatedPart, PatternKind};
+use crate::scanner::{self, Scanner, ScannerError, Token, TokenKind, StringPart};
+use crate::span::{FileId, Span};
 use crate::typ::Typ;
 use crate::error::Report;
+use crate::diagnostic::{Diagnostic, Label};
 
-use std::collections::HashMap;
-use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+
+// The codes of errors found by the scanner and by the parser itself
+const SCANNER_ERROR: &str = "E0001";
+const SYNTAX_ERROR: &str = "E0002";
 
 pub struct Parser {
-    scanner: Scanner,
+    tokens: Tokens,
 
     previous: Token,
     current: Token,
+
+    // Tokens that have been scanned ahead of `current` by `peek`
+    lookahead: VecDeque<Token>,
+
+    // Which newlines end a declaration, see `scan_token`
+    layout: Layout,
+
+    // Whether we're parsing an element of a tuple or list, where a comma
+    // followed by a brace starts the next element rather than another
+    // case of a function
+    in_elements: bool,
+
+    // The errors found so far, including ones from the scanner
+    errors: Vec<ParserError>,
 }
 
 impl Parser {
-    pub fn new(source: String) -> Parser {
+    pub fn new(source: String, file: FileId) -> Parser {
+        Parser::with_scanner(Scanner::new(source, file))
+    }
+
+    // A parser for tokens that have already been scanned, ending with an
+    // `Eof` token, e.g. those of a CST. Whitespace and comments are skipped.
+    pub fn from_tokens(tokens: Vec<Result<Token, ScannerError>>) -> Parser {
+        Parser::with_tokens(Tokens::Scanned(tokens.into_iter()))
+    }
+
+    fn with_scanner(scanner: Scanner) -> Parser {
+        Parser::with_tokens(Tokens::Scanner(scanner))
+    }
+
+    fn with_tokens(tokens: Tokens) -> Parser {
         Parser {
-            scanner: Scanner::new(source),
-            previous: Token { kind: TokenKind::None, line: 0, col: 0, lexeme: String::new() },
-            current: Token { kind: TokenKind::None, line: 0, col: 0, lexeme: String::new() },
+            tokens,
+            previous: Token::none(),
+            current: Token::none(),
+            lookahead: VecDeque::new(),
+            layout: Layout::new(),
+            in_elements: false,
+            errors: Vec::new(),
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Expr> {
+    // Parse the whole of the source, giving back the declarations that
+    // could be parsed along with all of the errors found.
+    pub fn parse_all(&mut self) -> (Vec<Decl>, Vec<ParserError>) {
         self.advance();
-        let mut ast: Vec<Expr> = Vec::new();
+        let mut ast: Vec<Decl> = Vec::new();
 
         while !self.is_at_end() {
-            let expr = self.parse_precedence(Precedence::Or);
-            if let Ok(expr) = expr {
-                ast.push(expr);
-            } else if let Err(err) = expr {
-                err.report_in(&self.scanner.source.iter().collect());
-                self.advance();
-            };
+            match self.parse_decl() {
+                Ok(decl) => ast.push(decl),
+                Err(err) => {
+                    self.error(err);
+                    self.synchronise();
+                },
+            }
+        }
+
+        (ast, mem::take(&mut self.errors))
+    }
+
+    // Parse the expression in a hole of an interpolated string, which must
+    // be all of its source.
+    fn parse_hole(&mut self) -> Result<Expr, Vec<ParserError>> {
+        // The hole is inside of the string's braces, so newlines in it are
+        // ignored.
+        self.layout.depth = 1;
+        self.advance();
+
+        let result = self.parse_precedence(Precedence::Or)
+            .and_then(|expr| {
+                self.expect(TokenKind::Eof, "Expected '}' after expression in string.".to_string())?;
+                Ok(expr)
+            });
+
+        if let Err(err) = result.as_ref() {
+            self.error(err.clone());
+        }
+
+        match result {
+            Ok(expr) if self.errors.is_empty() => Ok(expr),
+            _ => Err(mem::take(&mut self.errors)),
+        }
+    }
+
+    // Record an error. An error at an `Error` token is left out, as the
+    // scanner has already given an error for it.
+    fn error(&mut self, err: ParserError) {
+        if err.token.kind != TokenKind::Error {
+            self.errors.push(err);
+        }
+    }
+
+    // After an error, skip ahead to the start of the next declaration, so
+    // that we can carry on parsing without reporting errors that only
+    // follow on from this one. Since newlines inside of brackets are left
+    // out by `scan_token`, this also skips past the closing brackets of
+    // the broken declaration.
+    //
+    // If a bracket is never closed, there won't be another newline, so an
+    // identifier at the very start of a line is taken to begin the next
+    // declaration instead.
+    fn synchronise(&mut self) {
+        loop {
+            match self.current.kind {
+                TokenKind::Eof => return,
+                TokenKind::Newline => {
+                    self.advance();
+                    return;
+                },
+                TokenKind::Identifier if self.layout.depth > 0 && starts_line(&self.current) => {
+                    self.layout.depth = 0;
+                    return;
+                },
+                _ => self.advance(),
+            }
+        }
+    }
+
+    // Parse a single top-level declaration. This is either a constant
+    // declaration such as `a = 2` or `a: Int = 2`, or an expression that
+    // is evaluated, like `print <- "Hello"`.
+    // Declarations are terminated by a newline.
+    fn parse_decl(&mut self) -> Result<Decl, ParserError> {
+        let decl = if self.check(TokenKind::Import) {
+            self.parse_import_decl()?
+        } else if self.at_constant_decl() {
+            self.parse_constant_decl()?
+        } else {
+            let expr = self.parse_precedence(Precedence::Or)?;
+            EvaluatedDecl{expr}.into()
+        };
+
+        if !self.is_at_end() {
+            self.expect(TokenKind::Newline, "Expected newline after declaration.".to_string())?;
+        }
+
+        Ok(decl)
+    }
+
+    // Whether the declaration starting at `current` declares a constant. A
+    // name followed by `:` may start either a constant with a type, e.g.
+    // `xs: [Int] = []`, or an expression, e.g. `x : xs -> print`, so it's
+    // only taken to be a constant if an `=` comes before the end of the
+    // declaration.
+    fn at_constant_decl(&mut self) -> bool {
+        if !self.check(TokenKind::Identifier) {
+            return false;
+        }
+
+        match self.peek().kind {
+            TokenKind::Equal => true,
+            TokenKind::Colon => {
+                let mut n = 1;
+                loop {
+                    match self.peek_nth(n).kind {
+                        TokenKind::Equal => return true,
+                        TokenKind::Newline | TokenKind::Eof => return false,
+                        _ => n += 1,
+                    }
+                }
+            },
+            _ => false,
+        }
+    }
+
+    fn parse_constant_decl(&mut self) -> Result<Decl, ParserError> {
+        self.advance();
+        let name = self.previous.clone();
+
+        let annotation = if self.consume(TokenKind::Colon) {
+            Some(self.parse_type(&mut HashMap::new())?)
+        } else {
+            None
+        };
+
+        self.expect(TokenKind::Equal, "Expected '=' after constant name.".to_string())?;
+        let value = self.parse_precedence(Precedence::Or)?;
+
+        Ok(ConstantDecl{name, annotation, value}.into())
+    }
+
+    fn parse_import_decl(&mut self) -> Result<Decl, ParserError> {
+        self.advance();
+        let keyword = self.previous.clone();
+
+        self.expect(TokenKind::Path, "Expected module to import after 'import'.".to_string())?;
+        let path = self.previous.clone();
+
+        Ok(ImportDecl{keyword, path}.into())
+    }
+
+    // Parse a type, e.g. `Int`, `[a]`, `(Int, String)` or `Int -> Bool`.
+    // `->` is right associative, so `a -> b -> c` is `a -> (b -> c)`.
+    // Type variables are named with a lowercase letter, and `vars` keeps
+    // track of which names have been given which numbers.
+    fn parse_type(&mut self, vars: &mut HashMap<String, u32>) -> Result<Typ, ParserError> {
+        let param = self.parse_type_atom(vars)?;
+
+        if self.consume(TokenKind::RightArrow) {
+            let ret = self.parse_type(vars)?;
+            Ok(Typ::Func(Box::new(param), Box::new(ret)))
+        } else {
+            Ok(param)
         }
+    }
+
+    fn parse_type_atom(&mut self, vars: &mut HashMap<String, u32>) -> Result<Typ, ParserError> {
+        self.advance();
+
+        match self.previous.kind {
+            TokenKind::Identifier => {
+                let name = self.previous.lexeme.clone();
+                match &name[..] {
+                    "Int" => Ok(Typ::Int),
+                    "Float" => Ok(Typ::Float),
+                    "Bool" => Ok(Typ::Bool),
+                    "String" => Ok(Typ::String),
+                    _ if name.starts_with(char::is_lowercase) => {
+                        let next = vars.len() as u32;
+                        Ok(Typ::Var(*vars.entry(name).or_insert(next)))
+                    },
+                    _ => Err(self.make_error_at(&self.previous, format!("Unknown type '{}'.", name))),
+                }
+            },
+
+            TokenKind::LeftSquare => {
+                let elem = self.parse_type(vars)?;
+                self.expect(TokenKind::RightSquare, "Expected ']' after list type.".to_string())?;
+                Ok(Typ::List(Box::new(elem)))
+            },
+
+            TokenKind::LeftParen => {
+                let mut elements = vec![];
+                if !self.check(TokenKind::RightParen) {
+                    elements.push(self.parse_type(vars)?);
+                    while self.consume(TokenKind::Comma) {
+                        elements.push(self.parse_type(vars)?);
+                    }
+                }
+
+                self.expect(TokenKind::RightParen, "Expected ')' after tuple type.".to_string())?;
 
-        ast
+                // As with expressions, a type in brackets is just that type
+                if elements.len() == 1 {
+                    Ok(elements.pop().unwrap())
+                } else {
+                    Ok(Typ::Tuple(elements))
+                }
+            },
+
+            _ => Err(self.make_error_at(&self.previous, "Expected type.".to_string())),
+        }
     }
 
     fn parse_precedence(&mut self, precedence: Precedence) -> Result<Expr, ParserError> {
+        let start = self.current.span;
         self.advance();
         let prefix_fn = get_parse_rule(self.previous.kind).prefix;
 
         if let Some(prefix_fn) = prefix_fn {
             let mut expr = prefix_fn(self)?;
+            expr.span = start.to(self.previous.span);
 
             while precedence <= get_parse_rule(self.current.kind).precedence {
                 self.advance();
@@ -53,6 +289,7 @@ impl Parser {
 
                 if let Some(infix_fn) = infix_fn {
                     expr = infix_fn(self, expr)?;
+                    expr.span = start.to(self.previous.span);
                 }
             }
 
@@ -63,13 +300,14 @@ impl Parser {
     }
 
     fn parse_number(&mut self) -> Result<Expr, ParserError> {
-        if self.previous.lexeme.contains(".") {
-            let value = self.previous.lexeme.parse::<f64>().unwrap();
-            Ok(Literal::Float(value).into())
-        } else {
-            let value = self.previous.lexeme.parse::<i32>().unwrap();
-            Ok(Literal::Int(value).into())
-        }
+        Ok(self.number_literal()?.into())
+    }
+
+    // Convert the number token just consumed, e.g. `42` or `4.2`, into a
+    // literal. The scanner has already checked it, so this shouldn't fail.
+    fn number_literal(&self) -> Result<Literal, ParserError> {
+        scanner::number_literal(&self.previous.lexeme)
+            .map_err(|msg| self.make_error_at(&self.previous, msg))
     }
 
     fn parse_bool(&mut self) -> Result<Expr, ParserError> {
@@ -83,8 +321,29 @@ impl Parser {
     }
 
     fn parse_string(&mut self) -> Result<Expr, ParserError> {
-        let value = String::from(self.previous.lexeme.clone());
-        Ok(Literal::String(Box::new(value)).into())
+        let value = self.previous.lexeme.clone();
+        Ok(Literal::String(value).into())
+    }
+
+    // Parse the token just consumed, an interpolated string, parsing the
+    // source of each of its holes as an expression.
+    fn parse_interpolated_string(&mut self) -> Result<Expr, ParserError> {
+        let mut parts = vec![];
+
+        for part in self.previous.parts.clone() {
+            match part {
+                StringPart::Text(text) => parts.push(InterpolatedPart::Text(text)),
+                StringPart::Hole { source, span, col } => {
+                    let scanner = Scanner::at(source, span.file, span.start, col);
+                    match Parser::with_scanner(scanner).parse_hole() {
+                        Ok(expr) => parts.push(InterpolatedPart::Hole(expr)),
+                        Err(errors) => self.errors.extend(errors),
+                    }
+                },
+            }
+        }
+
+        Ok(InterpolatedStringExpr{parts}.into())
     }
 
     fn parse_constant(&mut self) -> Result<Expr, ParserError> {
@@ -139,18 +398,17 @@ impl Parser {
     }
 
     fn parse_tuple(&mut self) -> Result<Expr, ParserError> {
-        let paren = self.previous.clone();
-        let expression = self.parse_precedence(Precedence::Or)?;
+        let expression = self.parse_element()?;
 
         let value = if self.consume(TokenKind::Comma) {
             let mut elements: Vec<Expr> = vec![expression];
-            elements.push(self.parse_precedence(Precedence::Or)?);
+            elements.push(self.parse_element()?);
             
             while self.consume(TokenKind::Comma) {
-                elements.push(self.parse_precedence(Precedence::Or)?);
+                elements.push(self.parse_element()?);
             }
 
-            Ok(TupleExpr{elements, paren}.into())
+            Ok(TupleExpr{elements}.into())
         } else {
             // A single element tuple is equivalent to the element
             Ok(expression)
@@ -160,44 +418,285 @@ impl Parser {
         value
     }
 
+    // Parse an element of a tuple, list or range. A function with several cases
+    // can't be one, so that `({a | a}, {b | b})` is a tuple of two
+    // functions. Such a function has to be given a name first.
+    fn parse_element(&mut self) -> Result<Expr, ParserError> {
+        let in_elements = mem::replace(&mut self.in_elements, true);
+        let element = self.parse_precedence(Precedence::Or);
+        self.in_elements = in_elements;
+        element
+    }
+
+    // Parse either a list, e.g. `[1, 2, 3]`, or a range, e.g. `[1..3]`.
     fn parse_list(&mut self) -> Result<Expr, ParserError> {
         let square = self.previous.clone();
         let mut elements: Vec<Expr> = vec![];
 
         if !self.check(TokenKind::RightSquare) {
-            elements.push(self.parse_precedence(Precedence::Or)?);
+            elements.push(self.parse_element()?);
         }
 
         while self.consume(TokenKind::Comma) {
-            elements.push(self.parse_precedence(Precedence::Or)?);
+            elements.push(self.parse_element()?);
+        }
+
+        // A range may have either one element before the `..`, or two if it
+        // has a step.
+        if !elements.is_empty() && elements.len() <= 2 && self.consume(TokenKind::DotDot) {
+            return self.parse_range(square, elements);
         }
 
         self.expect(TokenKind::RightSquare, "Expected ']' after list.".to_string())?;
-        Ok(ListExpr{elements, square}.into())
+        Ok(ListExpr{elements}.into())
+    }
+
+    // Parse the rest of a range, from just after the `..`.
+    fn parse_range(&mut self, square: Token, mut elements: Vec<Expr>) -> Result<Expr, ParserError> {
+        let next = if elements.len() == 2 { elements.pop() } else { None };
+        let start = elements.pop().unwrap();
+
+        let end = if self.check(TokenKind::RightSquare) {
+            None
+        } else {
+            Some(self.parse_element()?)
+        };
+
+        self.expect(TokenKind::RightSquare, "Expected ']' after range.".to_string())?;
+        Ok(RangeExpr{start, next, end, square}.into())
+    }
+
+    // Parse a function literal, e.g. `{x | x + 1}`.
+    // A function may be made up of several comma-seperated cases, which
+    // are tried in order:
+    // ```
+    // {0 | 0},
+    // {n | n - 1}
+    // ```
+    fn parse_function(&mut self) -> Result<Expr, ParserError> {
+        let brace = self.previous.clone();
+        let mut cases = vec![self.parse_case()?];
+
+        // A comma followed by another brace continues the list of cases,
+        // unless it's between the elements of a tuple or list.
+        while !self.in_elements && self.check(TokenKind::Comma) && self.peek().kind == TokenKind::LeftBrace {
+            self.advance();
+            self.advance();
+
+            let case = self.parse_case()?;
+            if case.params.len() != cases[0].params.len() {
+                return Err(self.make_error_at(&brace,
+                    "Every case of a function must take the same number of parameters.".to_string()));
+            }
+
+            cases.push(case);
+        }
+
+        Ok(FuncExpr{cases, brace}.into())
+    }
+
+    // Parse a single case of a function, from just after the opening '{'
+    // up to and including the closing '}'. Inside of the case, a comma and
+    // a brace continue a function again, even if the case is in a tuple.
+    fn parse_case(&mut self) -> Result<FuncCase, ParserError> {
+        let in_elements = mem::replace(&mut self.in_elements, false);
+        let case = self.parse_case_body();
+        self.in_elements = in_elements;
+        case
+    }
+
+    fn parse_case_body(&mut self) -> Result<FuncCase, ParserError> {
+        let brace = self.previous.clone();
+
+        let mut params: Vec<Pattern> = vec![];
+        while !self.check(TokenKind::Pipe) && !self.is_at_end() {
+            params.push(self.parse_pattern()?);
+        }
+
+        if params.is_empty() {
+            return Err(self.make_error_at(&self.current, "Expected parameter before '|'.".to_string()));
+        }
+
+        self.expect(TokenKind::Pipe, "Expected '|' after function parameters.".to_string())?;
+
+        let mut guards: Vec<FuncGuard> = vec![];
+        loop {
+            guards.push(self.parse_guard()?);
+
+            if self.consume(TokenKind::RightBrace) {
+                break;
+            }
+
+            if self.is_at_end() {
+                return Err(self.make_error_at(&self.current, "Expected '}' after function body.".to_string()));
+            }
+
+            // Guards may be seperated by a comma or a leading '|', or just
+            // placed one after another.
+            if !self.consume(TokenKind::Comma) {
+                self.consume(TokenKind::Pipe);
+            }
+        }
+
+        Ok(FuncCase{params, guards, brace})
+    }
+
+    // Parse a single guard, e.g. `x == 0 ? "zero"`, `else ? "other"`, or
+    // just a value such as `x + 1`, which is sugar for `true ? x + 1`.
+    fn parse_guard(&mut self) -> Result<FuncGuard, ParserError> {
+        if self.consume(TokenKind::Else) {
+            let condition = always(self.previous.span);
+            self.expect(TokenKind::Question, "Expected '?' after 'else'.".to_string())?;
+
+            let value = self.parse_precedence(Precedence::Or)?;
+            return Ok(FuncGuard{condition, value});
+        }
+
+        let expr = self.parse_precedence(Precedence::Or)?;
+
+        if self.consume(TokenKind::Question) {
+            let value = self.parse_precedence(Precedence::Or)?;
+            Ok(FuncGuard{condition: expr, value})
+        } else {
+            Ok(FuncGuard{condition: always(expr.span), value: expr})
+        }
     }
 
-    /*fn parse_function(&mut self) -> Result<Expr, ParserError> {
+    // Parse a pattern that a function parameter is matched against,
+    // e.g. `0`, `x`, `(a, b)` or `x : xs`. `:` is right associative, so
+    // `a : b : rest` is `a : (b : rest)`.
+    fn parse_pattern(&mut self) -> Result<Pattern, ParserError> {
+        let head = self.parse_pattern_primary()?;
+
+        if self.consume(TokenKind::Colon) {
+            let tail = self.parse_pattern()?;
+            let span = head.span.to(tail.span);
+            Ok(Pattern::new(PatternKind::Cons(Box::new(head), Box::new(tail)), span))
+        } else {
+            Ok(head)
+        }
+    }
+
+    fn parse_pattern_primary(&mut self) -> Result<Pattern, ParserError> {
+        let start = self.current.span;
+        let node = self.parse_pattern_node()?;
+        Ok(Pattern::new(node, start.to(self.previous.span)))
+    }
+
+    fn parse_pattern_node(&mut self) -> Result<PatternKind, ParserError> {
+        self.advance();
+
+        match self.previous.kind {
+            TokenKind::Number => Ok(PatternKind::Literal(self.number_literal()?)),
+            TokenKind::String => Ok(PatternKind::Literal(Literal::String(self.previous.lexeme.clone()))),
+            TokenKind::True => Ok(PatternKind::Literal(Literal::Bool(true))),
+            TokenKind::False => Ok(PatternKind::Literal(Literal::Bool(false))),
+
+            // A negative number, e.g. `-1`
+            TokenKind::Minus => {
+                self.expect(TokenKind::Number, "Expected number after '-' in pattern.".to_string())?;
+                match self.number_literal()? {
+                    Literal::Int(i) => Ok(PatternKind::Literal(Literal::Int(i.neg()))),
+                    Literal::Float(x) => Ok(PatternKind::Literal(Literal::Float(-x))),
+                    _ => unreachable!(),
+                }
+            },
+
+            TokenKind::Identifier if self.previous.lexeme == "_" => Ok(PatternKind::Wildcard),
+            TokenKind::Identifier => Ok(PatternKind::Identifier(Box::new(self.previous.clone()))),
+
+            TokenKind::LeftParen => {
+                let mut elements = vec![];
+                if !self.check(TokenKind::RightParen) {
+                    elements.push(self.parse_pattern()?);
+                    while self.consume(TokenKind::Comma) {
+                        elements.push(self.parse_pattern()?);
+                    }
+                }
+
+                self.expect(TokenKind::RightParen, "Expected ')' after tuple pattern.".to_string())?;
 
-    }*/
+                // A single element tuple is equivalent to the element
+                if elements.len() == 1 {
+                    Ok(elements.pop().unwrap().node)
+                } else {
+                    Ok(PatternKind::Tuple(elements))
+                }
+            },
+
+            TokenKind::LeftSquare => {
+                let mut elements = vec![];
+                if !self.check(TokenKind::RightSquare) {
+                    elements.push(self.parse_pattern()?);
+                    while self.consume(TokenKind::Comma) {
+                        elements.push(self.parse_pattern()?);
+                    }
+                }
+
+                self.expect(TokenKind::RightSquare, "Expected ']' after list pattern.".to_string())?;
+                Ok(PatternKind::List(elements))
+            },
+
+            _ => Err(self.make_error_at(&self.previous, "Expected pattern.".to_string())),
+        }
+    }
 
     fn advance(&mut self) {
         self.previous = self.current.clone();
-        let mut token = self.scanner.scan_token();
+        self.current = match self.lookahead.pop_front() {
+            Some(token) => token,
+            None => self.scan_token(),
+        };
+    }
 
-        if let Ok(token) = token {
-            self.current = token;
-        } else {
-            while let Err(err) = token { 
-                err.report_in(&self.scanner.source.iter().collect());
-                token = self.scanner.scan_token();
-            }
-            
-            if let Ok(token) = token {
-                self.current = token;
+    // Scan the next token. Scanner errors are recorded, and an `Error`
+    // token is given back in place of the source that couldn't be scanned.
+    //
+    // This is also where layout is handled. A newline ends a declaration,
+    // except that it is ignored:
+    // - inside of `()`, `[]` or `{}`
+    // - after a token that can't end an expression, such as a binary
+    //   operator, `|`, `,` or `?`, so that the expression continues onto
+    //   the next line
+    // - at the start of the source, and after another newline
+    fn scan_token(&mut self) -> Token {
+        loop {
+            let scanned = match &mut self.tokens {
+                Tokens::Scanner(scanner) => scanner.scan_token(),
+                Tokens::Scanned(tokens) => tokens.next()
+                    .unwrap_or_else(|| Ok(Token { kind: TokenKind::Eof, ..Token::none() })),
+            };
+
+            let token = match scanned {
+                Ok(token) => token,
+                Err(err) => {
+                    let token = err.token();
+                    self.errors.push(err.into());
+                    token
+                }
+            };
+
+            if self.layout.admits(token.kind) {
+                return token;
             }
         }
     }
 
+    // Look at the token after `current`, without consuming anything.
+    fn peek(&mut self) -> &Token {
+        self.peek_nth(0)
+    }
+
+    // The token `n` tokens after the one that `peek` gives back
+    fn peek_nth(&mut self, n: usize) -> &Token {
+        while self.lookahead.len() <= n {
+            let token = self.scan_token();
+            self.lookahead.push_back(token);
+        }
+
+        &self.lookahead[n]
+    }
+
     fn check(&self, kind: TokenKind) -> bool {
         self.current.kind == kind
     }
@@ -216,13 +715,15 @@ impl Parser {
             return Err(self.make_error_at(&self.current, msg));
         }
 
-        return Ok(())
+        Ok(())
     }
 
     fn make_error_at(&self, token: &Token, msg: String) -> ParserError {
         ParserError {
-            token: token.clone(),
-            msg
+            token: Box::new(token.clone()),
+            msg,
+            code: SYNTAX_ERROR,
+            help: None,
         }
     }
 
@@ -265,10 +766,22 @@ impl From<u32> for Precedence {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Associativity {
+    // `a - b - c` is `(a - b) - c`
+    Left,
+
+    // `a : b : c` is `a : (b : c)`
+    Right,
+}
+
+type PrefixFn = fn(&mut Parser) -> Result<Expr, ParserError>;
+type InfixFn = fn(&mut Parser, Expr) -> Result<Expr, ParserError>;
+
 struct ParseRule {
     precedence: Precedence,
-    prefix: Option<fn(&mut Parser) -> Result<Expr, ParserError>>,
-    infix: Option<fn(&mut Parser, Expr) -> Result<Expr, ParserError>>,
+    prefix: Option<PrefixFn>,
+    infix: Option<InfixFn>,
 }
 
 lazy_static! {
@@ -298,6 +811,12 @@ lazy_static! {
             infix: None,
         }),
 
+        (TokenKind::InterpolatedString, ParseRule {
+            precedence: Precedence::None,
+            prefix: Some(Parser::parse_interpolated_string),
+            infix: None,
+        }),
+
         (TokenKind::Identifier, ParseRule {
             precedence: Precedence::None,
             prefix: Some(Parser::parse_constant), 
@@ -316,6 +835,12 @@ lazy_static! {
             infix: None,
         }),
 
+        (TokenKind::LeftBrace, ParseRule {
+            precedence: Precedence::None,
+            prefix: Some(Parser::parse_function), 
+            infix: None,
+        }),
+
 
         (TokenKind::Minus, ParseRule {
             precedence: Precedence::Term,
@@ -401,6 +926,18 @@ lazy_static! {
             infix: Some(Parser::parse_binary_left),
         }),
 
+        (TokenKind::And, ParseRule {
+            precedence: Precedence::And,
+            prefix: None, 
+            infix: Some(Parser::parse_binary_left),
+        }),
+
+        (TokenKind::Or, ParseRule {
+            precedence: Precedence::Or,
+            prefix: None, 
+            infix: Some(Parser::parse_binary_left),
+        }),
+
         (TokenKind::Colon, ParseRule {
             precedence: Precedence::Term,
             prefix: None,
@@ -433,25 +970,356 @@ fn get_parse_rule(kind: TokenKind) -> &'static ParseRule {
     }
 }
 
+// How tightly an infix operator binds, and which way it groups, i.e.
+// whether it is parsed by `parse_binary_left` or `parse_binary_right`. This
+// is what the AST is printed back out with, so that it gets only the
+// brackets it needs.
+pub fn binding(kind: TokenKind) -> (Precedence, Associativity) {
+    let associativity = match kind {
+        TokenKind::Colon | TokenKind::LeftArrow => Associativity::Right,
+        _ => Associativity::Left,
+    };
+
+    (get_parse_rule(kind).precedence, associativity)
+}
+
+// Where the parser gets its tokens from
+enum Tokens {
+    Scanner(Scanner),
+
+    // Tokens that were scanned beforehand, along with any errors
+    Scanned(std::vec::IntoIter<Result<Token, ScannerError>>),
+}
+
+// The layout rules, see `Parser::scan_token`. The CST follows them too, so
+// that it splits the source into declarations just as the parser does.
+pub struct Layout {
+    // The number of brackets currently open
+    depth: u32,
+
+    // The kind of the last token let through
+    last: TokenKind,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout::new()
+    }
+}
+
+impl Layout {
+    pub fn new() -> Layout {
+        Layout { depth: 0, last: TokenKind::None }
+    }
+
+    // Whether a token of this kind should be let through to the parser.
+    // Newlines that don't end a declaration aren't, and nor are whitespace
+    // and comments.
+    pub fn admits(&mut self, kind: TokenKind) -> bool {
+        match kind {
+            TokenKind::LeftBrace | TokenKind::LeftSquare | TokenKind::LeftParen =>
+                self.depth += 1,
+
+            TokenKind::RightBrace | TokenKind::RightSquare | TokenKind::RightParen =>
+                self.depth = self.depth.saturating_sub(1),
+
+            TokenKind::Newline if self.depth > 0 || continues_line(self.last) =>
+                return false,
+
+            TokenKind::Whitespace | TokenKind::Comment =>
+                return false,
+
+            _ => {}
+        }
+
+        self.last = kind;
+        true
+    }
+}
+
+// Whether a token is right at the start of its line, with no indentation.
+fn starts_line(token: &Token) -> bool {
+    token.col == 1
+}
+
+// Whether a newline after a token of this kind should be ignored, because
+// the token can't be the last one in a declaration.
+fn continues_line(kind: TokenKind) -> bool {
+    match kind {
+        TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash |
+        TokenKind::Percent | TokenKind::Carat | TokenKind::PlusPlus |
+        TokenKind::EqualEqual | TokenKind::BangEqual |
+        TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual |
+        TokenKind::And | TokenKind::Or | TokenKind::Not |
+        TokenKind::Colon | TokenKind::LeftArrow | TokenKind::RightArrow |
+        TokenKind::Equal | TokenKind::Comma | TokenKind::Pipe | TokenKind::Question |
+        TokenKind::Else => true,
+
+        // Leading and repeated newlines are dropped too
+        TokenKind::Newline | TokenKind::None => true,
+
+        _ => false,
+    }
+}
+
+// The condition of a guard that always holds, as with `else` or a guard
+// that is just a value.
+fn always(span: Span) -> Expr {
+    let mut condition: Expr = Literal::Bool(true).into();
+    condition.span = span;
+    condition
+}
+
+#[derive(Debug, Clone)]
 pub struct ParserError {
-    token: Token,
+    token: Box<Token>,
     msg: String,
+
+    // Whether the error came from the scanner or the parser itself
+    code: &'static str,
+
+    // How the source might be fixed
+    help: Option<String>,
+}
+
+impl From<ScannerError> for ParserError {
+    fn from(err: ScannerError) -> ParserError {
+        // Point at what went wrong, rather than all of the error token
+        ParserError {
+            token: Box::new(Token { span: err.span(), ..err.token() }),
+            msg: err.message().to_string(),
+            code: SCANNER_ERROR,
+            help: err.help().map(str::to_string),
+        }
+    }
 }
 
 impl Report for ParserError {
-    fn position(&self) -> (u32, u32) {
-        (self.token.line, self.token.col)
+    fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.code, self.msg.clone())
+            .with_label(Label::primary(self.token.span, ""));
+
+        match &self.help {
+            Some(help) => diagnostic.with_help(help.clone()),
+            None => diagnostic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ExprKind;
+    use crate::diagnostic::Position;
+
+    fn parse(source: &str) -> Vec<Expr> {
+        let mut parser = Parser::new(source.to_string(), FileId::default());
+        parser.advance();
+
+        let mut exprs = vec![];
+        while !parser.is_at_end() {
+            exprs.push(parser.parse_precedence(Precedence::Or).unwrap_or_else(|err| panic!("{}", err.msg)));
+            parser.consume(TokenKind::Newline);
+        }
+
+        exprs
     }
 
-    fn message(&self) -> &String {
-        &(self.msg)
+    fn cases(expr: &Expr) -> &Vec<FuncCase> {
+        match &expr.node {
+            ExprKind::Func(func) => &func.cases,
+            node => panic!("Expected a function, found {:?}", node),
+        }
     }
 
-    fn place(&self) -> String {
-        format!(" at {}", match self.token.kind {
-            TokenKind::Newline => "newline".to_string(),
-            TokenKind::Eof => "end".to_string(),
-            _ => format!("'{}'", self.token.lexeme),
-        })
+    fn elements(expr: &Expr) -> &Vec<Expr> {
+        match &expr.node {
+            ExprKind::Tuple(tuple) => &tuple.elements,
+            ExprKind::List(list) => &list.elements,
+            node => panic!("Expected a tuple or list, found {:?}", node),
+        }
+    }
+
+    #[test]
+    fn cases_and_guards() {
+        let ast = parse("{0 | 0},\n{n | n > 0 ? n\n    | else ? 0}");
+        assert_eq!(ast.len(), 1);
+
+        let cases = cases(&ast[0]);
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].guards.len(), 1);
+        assert_eq!(cases[1].guards.len(), 2);
+        assert!(matches!(cases[1].guards[1].condition.node, ExprKind::Literal(Literal::Bool(true))));
+    }
+
+    #[test]
+    fn curried_parameters() {
+        let ast = parse("{x y z | x + y + z}");
+        assert_eq!(cases(&ast[0])[0].params.len(), 3);
+    }
+
+    #[test]
+    fn functions_in_a_tuple_or_list_are_separate_elements() {
+        for source in ["({a | a}, {b | b})", "[{a | a}, {b | b}]"] {
+            let ast = parse(source);
+            let elements = elements(&ast[0]);
+            assert_eq!(elements.len(), 2);
+            assert!(elements.iter().all(|element| cases(element).len() == 1));
+        }
+
+        // Commas still separate the guards of a case in a tuple
+        let ast = parse("({x | x == 0 ? 1, else ? 2}, 3)");
+        let elements = elements(&ast[0]);
+        assert_eq!(elements.len(), 2);
+        assert_eq!(cases(&elements[0])[0].guards.len(), 2);
+    }
+
+    #[test]
+    fn binding_matches_how_operators_are_parsed() {
+        let operators = ["+", "-", "*", "/", "%", "^", "==", "!=", "<", "<=", ">", ">=", "++", "and", "or", ":", "<-", "->"];
+        for operator in operators {
+            let source = format!("a {} b {} c", operator, operator);
+            let ast = parse(&source);
+
+            // The operand written first, whichever side of `->` it's on
+            let (kind, first) = match &ast[0].node {
+                ExprKind::Binary(binary) => (binary.operator.kind, &binary.left),
+                ExprKind::Logical(logical) => (logical.operator.kind, &logical.left),
+                ExprKind::Apply(apply) if apply.operator.kind == TokenKind::RightArrow => (apply.operator.kind, &apply.arg),
+                ExprKind::Apply(apply) => (apply.operator.kind, &apply.func),
+                node => panic!("Expected an infix operation, found {:?}", node),
+            };
+
+            let grouped = if first.span().end - first.span().start == 1 { Associativity::Right } else { Associativity::Left };
+            assert_eq!(binding(kind).1, grouped, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn declarations() {
+        let (ast, errors) = Parser::new("a = 2\nb: Int = 3\n\nprint <- a\n".to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "The declarations don't parse");
+        assert_eq!(ast.len(), 3);
+        assert!(matches!(ast[0], Decl::Constant(_)));
+        assert!(matches!(ast[1], Decl::Constant(_)));
+        assert!(matches!(ast[2], Decl::Evaluated(_)));
+    }
+
+    #[test]
+    fn declarations_may_start_with_a_cons() {
+        let (ast, errors) = Parser::new("x : xs -> print\nys: [Int] = x : xs\nx : xs == ys\n".to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "The declarations don't parse");
+        assert_eq!(ast.len(), 3);
+        assert!(matches!(ast[0], Decl::Evaluated(_)));
+        assert!(matches!(ast[1], Decl::Constant(_)));
+        assert!(matches!(ast[2], Decl::Evaluated(_)));
+
+        // A bad type is still reported as one
+        let (_, errors) = Parser::new("n: 5 = 1\n".to_string(), FileId::default()).parse_all();
+        assert_eq!(errors.iter().map(|err| err.msg.clone()).collect::<Vec<_>>(), ["Expected type."]);
+    }
+
+    #[test]
+    fn newlines_continue_inside_brackets_and_after_operators() {
+        let source = "a = 1 +\n    2\nb = [1,\n    2]\nc = {x | x == 0 ?\n    1\n    | else ? 2}\nprint <- a\n";
+        let (ast, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "{:?} doesn't parse", source);
+        assert_eq!(ast.len(), 4);
+    }
+
+    #[test]
+    fn ranges() {
+        let ast = parse("[1..100]\n[99..1]\n[1, 3 .. 99]\n[1..]\n[1, 2]");
+        let ranges: Vec<(bool, bool)> = ast.iter()
+            .map(|expr| match &expr.node {
+                ExprKind::Range(range) => (range.next.is_some(), range.end.is_some()),
+                node => panic!("Expected a range, found {:?}", node),
+            })
+            .take(4)
+            .collect();
+
+        assert_eq!(ranges, [(false, true), (false, true), (true, true), (false, false)]);
+        assert_eq!(elements(&ast[4]).len(), 2);
+    }
+
+    #[test]
+    fn spans_cover_the_source_of_each_node() {
+        let source = "f = {(a, _) | a + 10}\nprint <- [1, 3 .. 9]\n";
+        let (ast, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "{:?} doesn't parse", source);
+        let text = |span: Span| &source[span.start..span.end];
+
+        assert_eq!(text(ast[0].span()), "f = {(a, _) | a + 10}");
+        assert_eq!(text(ast[1].span()), "print <- [1, 3 .. 9]");
+
+        let case = match &ast[0] {
+            Decl::Constant(constant) => &cases(&constant.value)[0],
+            decl => panic!("Expected a constant, found {:?}", decl),
+        };
+        assert_eq!(text(case.params[0].span()), "(a, _)");
+        assert_eq!(text(case.guards[0].value.span()), "a + 10");
+
+        let range = match &ast[1] {
+            Decl::Evaluated(evaluated) => match &evaluated.expr.node {
+                ExprKind::Apply(apply) => &apply.arg,
+                node => panic!("Expected an application, found {:?}", node),
+            },
+            decl => panic!("Expected an evaluated declaration, found {:?}", decl),
+        };
+        assert_eq!(text(range.span()), "[1, 3 .. 9]");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn holes_in_strings_are_parsed() {
+        let source = "\"{n} is {n * 2 -> toString}\"";
+        let ast = parse(source);
+        let parts = match &ast[0].node {
+            ExprKind::InterpolatedString(string) => &string.parts,
+            node => panic!("Expected an interpolated string, found {:?}", node),
+        };
+
+        let parts: Vec<_> = parts.iter()
+            .map(|part| match part {
+                InterpolatedPart::Text(text) => text.clone(),
+                InterpolatedPart::Hole(hole) => format!("{{{}}}", &source[hole.span().start..hole.span().end]),
+            })
+            .collect();
+        assert_eq!(parts, ["", "{n}", " is ", "{n * 2 -> toString}", ""]);
+    }
+
+    // The code and line of each error in `source`
+    fn errors(source: &str) -> Vec<(&'static str, u32)> {
+        let (_, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+        errors.iter()
+            .map(|err| err.diagnostic())
+            .map(|diagnostic| (diagnostic.code, Position::of(source, diagnostic.labels[0].span.start).line))
+            .collect()
+    }
+
+    #[test]
+    fn every_broken_declaration_is_reported() {
+        assert_eq!(errors("a = )\nb = 1\nc = {x x}\nd = 2\ne = 1 2\nf = 3\n"), [("E0002", 1), ("E0002", 3), ("E0002", 5)]);
+    }
+
+    #[test]
+    fn unclosed_brackets_stop_at_the_next_declaration() {
+        assert_eq!(errors("a = [1, 2\nb = (3\nc = 4\n"), [("E0002", 2), ("E0002", 3)]);
+    }
+
+    #[test]
+    fn scanner_and_parser_errors_are_reported_together() {
+        assert_eq!(errors("a = @\nb = $\nc = )\nd = 1\n"), [("E0001", 1), ("E0001", 2), ("E0002", 3)]);
+    }
+
+    #[test]
+    fn errors_in_holes_point_into_the_string() {
+        assert_eq!(errors("a = 1\nb = \"{a +}\"\nc = \"{a b}\"\n"), [("E0002", 2), ("E0002", 3)]);
+        assert_eq!(errors("a = \"{\"\nb = 1\n"), [("E0001", 1)]);
+    }
+
+    #[test]
+    fn no_follow_on_errors() {
+        assert_eq!(errors("a = (1 + )\n").len(), 1);
+        assert_eq!(errors("a = [1, {x | }, 3]\nb = 2\n").len(), 1);
+    }
+}
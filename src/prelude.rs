@@ -0,0 +1,327 @@
+// gwn::prelude: The functions that are built in to the language, along
+// with their types. These are in scope in every program, for both the type
+// checker and the interpreter.
+//
+// Functions over lists take the list first, so that they read well with
+// `->`, e.g. `[1..10] -> filter <- {x | x % 2 == 0}`.
+
+use crate::typ::Typ;
+use crate::value::{Value, List, Native, NativeFn};
+use crate::int::Int;
+use crate::interpreter::{call, RuntimeError};
+
+use std::io;
+use std::rc::Rc;
+
+pub struct Builtin {
+    pub native: Native,
+
+    // The type of the function. Any type variables in it may be used at
+    // any type.
+    pub typ: Typ,
+}
+
+pub fn builtins() -> Vec<Builtin> {
+    let a = || Typ::Var(0);
+    let b = || Typ::Var(1);
+
+    vec![
+        // print: a -> ()
+        builtin("print", 1, print, func(a(), Typ::Tuple(vec![]))),
+
+        // prompt: String
+        // Reads a line of input each time it is used.
+        builtin("prompt", 0, prompt, Typ::String),
+
+        // args: [String]
+        // The arguments given to the program after `--`. These belong to
+        // the interpreter running the program, which defines `args` over
+        // this, see `Interpreter::new`.
+        builtin("args", 0, no_args, list(Typ::String)),
+
+        // toString: a -> String
+        builtin("toString", 1, to_string, func(a(), Typ::String)),
+
+        // map: [a] -> (a -> b) -> [b]
+        builtin("map", 2, map, func(list(a()), func(func(a(), b()), list(b())))),
+
+        // filter: [a] -> (a -> Bool) -> [a]
+        builtin("filter", 2, filter, func(list(a()), func(func(a(), Typ::Bool), list(a())))),
+
+        // foldl: [a] -> b -> (b -> a -> b) -> b
+        builtin("foldl", 3, foldl, func(list(a()), func(b(), func(func(b(), func(a(), b())), b())))),
+
+        // foldr: [a] -> b -> (a -> b -> b) -> b
+        builtin("foldr", 3, foldr, func(list(a()), func(b(), func(func(a(), func(b(), b())), b())))),
+
+        // length: [a] -> Int
+        builtin("length", 1, length, func(list(a()), Typ::Int)),
+
+        // head: [a] -> a
+        builtin("head", 1, head, func(list(a()), a())),
+
+        // tail: [a] -> [a]
+        builtin("tail", 1, tail, func(list(a()), list(a()))),
+
+        // reverse: [a] -> [a]
+        builtin("reverse", 1, reverse, func(list(a()), list(a()))),
+
+        // zip: [a] -> [b] -> [(a, b)]
+        builtin("zip", 2, zip, func(list(a()), func(list(b()), list(Typ::Tuple(vec![a(), b()]))))),
+
+        // sum: [Int] -> Int
+        builtin("sum", 1, sum, func(list(Typ::Int), Typ::Int)),
+
+        // sumFloat: [Float] -> Float
+        builtin("sumFloat", 1, sum_float, func(list(Typ::Float), Typ::Float)),
+
+        // concat: [[a]] -> [a]
+        builtin("concat", 1, concat, func(list(list(a())), list(a()))),
+    ]
+}
+
+fn builtin(name: &'static str, arity: usize, f: NativeFn, typ: Typ) -> Builtin {
+    Builtin {
+        native: Native{name, arity, func: f},
+        typ,
+    }
+}
+
+fn list(elem: Typ) -> Typ {
+    Typ::List(Box::new(elem))
+}
+
+fn func(param: Typ, ret: Typ) -> Typ {
+    Typ::Func(Box::new(param), Box::new(ret))
+}
+
+// Print a value on its own line. Each element of a list is printed on its
+// own line, so `print <- ["a", "b"]` prints `a` and then `b`.
+fn print(args: &[Value]) -> Result<Value, RuntimeError> {
+    match &args[0] {
+        Value::List(list) => {
+            let mut list = list.clone();
+            while let Some((head, tail)) = list.uncons()? {
+                println!("{}", head.to_text()?);
+                list = tail;
+            }
+        },
+        value => println!("{}", value.to_text()?),
+    }
+
+    Ok(Value::unit())
+}
+
+fn prompt(_: &[Value]) -> Result<Value, RuntimeError> {
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)
+        .map_err(|err| RuntimeError::new(format!("Unable to read input: {}.", err)))?;
+
+    let line = input.trim_end_matches('\n').trim_end_matches('\r');
+    Ok(Value::string(line))
+}
+
+fn no_args(_: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::List(List::from_vec(vec![])))
+}
+
+fn to_string(args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::string(&args[0].to_text()?))
+}
+
+fn map(args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::List(map_list(expect_list(&args[0])?, args[1].clone())))
+}
+
+fn map_list(list: List, f: Value) -> List {
+    List::lazy(move || match list.uncons()? {
+        Some((head, tail)) => Ok(Some((call(f.clone(), head)?, map_list(tail, f)))),
+        None => Ok(None),
+    })
+}
+
+fn filter(args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::List(filter_list(expect_list(&args[0])?, args[1].clone())))
+}
+
+fn filter_list(list: List, f: Value) -> List {
+    List::lazy(move || {
+        let mut list = list;
+        while let Some((head, tail)) = list.uncons()? {
+            if let Value::Bool(true) = call(f.clone(), head.clone())? {
+                return Ok(Some((head, filter_list(tail, f))));
+            }
+            list = tail;
+        }
+
+        Ok(None)
+    })
+}
+
+fn foldl(args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut acc = args[1].clone();
+    for value in expect_list(&args[0])?.to_vec()? {
+        acc = call(call(args[2].clone(), acc)?, value)?;
+    }
+
+    Ok(acc)
+}
+
+fn foldr(args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut acc = args[1].clone();
+    for value in expect_list(&args[0])?.to_vec()?.into_iter().rev() {
+        acc = call(call(args[2].clone(), value)?, acc)?;
+    }
+
+    Ok(acc)
+}
+
+fn length(args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut list = expect_list(&args[0])?;
+    let mut length = 0;
+
+    while let Some((_, tail)) = list.uncons()? {
+        length += 1;
+        list = tail;
+    }
+
+    Ok(Value::Int(Int::from(length)))
+}
+
+fn head(args: &[Value]) -> Result<Value, RuntimeError> {
+    match expect_list(&args[0])?.uncons()? {
+        Some((head, _)) => Ok(head),
+        None => Err(RuntimeError::new("Can't take the head of an empty list.".to_string())),
+    }
+}
+
+fn tail(args: &[Value]) -> Result<Value, RuntimeError> {
+    match expect_list(&args[0])?.uncons()? {
+        Some((_, tail)) => Ok(Value::List(tail)),
+        None => Err(RuntimeError::new("Can't take the tail of an empty list.".to_string())),
+    }
+}
+
+fn reverse(args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut values = expect_list(&args[0])?.to_vec()?;
+    values.reverse();
+    Ok(Value::List(List::from_vec(values)))
+}
+
+fn zip(args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::List(zip_lists(expect_list(&args[0])?, expect_list(&args[1])?)))
+}
+
+fn zip_lists(a: List, b: List) -> List {
+    List::lazy(move || match (a.uncons()?, b.uncons()?) {
+        (Some((x, xs)), Some((y, ys))) => {
+            let pair = Value::Tuple(Rc::new(vec![x, y]));
+            Ok(Some((pair, zip_lists(xs, ys))))
+        },
+        _ => Ok(None),
+    })
+}
+
+fn sum(args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut total = Int::from(0);
+    for value in expect_list(&args[0])?.to_vec()? {
+        match value {
+            Value::Int(i) => total = total.add(&i),
+            value => return Err(RuntimeError::new(format!("Can't add {}.", value.show()?))),
+        }
+    }
+
+    Ok(Value::Int(total))
+}
+
+fn sum_float(args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut total = 0.0;
+    for value in expect_list(&args[0])?.to_vec()? {
+        match value {
+            Value::Float(f) => total += f,
+            value => return Err(RuntimeError::new(format!("Can't add {}.", value.show()?))),
+        }
+    }
+
+    Ok(Value::Float(total))
+}
+
+fn concat(args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::List(concat_lists(expect_list(&args[0])?)))
+}
+
+fn concat_lists(lists: List) -> List {
+    List::lazy(move || match lists.uncons()? {
+        Some((first, rest)) => expect_list(&first)?.append(concat_lists(rest)).uncons(),
+        None => Ok(None),
+    })
+}
+
+fn expect_list(value: &Value) -> Result<List, RuntimeError> {
+    match value {
+        Value::List(list) => Ok(list.clone()),
+        _ => Err(RuntimeError::new(format!("Expected a list, found {}.", value.show()?))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checker::Checker;
+    use crate::error::Report;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::span::FileId;
+
+    // Check and evaluate an expression, showing its value or giving back
+    // the message of the error that stopped it.
+    fn eval(source: &str) -> Result<String, String> {
+        let (mut ast, errors) = Parser::new(source.to_string(), FileId::default()).parse_all();
+        assert!(errors.is_empty(), "{:?} doesn't parse", source);
+        assert!(Checker::new().check(&mut ast).is_empty(), "{} doesn't type check", source);
+
+        let value = Interpreter::new(vec![]).run(&ast[0]).map_err(|err| err.diagnostic().message)?;
+        value.expect("Expected an evaluated declaration.").show().map_err(|err| err.diagnostic().message)
+    }
+
+    fn ok(value: &str) -> Result<String, String> {
+        Ok(value.to_string())
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(eval("[1..5] -> map <- {x | x * 2}"), ok("[2, 4, 6, 8, 10]"));
+        assert_eq!(eval("[1..10] -> filter <- {x | x % 3 == 0}"), ok("[3, 6, 9]"));
+        assert_eq!(eval("([1..4] -> foldl <- 0) <- {acc x | acc * 10 + x}"), ok("1234"));
+        assert_eq!(eval("([1..4] -> foldr <- 0) <- {x acc | acc * 10 + x}"), ok("4321"));
+        assert_eq!(eval("[\"a\", \"b\", \"c\"] -> length"), ok("3"));
+        assert_eq!(eval("[1..3] -> reverse"), ok("[3, 2, 1]"));
+        assert_eq!(eval("[[1, 2], [], [3]] -> concat"), ok("[1, 2, 3]"));
+        assert_eq!(eval("[1..3] -> zip <- [\"a\", \"b\"]"), ok(r#"[(1, "a"), (2, "b")]"#));
+        assert_eq!(eval("(1, [true]) -> toString"), ok(r#""(1, [true])""#));
+    }
+
+    #[test]
+    fn head_and_tail() {
+        assert_eq!(eval("[1..3] -> head"), ok("1"));
+        assert_eq!(eval("[1..3] -> tail"), ok("[2, 3]"));
+        assert_eq!(eval("[] -> head"), Err("Can't take the head of an empty list.".to_string()));
+        assert_eq!(eval("[] -> tail"), Err("Can't take the tail of an empty list.".to_string()));
+    }
+
+    #[test]
+    fn sum() {
+        assert_eq!(eval("[1..100] -> sum"), ok("5050"));
+        assert_eq!(eval("[] -> sum"), ok("0"));
+        assert_eq!(eval("([1..5] -> filter <- {x | x > 5}) -> sum"), ok("0"));
+        assert_eq!(eval("[0.5, 1.5, 2.25] -> sumFloat"), ok("4.25"));
+        assert_eq!(eval("[] -> sumFloat"), ok("0.0"));
+    }
+
+    #[test]
+    fn list_functions_work_on_infinite_lists() {
+        assert_eq!(eval("([1..] -> map <- {x | x * x}) -> head"), ok("1"));
+        assert_eq!(eval("(([1..] -> filter <- {x | x > 10}) -> tail) -> head"), ok("12"));
+        assert_eq!(eval("[1..] -> zip <- [\"a\"]"), ok(r#"[(1, "a")]"#));
+        assert_eq!(eval("([[1], [2..]] -> concat) -> head"), ok("1"));
+    }
+}
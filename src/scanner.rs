@@ -1,4 +1,10 @@
 use crate::error::Report;
+use crate::diagnostic::{Diagnostic, Label};
+use crate::ast::Literal;
+use crate::int::Int;
+use crate::span::{FileId, Span};
+
+use std::mem;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TokenKind {
@@ -38,7 +44,8 @@ pub enum TokenKind {
     RightArrow,     // '->'
 
     // 2 character
-    BangEqual,
+    BangEqual,      // '!='
+    DotDot,         // '..'
 
     // Keywords
     And,            // 'and'
@@ -46,63 +53,171 @@ pub enum TokenKind {
     Not,            // 'not'
     True,           // 'true'
     False,          // 'false'
+    Else,           // 'else'
+    Import,         // 'import'
+
+    // Trivia, which is only scanned when asked for, see `Scanner::with_trivia`
+    Whitespace,
+    Comment,
 
     // Other
     Number,
     String,
+
+    // A string with holes in it, e.g. "Hello {name}!". Its parts are in
+    // `Token::parts`.
+    InterpolatedString,
     Identifier,
     Newline,
 
+    // The module named by an import, e.g. 'List' or './util'
+    Path,
+
+    // Stands in for some source that the scanner couldn't make sense of
+    Error,
+
     None,
     Eof,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
-    pub line: u32,
+
+    // The column that the token starts at, counting from 1, so that the
+    // parser can tell which tokens start a line
     pub col: u32,
+
     pub lexeme: String,
+
+    // The source that the token was scanned from, including the quotes
+    // around a string
+    pub span: Span,
+
+    // The text and holes of an interpolated string. Empty for any other
+    // kind of token.
+    pub parts: Vec<StringPart>,
+}
+
+// A piece of an interpolated string, e.g. "Hello {name}!" is the text
+// `Hello `, the hole `name` and the text `!`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    // Some text, with its escapes worked out
+    Text(String),
+
+    // The source of the expression in a hole, to be parsed by the parser.
+    // `col` is the column just before it, i.e. of the `{`.
+    Hole { source: String, span: Span, col: u32 },
+}
+
+impl Token {
+    // A placeholder token that doesn't come from the source, e.g. the
+    // type name of a declaration that was left out.
+    pub fn none() -> Token {
+        Token { kind: TokenKind::None, col: 0, lexeme: String::new(), span: Span::default(), parts: vec![] }
+    }
 }
 
 pub struct Scanner {
     pub source: Vec<char>,
+    file: FileId,
 
+    // Indices into `source` of the start of the token being scanned, and
+    // of the next character
     start: usize,
     current: usize,
 
-    line: u32,
+    // The byte offsets of `start` and `current`, for spans
+    start_byte: usize,
+    current_byte: usize,
+
     col: u32,
+
+    // The column that the token being scanned starts at
+    start_col: u32,
+
+    // Whether the last token was 'import', so that the next one is a path
+    after_import: bool,
+
+    // The index into `source` just after the last number scanned, so that
+    // a '.' right after one isn't taken for the start of e.g. `.5`
+    number_end: Option<usize>,
+
+    // Whether whitespace and comments are given back as tokens, rather
+    // than skipped
+    trivia: bool,
 }
 
 impl Scanner {
-    pub fn new(source: String) -> Scanner {
+    pub fn new(source: String, file: FileId) -> Scanner {
+        Scanner::at(source, file, 0, 0)
+    }
+
+    // A scanner for source that is part of a bigger file, e.g. a hole in
+    // an interpolated string, starting at byte `offset` and just after the
+    // column `col`, so that spans point into the file.
+    pub fn at(source: String, file: FileId, offset: usize, col: u32) -> Scanner {
         Scanner {
             source: source.chars().collect(),
+            file,
             start: 0,
             current: 0,
-            line: 1,
-            col: 0,
+            start_byte: offset,
+            current_byte: offset,
+            col,
+            start_col: 1,
+            after_import: false,
+            number_end: None,
+            trivia: false,
         }
     }
 
+    // Give back whitespace and comments as tokens too, so that the tokens
+    // cover every byte of the source.
+    pub fn with_trivia(self) -> Scanner {
+        Scanner { trivia: true, ..self }
+    }
+
     // Scan a single token from 'source'. 
     // Returns ScannerError on failure, due to:
     // - unrecognised character
     // - unrecognised escape sequence
     // - unterminated string literal
+    // - malformed number literal
     pub fn scan_token(&mut self) -> Result<Token, ScannerError> {
-        self.skip_whitespace();
+        if !self.trivia {
+            while self.scan_trivia().is_some() {}
+        }
+
         self.start = self.current;
+        self.start_byte = self.current_byte;
+        self.start_col = self.col + 1;
+
+        if self.trivia {
+            if let Some(kind) = self.scan_trivia() {
+                return Ok(self.make_token(kind));
+            }
+        }
 
         if self.is_at_end() {
             return Ok(self.make_token(TokenKind::Eof));
         }
 
+        // A path isn't made of the usual tokens, e.g. './util' would
+        // otherwise be a rogue '.' then a '/'.
+        if mem::take(&mut self.after_import) && self.peek() != '\n' {
+            return Ok(self.scan_path());
+        }
+
         let c = self.advance();
 
-        if c.is_digit(10) {
-            return Ok(self.scan_number());
+        if c.is_ascii_digit() {
+            return self.scan_number();
+        }
+
+        if c == 'r' && self.at_raw_string() {
+            return self.scan_raw_string();
         }
 
         if is_identifier_start(c) {
@@ -126,11 +241,33 @@ impl Scanner {
             '|' => Ok(self.make_token(TokenKind::Pipe)),
             '?' => Ok(self.make_token(TokenKind::Question)),
 
-            '"' => self.scan_string(),
+            '"' => {
+                let triple = self.peek() == '"' && self.peek_next() == '"';
+                if triple {
+                    self.advance();
+                    self.advance();
+                }
+                self.scan_string(triple)
+            },
+
+            '.' => {
+                if self.consume('.') {
+                    Ok(self.make_token(TokenKind::DotDot))
+                } else if self.peek().is_ascii_digit() && self.number_end != Some(self.start) {
+                    // Someone wrote e.g. `.5`, so take the digits for the
+                    // error and suggest `0.5` instead.
+                    self.scan_digits();
+                    let digits: String = self.source[self.start + 1..self.current].iter().collect();
+                    Err(self.make_error("Expected a digit before the '.' of a number.".to_string())
+                        .with_help(format!("Write `0.{}` instead.", digits)))
+                } else {
+                    Err(self.make_error(format!("Rogue unrecognised character '{}'.", c)))
+                }
+            },
 
             '!' => {
                 if self.consume('=') {
-                    Ok(self.make_token(TokenKind::Question))
+                    Ok(self.make_token(TokenKind::BangEqual))
                 } else {
                     Err(self.make_error(format!("Rogue unrecognised character '{}'.", c)))
                 }
@@ -167,55 +304,120 @@ impl Scanner {
                 Ok(self.make_token(t))
             },
 
-            '\n' => {
-                let token = self.make_token(TokenKind::Newline);
+            // Blank lines give a newline each, which the parser skips over
+            '\n' => Ok(self.make_token(TokenKind::Newline)),
+
+            _ => Err(self.make_error(format!("Rogue unrecognised character '{}'.", c))),
+        }
+    }
 
-                self.col = 0;
-                self.line += 1;
+    // Scan a run of whitespace or a comment, if that's what comes next.
+    fn scan_trivia(&mut self) -> Option<TokenKind> {
+        match self.peek() {
+            ' ' | '\r' | '\t' => {
+                while matches!(self.peek(), ' ' | '\r' | '\t') {
+                    self.advance();
+                }
+                Some(TokenKind::Whitespace)
+            },
 
-                while !self.is_at_end() && self.consume('\n') {
-                    self.line += 1;
+            '#' => {
+                while !self.is_at_end() && self.peek() != '\n' {
+                    self.advance();
                 }
-                
-                Ok(token)
+                Some(TokenKind::Comment)
             },
 
-            _ => Err(self.make_error(format!("Rogue unrecognised character '{}'.", c))),
+            _ => None,
         }
     }
 
-    fn skip_whitespace(&mut self) {
-        while !self.is_at_end() {
-            match self.peek() {
-                ' ' | '\r' | '\t' => {
+    // Scan a number literal, e.g. `3`, `2.40`, `1_000`, `1.5e-3`, or an
+    // integer in another base, e.g. `0xFF`, `0b1010` or `0o17`. The literal
+    // is checked here, so that the parser can be sure it has a value.
+    fn scan_number(&mut self) -> Result<Token, ScannerError> {
+        let base = match (self.source[self.start], self.peek()) {
+            ('0', 'x') | ('0', 'X') => Some((16, "hexadecimal")),
+            ('0', 'o') | ('0', 'O') => Some((8, "octal")),
+            ('0', 'b') | ('0', 'B') => Some((2, "binary")),
+            _ => None,
+        };
+
+        if let Some((radix, name)) = base {
+            self.advance();
+
+            // Take any letters along with the digits, so that a digit that
+            // is wrong for the base is part of the error.
+            while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
+                self.advance();
+            }
+
+            let digits: String = self.source[self.start + 2..self.current].iter().collect();
+            if digits.is_empty() {
+                let prefix: String = self.source[self.start..self.current].iter().collect();
+                return Err(self.make_error(format!("Expected {} digits after '{}'.", name, prefix)));
+            }
+
+            if let Some(bad) = digits.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+                return Err(self.make_error(format!("'{}' isn't a digit in {}.", bad, name)));
+            }
+
+            // Take the fraction for the error, e.g. `0xFF.5`
+            if self.peek() == '.' && self.peek_next().is_ascii_alphanumeric() {
+                self.advance();
+                while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
                     self.advance();
-                },
+                }
 
-                '#' => {
-                    while self.peek() != '\n' {
+                let mut name = name.to_string();
+                name[..1].make_ascii_uppercase();
+                return Err(self.make_error(format!("{} numbers can't have a fraction.", name)));
+            }
+        } else {
+            self.scan_digits();
+
+            if self.peek() == '.' && self.peek_next().is_ascii_digit() {
+                self.advance();
+                self.scan_digits();
+            }
+
+            // An exponent, e.g. `e10` or `e-3`. Without any digits after it,
+            // the `e` isn't part of the number.
+            if matches!(self.peek(), 'e' | 'E') {
+                let signed = matches!(self.peek_next(), '+' | '-');
+                let digit = self.source.get(self.current + 1 + signed as usize).copied().unwrap_or('\0');
+
+                if digit.is_ascii_digit() {
+                    self.advance();
+                    if signed {
                         self.advance();
                     }
+                    self.scan_digits();
                 }
-
-                _ => {return;}
             }
         }
-    }
 
-    // Scan a number literal, e.g. '2.40' or '3'.
-    fn scan_number(&mut self) -> Token {
-        while self.peek().is_digit(10) {
-            self.advance();
+        let lexeme: String = self.source[self.start..self.current].iter().collect();
+        let misplaced_separator = digit_groups(&lexeme).iter()
+            .any(|group| group.starts_with('_') || group.ends_with('_') || group.contains("__"));
+
+        if misplaced_separator {
+            return Err(self.make_error("A '_' in a number must go between two digits.".to_string()));
         }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
-            self.advance();
-            while self.peek().is_digit(10) {
-                self.advance();
-            }
+        self.number_end = Some(self.current);
+
+        match number_literal(&lexeme) {
+            Ok(_) => Ok(self.make_token(TokenKind::Number)),
+            Err(msg) => Err(self.make_error(msg)),
         }
+    }
 
-        self.make_token(TokenKind::Number)
+    // Scan decimal digits and the `_`s that may separate them.
+    fn scan_digits(&mut self) {
+        while self.peek().is_ascii_digit() || self.peek() == '_' {
+            self.advance();
+        }
     }
 
     // Scan either an identifier or a keyword.
@@ -226,36 +428,91 @@ impl Scanner {
 
         let lexeme: String = self.source[self.start..self.current].iter().collect();
         
-        self.make_token(match &lexeme[..] {
+        let kind = match &lexeme[..] {
             "and" => TokenKind::And,
             "or" => TokenKind::Or,
             "not" => TokenKind::Not,
             "true" => TokenKind::True,
             "false" => TokenKind::False,
+            "else" => TokenKind::Else,
+            "import" => {
+                self.after_import = true;
+                TokenKind::Import
+            },
             _ => TokenKind::Identifier,
-        })
+        };
+
+        self.make_token(kind)
+    }
+
+    // Scan the path of an import, which goes up to the next whitespace or
+    // comment.
+    fn scan_path(&mut self) -> Token {
+        while !self.is_at_end() && !matches!(self.peek(), ' ' | '\r' | '\t' | '\n' | '#') {
+            self.advance();
+        }
+
+        self.make_token(TokenKind::Path)
     }
 
-    // Scan a string literal.
+    // Scan a string literal, which may have holes in it, e.g.
+    // "Hello {name}!". A literal '{' is written `{{`.
+    //
+    // A triple-quoted string, e.g. `"""..."""`, may go over several lines.
+    // The indentation that all of its lines share is stripped, as are the
+    // newline after the opening quotes and the line that the closing
+    // quotes are on, if there is nothing else on either of them.
+    //
     // This may fail, in which case a ScannerError will be returned, due to:
     // - an unrecognised escape sequence
     // - an unterminated string literal
-    fn scan_string(&mut self) -> Result<Token, ScannerError> {
+    // - an empty or unterminated hole
+    fn scan_string(&mut self, triple: bool) -> Result<Token, ScannerError> {
         let mut lexeme = String::new();
+        let mut parts = vec![];
+
+        // The first error in the string. The rest of the string is still
+        // scanned, so that scanning carries on after it.
+        let mut error = None;
+
+        let (indent, closes_alone) = if triple { self.triple_quoted_layout() } else { (0, false) };
+        let mut last_line_start = 0;
+        if triple && self.consume('\n') {
+            self.skip_indent(indent);
+        }
 
-        while !self.is_at_end() && self.peek() != '"' {
+        while !self.is_at_end() && !self.at_closing_quotes(triple) {
             let c = self.advance();
 
             // Check for escape sequences
             if c == '\\' {
-                let escape = self.advance();
-                match escape {
-                    'n' => lexeme.push('\n'),
-                    't' => lexeme.push('\t'),
-                    'r' => lexeme.push('\r'),
-                    '"' => lexeme.push('"'),
-                    _ => return Err(self.make_error(format!("Unrecognised escape sequence '\\{}'.", escape))),
+                if self.is_at_end() {
+                    break;
                 }
+
+                match self.scan_escape() {
+                    Ok(c) => lexeme.push(c),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    },
+                }
+            } else if c == '{' && self.consume('{') {
+                lexeme.push('{');
+            } else if c == '{' {
+                parts.push(StringPart::Text(mem::take(&mut lexeme)));
+                match self.scan_hole() {
+                    Ok(hole) => parts.push(hole),
+                    // An unterminated hole runs to the end of the source,
+                    // which says more than the string being unterminated.
+                    Err(err) if self.is_at_end() => return Err(err),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    },
+                }
+            } else if c == '\n' && triple {
+                lexeme.push(c);
+                last_line_start = lexeme.len();
+                self.skip_indent(indent);
             } else {
                 lexeme.push(c);
             }
@@ -265,48 +522,244 @@ impl Scanner {
             return Err(self.make_error("Unterminated string literal.".to_string()));
         }
 
+        for _ in 0..if triple { 3 } else { 1 } {
+            self.advance();
+        }
+
+        // The error token is all of the string, so that scanning carries on
+        // after it, but the error still points at what was wrong.
+        if let Some(err) = error {
+            return Err(ScannerError { token: Box::new(self.make_token(TokenKind::Error)), ..err });
+        }
+
+        // Leave out the line the closing quotes are on, along with the
+        // newline before it.
+        if closes_alone {
+            lexeme.truncate(last_line_start.saturating_sub(1));
+        }
+
+        if parts.is_empty() {
+            return Ok(Token {
+                lexeme,
+                ..self.make_token(TokenKind::String)
+            });
+        }
+
+        parts.push(StringPart::Text(lexeme));
+        Ok(Token {
+            parts,
+            ..self.make_token(TokenKind::InterpolatedString)
+        })
+    }
+
+    fn at_closing_quotes(&self, triple: bool) -> bool {
+        let quotes = if triple { 3 } else { 1 };
+        (0..quotes).all(|i| self.source.get(self.current + i) == Some(&'"'))
+    }
+
+    // Look ahead through a triple-quoted string to work out how much
+    // indentation to strip from its lines, and whether its closing quotes
+    // are on a line of their own.
+    fn triple_quoted_layout(&self) -> (usize, bool) {
+        let mut end = self.current;
+        while end < self.source.len() && !self.source[end..].starts_with(&['"', '"', '"']) {
+            end += if self.source[end] == '\\' { 2 } else { 1 };
+        }
+
+        let body: String = self.source[self.current..end.min(self.source.len())].iter().collect();
+        let lines: Vec<&str> = body.split('\n').collect();
+        if lines.len() < 2 {
+            return (0, false);
+        }
+
+        let is_blank = |line: &str| line.chars().all(|c| c == ' ' || c == '\t');
+        let indent_of = |line: &str| line.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+
+        let last = lines[lines.len() - 1];
+        let closes_alone = is_blank(last);
+
+        let indent = lines[1..lines.len() - 1].iter()
+            .filter(|line| !is_blank(line))
+            .map(|line| indent_of(line))
+            .chain(Some(indent_of(last)))
+            .min()
+            .unwrap_or(0);
+
+        (indent, closes_alone)
+    }
+
+    // Skip up to `indent` spaces or tabs at the start of a line.
+    fn skip_indent(&mut self, indent: usize) {
+        for _ in 0..indent {
+            if !matches!(self.peek(), ' ' | '\t') {
+                break;
+            }
+            self.advance();
+        }
+    }
+
+    // Scan the rest of an escape sequence, after the backslash, giving back
+    // the character that it stands for.
+    fn scan_escape(&mut self) -> Result<char, ScannerError> {
+        let escape = self.advance();
+        match escape {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+
+            // A unicode escape, e.g. `\u{1F600}`
+            'u' => {
+                if !self.consume('{') {
+                    return Err(self.make_error("Expected '{' after '\\u'.".to_string())
+                        .with_help("Write a unicode escape like `\\u{1F600}`.".to_string()));
+                }
+
+                let mut digits = String::new();
+                while self.peek().is_ascii_hexdigit() {
+                    digits.push(self.advance());
+                }
+
+                if !self.consume('}') || digits.is_empty() || digits.len() > 6 {
+                    return Err(self.make_error("A unicode escape must have 1 to 6 hexadecimal digits inside of '{}'.".to_string())
+                        .with_help("Write a unicode escape like `\\u{1F600}`.".to_string()));
+                }
+
+                u32::from_str_radix(&digits, 16).ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.make_error(format!("'\\u{{{}}}' isn't a unicode character.", digits)))
+            },
+
+            _ => Err(self.make_error(format!("Unrecognised escape sequence '\\{}'.", escape))),
+        }
+    }
+
+    // Whether an `r` that has just been scanned starts a raw string, i.e.
+    // is followed by any number of `#`s and then a `"`.
+    fn at_raw_string(&self) -> bool {
+        let hashes = self.source[self.current..].iter().take_while(|c| **c == '#').count();
+        self.source.get(self.current + hashes) == Some(&'"')
+    }
+
+    // Scan a raw string, e.g. `r"C:\path"` or `r#"say "hi""#`, which has no
+    // escape sequences or holes. It ends at a `"` followed by as many `#`s
+    // as it started with, so that it can have `"`s in it.
+    fn scan_raw_string(&mut self) -> Result<Token, ScannerError> {
+        let mut hashes = 0;
+        while self.consume('#') {
+            hashes += 1;
+        }
         self.advance();
+
+        let start = self.current;
+        loop {
+            if self.is_at_end() {
+                return Err(self.make_error("Unterminated raw string literal.".to_string()));
+            }
+
+            if self.advance() == '"' {
+                let closing = self.source[self.current..].iter().take(hashes).filter(|c| **c == '#').count();
+                if closing == hashes {
+                    for _ in 0..hashes {
+                        self.advance();
+                    }
+                    break;
+                }
+            }
+        }
+
         Ok(Token {
-            kind: TokenKind::String,
-            line: self.line,
-            col: self.col,
-            lexeme,
+            lexeme: self.source[start..self.current - hashes - 1].iter().collect(),
+            ..self.make_token(TokenKind::String)
         })
     }
 
+    // Scan the source of a hole in a string, up to the `}` that closes it.
+    // Braces inside of the hole, e.g. of a function, are matched up, and
+    // strings inside of it are skipped over.
+    fn scan_hole(&mut self) -> Result<StringPart, ScannerError> {
+        let (start, start_byte, col) = (self.current, self.current_byte, self.col);
+        let mut depth = 0;
+
+        loop {
+            if self.is_at_end() {
+                return Err(self.make_error("Unterminated '{' in string.".to_string())
+                    .with_help("Write `{{` for a literal '{'.".to_string()));
+            }
+
+            match self.advance() {
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                '"' => {
+                    while !self.is_at_end() && self.peek() != '"' {
+                        if self.advance() == '\\' && !self.is_at_end() {
+                            self.advance();
+                        }
+                    }
+                    self.consume('"');
+                },
+                _ => {},
+            }
+        }
+
+        let source: String = self.source[start..self.current - 1].iter().collect();
+        if source.trim().is_empty() {
+            return Err(self.make_error("Expected an expression between '{' and '}' in string.".to_string())
+                .with_help("Write `{{` for a literal '{'.".to_string()));
+        }
+
+        let span = Span::new(self.file, start_byte, self.current_byte - 1);
+        Ok(StringPart::Hole { source, span, col })
+    }
+
     // Create a new Token of the specified type at the current position
     fn make_token(&self, kind: TokenKind) -> Token {
         Token {
             kind,
-            line: self.line,
-            col: self.col,
+            col: self.start_col,
             lexeme: self.source[self.start..self.current].iter().collect(),
+            span: Span::new(self.file, self.start_byte, self.current_byte),
+            parts: vec![],
         }
     }
 
-    // Create a new ScannerError at the current position
+    // Create a new ScannerError covering the source scanned so far for
+    // the current token.
     fn make_error(&self, msg: String) -> ScannerError {
+        let token = self.make_token(TokenKind::Error);
         ScannerError {
             msg,
-            place: self.source[self.current],
-            line: self.line,
-            col: self.col,
+            span: token.span,
+            token: Box::new(token),
+            help: None,
         }
     }
 
     fn advance(&mut self) -> char {
         let c = self.source[self.current];
         self.current += 1;
-        self.col += 1;
+        self.current_byte += c.len_utf8();
+
+        // Newlines inside of a string start a new line too
+        if c == '\n' {
+            self.col = 0;
+        } else {
+            self.col += 1;
+        }
+
         c
     }
 
+    // Look at the current character, or '\0' at the end of the source.
     fn peek(&self) -> char {
-        self.source[self.current]
+        self.source.get(self.current).copied().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        self.source[self.current + 1]
+        self.source.get(self.current + 1).copied().unwrap_or('\0')
     }
 
     fn consume(&mut self, expected: char) -> bool {
@@ -328,27 +781,271 @@ fn is_identifier_start(c: char) -> bool {
 }
 
 fn is_identifier_body(c: char) -> bool {
-    return is_identifier_start(c) || c.is_digit(10)
+    is_identifier_start(c) || c.is_ascii_digit()
+}
+
+// Split the prefix off of an integer in another base, e.g. `0xFF` gives
+// 16 and `FF`.
+fn split_radix(lexeme: &str) -> Option<(u32, &str)> {
+    let radix = match lexeme.get(..2)? {
+        "0x" | "0X" => 16,
+        "0o" | "0O" => 8,
+        "0b" | "0B" => 2,
+        _ => return None,
+    };
+
+    Some((radix, &lexeme[2..]))
 }
 
-#[derive(Debug)]
+// The runs of digits in a number literal, i.e. the parts between its
+// prefix, point and exponent.
+fn digit_groups(lexeme: &str) -> Vec<&str> {
+    match split_radix(lexeme) {
+        Some((_, digits)) => vec![digits],
+        None => lexeme.split(['.', 'e', 'E', '+', '-']).collect(),
+    }
+}
+
+// Work out the value of a number literal. A literal with a point or an
+// exponent is a Float, and anything else is an Int.
+pub fn number_literal(lexeme: &str) -> Result<Literal, String> {
+    let digits = lexeme.replace('_', "");
+    let malformed = || format!("Malformed number literal '{}'.", lexeme);
+
+    if let Some((radix, digits)) = split_radix(&digits) {
+        return Int::parse_radix(digits, radix).map(Literal::Int).ok_or_else(malformed);
+    }
+
+    if digits.contains(['.', 'e', 'E']) {
+        match digits.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(Literal::Float(x)),
+            Ok(_) => Err(format!("'{}' is too large for a Float.", lexeme)),
+            Err(_) => Err(malformed()),
+        }
+    } else {
+        Int::parse(&digits).map(Literal::Int).ok_or_else(malformed)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ScannerError {
     msg: String,
-    place: char,
-    line: u32,
-    col: u32,
+
+    // An `Error` token for the source that couldn't be scanned
+    token: Box<Token>,
+
+    // Where in the token it went wrong, e.g. a bad escape in a string
+    span: Span,
+
+    // How the source might be fixed
+    help: Option<String>,
 }
 
-impl Report for ScannerError {
-    fn position(&self) -> (u32, u32) {
-        (self.line, self.col)
+impl ScannerError {
+    // An error token for the parser to stand in place of the source that
+    // couldn't be scanned.
+    pub fn token(&self) -> Token {
+        (*self.token).clone()
     }
 
-    fn place(&self) -> String {
-        format!(" at '{}'", self.place)
+    // Where in the error token it went wrong
+    pub fn span(&self) -> Span {
+        self.span
     }
 
-    fn message(&self) -> &String {
+    pub fn message(&self) -> &str {
         &(self.msg)
     }
-}
\ No newline at end of file
+
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    fn with_help(self, help: String) -> ScannerError {
+        ScannerError { help: Some(help), ..self }
+    }
+}
+
+impl Report for ScannerError {
+    fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error("E0001", self.msg.clone())
+            .with_label(Label::primary(self.span, ""));
+
+        match &self.help {
+            Some(help) => diagnostic.with_help(help.clone()),
+            None => diagnostic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scan a single number literal, giving back its value or the message
+    // and help of the error.
+    fn number(source: &str) -> Result<String, (String, Option<String>)> {
+        let mut scanner = Scanner::new(source.to_string(), FileId::default());
+        let token = scanner.scan_token().map_err(|err| (err.msg, err.help))?;
+        assert_eq!(token.lexeme, source, "{:?} isn't scanned as one token", source);
+
+        match number_literal(&token.lexeme) {
+            Ok(Literal::Int(i)) => Ok(i.to_string()),
+            Ok(Literal::Float(x)) => Ok(format!("{:?}", x)),
+            Ok(literal) => panic!("Expected a number, found {:?}", literal),
+            Err(msg) => panic!("{:?} was scanned, but has no value: {}", source, msg),
+        }
+    }
+
+    fn error(source: &str) -> String {
+        number(source).expect_err("Expected an error.").0
+    }
+
+    #[test]
+    fn bases() {
+        assert_eq!(number("0xFF"), Ok("255".to_string()));
+        assert_eq!(number("0Xff"), Ok("255".to_string()));
+        assert_eq!(number("0o17"), Ok("15".to_string()));
+        assert_eq!(number("0b1010"), Ok("10".to_string()));
+        assert_eq!(number("0x1_0000_0000_0000_0000"), Ok("18446744073709551616".to_string()));
+    }
+
+    #[test]
+    fn separators_and_exponents() {
+        assert_eq!(number("1_000_000"), Ok("1000000".to_string()));
+        assert_eq!(number("0b1111_0000"), Ok("240".to_string()));
+        assert_eq!(number("3.141_592"), Ok("3.141592".to_string()));
+        assert_eq!(number("1e3"), Ok("1000.0".to_string()));
+        assert_eq!(number("1.5E-3"), Ok("0.0015".to_string()));
+        assert_eq!(number("2e+2"), Ok("200.0".to_string()));
+        assert_eq!(number("99999999999999999999"), Ok("99999999999999999999".to_string()));
+    }
+
+    #[test]
+    fn an_e_without_digits_is_not_an_exponent() {
+        let mut scanner = Scanner::new("2e".to_string(), FileId::default());
+        assert_eq!(scanner.scan_token().unwrap().lexeme, "2");
+        assert_eq!(scanner.scan_token().unwrap().kind, TokenKind::Identifier);
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        assert_eq!(error("0x"), "Expected hexadecimal digits after '0x'.");
+        assert_eq!(error("0b102"), "'2' isn't a digit in binary.");
+        assert_eq!(error("0o8"), "'8' isn't a digit in octal.");
+        assert_eq!(error("0xFG"), "'G' isn't a digit in hexadecimal.");
+        assert_eq!(error("1e400"), "'1e400' is too large for a Float.");
+
+        assert_eq!(number(".5"), Err((
+            "Expected a digit before the '.' of a number.".to_string(),
+            Some("Write `0.5` instead.".to_string()),
+        )));
+    }
+
+    #[test]
+    fn fractions_after_a_number_are_not_leading_dots() {
+        assert_eq!(number("0xFF.5"), Err(("Hexadecimal numbers can't have a fraction.".to_string(), None)));
+        assert_eq!(error("0b1.1"), "Binary numbers can't have a fraction.");
+        assert_eq!(error("0o7.A"), "Octal numbers can't have a fraction.");
+
+        let mut scanner = Scanner::new("1.2.3".to_string(), FileId::default());
+        assert_eq!(scanner.scan_token().unwrap().lexeme, "1.2");
+        let err = scanner.scan_token().unwrap_err();
+        assert_eq!(err.msg, "Rogue unrecognised character '.'.");
+        assert_eq!(err.help, None);
+    }
+
+    #[test]
+    fn misplaced_separators() {
+        for source in ["1__000", "1_", "1_.5", "1_e3", "0x_FF"] {
+            assert_eq!(error(source), "A '_' in a number must go between two digits.", "{:?}", source);
+        }
+    }
+
+    // The text and hole sources of a single interpolated string, or the
+    // message of its error.
+    fn parts(source: &str) -> Result<Vec<String>, String> {
+        let mut scanner = Scanner::new(source.to_string(), FileId::default());
+        let token = scanner.scan_token().map_err(|err| err.msg)?;
+        assert_eq!(token.kind, TokenKind::InterpolatedString);
+
+        Ok(token.parts.iter()
+            .map(|part| match part {
+                StringPart::Text(text) => text.clone(),
+                StringPart::Hole { source: hole, span, .. } => {
+                    assert_eq!(&source[span.start..span.end], hole);
+                    format!("{{{}}}", hole)
+                },
+            })
+            .collect())
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        assert_eq!(parts(r#""Hello {name}!""#), Ok(vec!["Hello ".to_string(), "{name}".to_string(), "!".to_string()]));
+        assert_eq!(parts(r#""{n - 1}\t{{}""#), Ok(vec![String::new(), "{n - 1}".to_string(), "\t{}".to_string()]));
+
+        // Braces and strings inside of a hole don't end it
+        assert_eq!(parts(r#""{map <- {x | "}"}}""#), Ok(vec![
+            String::new(),
+            r#"{map <- {x | "}"}}"#.to_string(),
+            String::new(),
+        ]));
+
+        let mut scanner = Scanner::new(r#""no holes {{}""#.to_string(), FileId::default());
+        let token = scanner.scan_token().unwrap();
+        assert_eq!((token.kind, &token.lexeme[..]), (TokenKind::String, "no holes {}"));
+    }
+
+    #[test]
+    fn malformed_holes() {
+        assert_eq!(parts(r#""{}""#), Err("Expected an expression between '{' and '}' in string.".to_string()));
+        assert_eq!(parts(r#""{n"#), Err("Unterminated '{' in string.".to_string()));
+    }
+
+    // Scan a single string literal, giving back its text or the message of
+    // its error.
+    fn string(source: &str) -> Result<String, String> {
+        let mut scanner = Scanner::new(source.to_string(), FileId::default());
+        let token = scanner.scan_token().map_err(|err| err.msg)?;
+        assert_eq!(token.kind, TokenKind::String);
+        assert!(scanner.is_at_end(), "{:?} isn't scanned as one token", source);
+        Ok(token.lexeme)
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(string(r#""a\tb\nc\r\0""#), Ok("a\tb\nc\r\0".to_string()));
+        assert_eq!(string(r#""\"\\""#), Ok("\"\\".to_string()));
+        assert_eq!(string(r#""\u{41}\u{1F600}""#), Ok("A\u{1F600}".to_string()));
+
+        assert_eq!(string(r#""\q""#), Err("Unrecognised escape sequence '\\q'.".to_string()));
+        assert_eq!(string(r#""\u41""#), Err("Expected '{' after '\\u'.".to_string()));
+        assert_eq!(string(r#""\u{}""#), Err("A unicode escape must have 1 to 6 hexadecimal digits inside of '{}'.".to_string()));
+        assert_eq!(string(r#""\u{1234567}""#), Err("A unicode escape must have 1 to 6 hexadecimal digits inside of '{}'.".to_string()));
+        assert_eq!(string(r#""\u{D800}""#), Err("'\\u{D800}' isn't a unicode character.".to_string()));
+    }
+
+    #[test]
+    fn raw_strings() {
+        assert_eq!(string(r#"r"C:\path\{name}""#), Ok(r"C:\path\{name}".to_string()));
+        assert_eq!(string(r###"r#"say "hi""#"###), Ok(r#"say "hi""#.to_string()));
+        assert_eq!(string(r###"r##"a "# b"##"###), Ok(r##"a "# b"##.to_string()));
+        assert_eq!(string(r#"r"unterminated"#), Err("Unterminated raw string literal.".to_string()));
+
+        // An `r` on its own is still a name
+        let mut scanner = Scanner::new("r #".to_string(), FileId::default());
+        assert_eq!(scanner.scan_token().unwrap().kind, TokenKind::Identifier);
+    }
+
+    #[test]
+    fn triple_quoted_strings() {
+        let source = "\"\"\"\n    Dear {{name},\n      \"quoted\"\n    Bye\n    \"\"\"";
+        assert_eq!(string(source), Ok("Dear {name},\n  \"quoted\"\nBye".to_string()));
+
+        // Text after the opening or before the closing quotes is kept
+        assert_eq!(string("\"\"\"one\n  two\n three\"\"\""), Ok("one\n two\nthree".to_string()));
+        assert_eq!(string("\"\"\"\"\"\""), Ok(String::new()));
+        assert_eq!(string("\"\"\"\n  never closed\n\""), Err("Unterminated string literal.".to_string()));
+    }
+}
@@ -1,9 +1,15 @@
+use std::fmt;
+
 // The type of an expression
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Typ {
     // This expression's type hasn't been resolved yet
     Unknown,
 
+    // A type variable, which stands in for any type, like the `a` in
+    // `a -> a`. Variables are numbered, and displayed as `a`, `b`, etc.
+    Var(u32),
+
     // Primitives
     Int,
     Float,
@@ -11,5 +17,106 @@ pub enum Typ {
     String,
 
     List(Box<Typ>),
+    Tuple(Vec<Typ>),
     Func(Box<Typ>, Box<Typ>),
-}
\ No newline at end of file
+}
+
+impl Typ {
+    // Whether the type variable `var` appears anywhere inside this type.
+    pub fn contains_var(&self, var: u32) -> bool {
+        match self {
+            Typ::Var(v) => *v == var,
+            Typ::List(elem) => elem.contains_var(var),
+            Typ::Tuple(elems) => elems.iter().any(|e| e.contains_var(var)),
+            Typ::Func(param, ret) => param.contains_var(var) || ret.contains_var(var),
+            _ => false,
+        }
+    }
+
+    // Collect the type variables in this type, in the order they first appear.
+    pub fn vars(&self, out: &mut Vec<u32>) {
+        match self {
+            Typ::Var(v) if !out.contains(v) => out.push(*v),
+            Typ::List(elem) => elem.vars(out),
+            Typ::Tuple(elems) => elems.iter().for_each(|e| e.vars(out)),
+            Typ::Func(param, ret) => {
+                param.vars(out);
+                ret.vars(out);
+            },
+            _ => {},
+        }
+    }
+}
+
+// The name of a type variable: 0 is `a`, 1 is `b`, ..., 25 is `z`,
+// 26 is `a1` and so on.
+pub fn var_name(var: u32) -> String {
+    let letter = (b'a' + (var % 26) as u8) as char;
+    if var < 26 {
+        letter.to_string()
+    } else {
+        format!("{}{}", letter, var / 26)
+    }
+}
+
+impl fmt::Display for Typ {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Typ::Unknown => write!(f, "?"),
+            Typ::Var(v) => write!(f, "{}", var_name(*v)),
+            Typ::Int => write!(f, "Int"),
+            Typ::Float => write!(f, "Float"),
+            Typ::Bool => write!(f, "Bool"),
+            Typ::String => write!(f, "String"),
+            Typ::List(elem) => write!(f, "[{}]", elem),
+            Typ::Tuple(elems) => {
+                write!(f, "(")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, ")")
+            },
+            // `->` is right associative, so only a function on the left needs brackets
+            Typ::Func(param, ret) => match **param {
+                Typ::Func(..) => write!(f, "({}) -> {}", param, ret),
+                _ => write!(f, "{} -> {}", param, ret),
+            },
+        }
+    }
+}
+
+// Some operators work on more than one type, e.g. `+` works on both Ints
+// and Floats. A type variable may be restricted to a class of types, so
+// that `{x | x + 1}` can't be applied to a String.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Class {
+    // Int or Float, for arithmetic
+    Num,
+
+    // Int, Float or String, for comparisons like `<`
+    Ord,
+
+    // String or any list, for `++`
+    Concat,
+}
+
+impl Class {
+    pub fn admits(self, typ: &Typ) -> bool {
+        match self {
+            Class::Num => matches!(typ, Typ::Int | Typ::Float),
+            Class::Ord => matches!(typ, Typ::Int | Typ::Float | Typ::String),
+            Class::Concat => matches!(typ, Typ::String | Typ::List(_)),
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Class::Num => "a number",
+            Class::Ord => "a comparable type",
+            Class::Concat => "a String or a list",
+        }
+    }
+}
@@ -0,0 +1,388 @@
+// gwn::unparse: Printing the AST back out as source.
+//
+// Declarations, expressions and patterns are printed on one line, with only
+// the brackets that are needed for them to parse back into the same tree.
+// Which those are is worked out from the parse table: a child needs
+// brackets if it binds less tightly than the operator it's under, or just
+// as tightly but groups the other way, e.g. `(a : b) : c` but `a : b : c`.
+//
+// A few trees can't be printed so that they parse back the same, as the
+// parser never makes them: negative number literals, which come back as
+// `-` applied to a number, tuples of one element, which come back as just
+// the element, and functions with several cases in a tuple or list, which
+// come back as a function for each case.
+
+use crate::ast::{Decl, Expr, ExprKind, FuncCase, InterpolatedPart, Literal, Pattern, PatternKind};
+use crate::parser::{self, Associativity, Precedence};
+use crate::scanner::{Token, TokenKind};
+
+use std::fmt;
+
+impl fmt::Display for Decl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decl::Constant(constant) => match &constant.annotation {
+                Some(typ) => write!(f, "{}: {} = {}", constant.name.lexeme, typ, constant.value),
+                None => write!(f, "{} = {}", constant.name.lexeme, constant.value),
+            },
+
+            Decl::Evaluated(evaluated) => write!(f, "{}", evaluated.expr),
+
+            Decl::Import(import) => write!(f, "import {}", import.path.lexeme),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.node {
+            ExprKind::Constant(constant) => write!(f, "{}", constant.name.lexeme),
+
+            ExprKind::Unary(unary) => {
+                let operand = bracketed(&unary.operand, binding(&unary.operand).0 < Precedence::Unary);
+                match unary.operator.kind {
+                    TokenKind::Not => write!(f, "not {}", operand),
+
+                    // `- -x`, not `--x`
+                    _ if operand.starts_with('-') => write!(f, "{} {}", unary.operator.lexeme, operand),
+                    _ => write!(f, "{}{}", unary.operator.lexeme, operand),
+                }
+            },
+
+            ExprKind::Binary(_) | ExprKind::Logical(_) | ExprKind::Apply(_) => {
+                let (operator, left, right) = operands(self).unwrap();
+                let (left_brackets, right_brackets) = brackets(operator, left, right);
+                write!(f, "{} {} {}", bracketed(left, left_brackets), operator.lexeme, bracketed(right, right_brackets))
+            },
+
+            ExprKind::Func(func) => {
+                for (i, case) in func.cases.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_case(f, case)?;
+                }
+                Ok(())
+            },
+
+            ExprKind::Tuple(tuple) => write!(f, "({})", sequence(tuple.elements.iter())),
+            ExprKind::List(list) => write!(f, "[{}]", sequence(list.elements.iter())),
+
+            ExprKind::Range(range) => {
+                write!(f, "[{}..", sequence(Some(&range.start).into_iter().chain(range.next.as_ref())))?;
+                if let Some(end) = &range.end {
+                    write!(f, "{}", end)?;
+                }
+                write!(f, "]")
+            },
+
+            ExprKind::InterpolatedString(string) => {
+                write!(f, "\"")?;
+                for part in string.parts.iter() {
+                    match part {
+                        InterpolatedPart::Text(text) => write!(f, "{}", escape(text))?,
+
+                        // `{{` would be a literal '{'
+                        InterpolatedPart::Hole(hole) => {
+                            let hole = hole.to_string();
+                            write!(f, "{{{}}}", bracketed_text(&hole, hole.starts_with('{')))?;
+                        },
+                    }
+                }
+                write!(f, "\"")
+            },
+
+            ExprKind::Literal(literal) => write!(f, "{}", literal),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let patterns = |patterns: &[Pattern]| patterns.iter()
+            .map(Pattern::to_string)
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        match &self.node {
+            PatternKind::Literal(literal) => write!(f, "{}", literal),
+            PatternKind::Identifier(name) => write!(f, "{}", name.lexeme),
+            PatternKind::Wildcard => write!(f, "_"),
+            PatternKind::Tuple(elements) => write!(f, "({})", patterns(elements)),
+            PatternKind::List(elements) => write!(f, "[{}]", patterns(elements)),
+
+            // `:` is right associative, so only a head that is itself a
+            // cons needs brackets
+            PatternKind::Cons(head, tail) => match head.node {
+                PatternKind::Cons(..) => write!(f, "({}) : {}", head, tail),
+                _ => write!(f, "{} : {}", head, tail),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Int(i) => write!(f, "{}", i),
+
+            // Debug always gives a '.' or an exponent, so that it scans
+            // back as a Float
+            Literal::Float(x) => write!(f, "{:?}", x),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::String(s) => write!(f, "\"{}\"", escape(s)),
+        }
+    }
+}
+
+// How tightly an expression binds, and which way it groups.
+fn binding(expr: &Expr) -> (Precedence, Associativity) {
+    match &expr.node {
+        ExprKind::Binary(binary) => parser::binding(binary.operator.kind),
+        ExprKind::Logical(logical) => parser::binding(logical.operator.kind),
+        ExprKind::Apply(apply) => parser::binding(apply.operator.kind),
+        ExprKind::Unary(_) => (Precedence::Unary, Associativity::Left),
+        _ => (Precedence::Primary, Associativity::Left),
+    }
+}
+
+// The operator of an infix expression, and its operands in the order they
+// are written in. The function is on the left of `<-`, but the right of
+// `->`.
+fn operands(expr: &Expr) -> Option<(&Token, &Expr, &Expr)> {
+    match &expr.node {
+        ExprKind::Binary(binary) => Some((&binary.operator, &binary.left, &binary.right)),
+        ExprKind::Logical(logical) => Some((&logical.operator, &logical.left, &logical.right)),
+        ExprKind::Apply(apply) if apply.operator.kind == TokenKind::RightArrow =>
+            Some((&apply.operator, &apply.arg, &apply.func)),
+        ExprKind::Apply(apply) => Some((&apply.operator, &apply.func, &apply.arg)),
+        _ => None,
+    }
+}
+
+// Whether each operand of `left operator right` needs brackets. An operand
+// that binds as tightly as the operator only goes without brackets on the
+// side that the operator groups towards, and only if it groups the same
+// way, e.g. `a - b - c` is `(a - b) - c`, but `a : b + c` is `a : (b + c)`.
+fn brackets(operator: &Token, left: &Expr, right: &Expr) -> (bool, bool) {
+    let (precedence, associativity) = parser::binding(operator.kind);
+    let (left_precedence, left_associativity) = binding(left);
+    let (right_precedence, _) = binding(right);
+
+    let left_brackets = left_precedence < precedence
+        || (left_precedence == precedence && left_associativity == Associativity::Right);
+    let right_brackets = right_precedence < precedence
+        || (right_precedence == precedence && associativity == Associativity::Left);
+
+    (left_brackets, right_brackets)
+}
+
+fn write_case(f: &mut fmt::Formatter, case: &FuncCase) -> fmt::Result {
+    let params: Vec<String> = case.params.iter().map(Pattern::to_string).collect();
+    write!(f, "{{{} | ", params.join(" "))?;
+
+    for (i, guard) in case.guards.iter().enumerate() {
+        if i > 0 {
+            write!(f, " | ")?;
+        }
+
+        // A guard without a condition has the condition `true`, which reads
+        // best as `else` when it comes last
+        if guard.condition.node != ExprKind::Literal(Literal::Bool(true)) {
+            write!(f, "{} ? {}", guard.condition, guard.value)?;
+        } else if i > 0 && i == case.guards.len() - 1 {
+            write!(f, "else ? {}", guard.value)?;
+        } else {
+            write!(f, "{}", guard.value)?;
+        }
+    }
+
+    write!(f, "}}")
+}
+
+// Comma separated expressions, as in a tuple or list
+fn sequence<'a>(exprs: impl Iterator<Item = &'a Expr>) -> String {
+    exprs.map(Expr::to_string).collect::<Vec<String>>().join(", ")
+}
+
+fn bracketed(expr: &Expr, brackets: bool) -> String {
+    bracketed_text(&expr.to_string(), brackets)
+}
+
+fn bracketed_text(text: &str, brackets: bool) -> String {
+    if brackets {
+        format!("({})", text)
+    } else {
+        text.to_string()
+    }
+}
+
+// Escape text to go inside of a string's quotes, using the escapes that the
+// scanner knows.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '{' => escaped.push_str("{{"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:X}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Decl, Expr, ExprKind, Literal};
+    use crate::dump;
+    use crate::int::Int;
+    use crate::typ::Typ;
+
+    use serde_json::Value as Json;
+
+    use std::fs;
+    use std::path::Path;
+
+    // The JSON dump of the declarations in `source`, without any spans, so
+    // that trees written differently can be compared.
+    fn syntax(source: &str) -> Json {
+        let parsed = crate::parse(source);
+        assert!(parsed.errors.is_empty(), "{:?} doesn't parse", source);
+
+        let mut json = dump::decls_json(&parsed.decls, source);
+        strip_spans(&mut json);
+        json
+    }
+
+    fn strip_spans(json: &mut Json) {
+        match json {
+            Json::Object(fields) => {
+                fields.remove("span");
+                fields.values_mut().for_each(strip_spans);
+            },
+            Json::Array(elements) => elements.iter_mut().for_each(strip_spans),
+            _ => {},
+        }
+    }
+
+    fn assert_round_trips(source: &str) {
+        let printed = crate::unparse(&crate::parse(source).decls);
+        assert!(crate::parse(&printed).errors.is_empty(), "{:?} was printed as {:?}, which doesn't parse", source, printed);
+        assert_eq!(syntax(source), syntax(&printed), "{:?} was printed as {:?}, which parses differently", source, printed);
+    }
+
+    fn assert_prints(source: &str, expected: &str) {
+        assert_round_trips(source);
+        assert_eq!(crate::unparse(&crate::parse(source).decls), expected);
+    }
+
+    #[test]
+    fn examples_round_trip() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "gwn") {
+                assert_round_trips(&fs::read_to_string(&path).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn right_associative_operators() {
+        assert_prints("x = (a : b) : c\n", "x = (a : b) : c\n");
+        assert_prints("x = a : (b : c)\n", "x = a : b : c\n");
+        assert_prints("x = (a + b) : c\n", "x = a + b : c\n");
+        assert_prints("x = (a : b) + c\n", "x = (a : b) + c\n");
+        assert_prints("x = a : (b + c)\n", "x = a : b + c\n");
+    }
+
+    #[test]
+    fn left_associative_operators() {
+        assert_prints("x = (a - b) - c\n", "x = a - b - c\n");
+        assert_prints("x = a - (b - c)\n", "x = a - (b - c)\n");
+        assert_prints("x = (a * b) + (c * d)\n", "x = a * b + c * d\n");
+        assert_prints("x = (a + b) * (c + d)\n", "x = (a + b) * (c + d)\n");
+        assert_prints("x = (a or b) and not (c or d)\n", "x = (a or b) and not (c or d)\n");
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_prints("x = - -x\n", "x = - -x\n");
+        assert_prints("x = -(-x)\n", "x = - -x\n");
+        assert_prints("x = -(a + b)\n", "x = -(a + b)\n");
+        assert_prints("x = (-a) ^ b\n", "x = -a ^ b\n");
+        assert_prints("x = -(f <- a)\n", "x = -(f <- a)\n");
+    }
+
+    #[test]
+    fn application_chains() {
+        assert_prints("x = f <- (g <- y)\n", "x = f <- g <- y\n");
+        assert_prints("x = (f <- g) <- y\n", "x = (f <- g) <- y\n");
+        assert_prints("x = (y -> f) -> g\n", "x = y -> f -> g\n");
+        assert_prints("x = y -> (f -> g)\n", "x = y -> (f -> g)\n");
+        assert_prints("x = f <- (y -> g)\n", "x = f <- y -> g\n");
+        assert_prints("x = (f <- y) -> g\n", "x = (f <- y) -> g\n");
+        assert_prints("print <- [1..30] -> map <- fibonacci\n", "print <- [1..30] -> map <- fibonacci\n");
+    }
+
+    #[test]
+    fn functions() {
+        assert_prints("x = ({a | a}, {b | b})\n", "x = ({a | a}, {b | b})\n");
+        assert_prints("x = [{a | a}, {b | b}]\n", "x = [{a | a}, {b | b}]\n");
+        assert_prints("f = {0 | 0}, {n | n - 1}\n", "f = {0 | 0}, {n | n - 1}\n");
+        assert_prints("f = {x | x > 0 ? 1, else ? 2}\n", "f = {x | x > 0 ? 1 | else ? 2}\n");
+        assert_prints("f = {(x : xs) [a, _] -1 | x}\n", "f = {x : xs [a, _] -1 | x}\n");
+        assert_prints("f = {((a : b) : c) | a}\n", "f = {(a : b) : c | a}\n");
+    }
+
+    #[test]
+    fn trees_the_parser_never_makes() {
+        // The value of the one constant in `source`, to be changed into a
+        // tree that the parser wouldn't make
+        fn value(source: &str) -> Expr {
+            match crate::parse(source).decls.pop() {
+                Some(Decl::Constant(constant)) => constant.value,
+                decl => panic!("Expected a constant, found {:?}", decl),
+            }
+        }
+
+        let negative = Expr::new(ExprKind::Literal(Literal::Int(Int::from(-1))), Typ::Unknown);
+        assert_eq!(negative.to_string(), "-1");
+
+        let mut tuple = value("x = (1, 2)");
+        if let ExprKind::Tuple(tuple) = &mut tuple.node {
+            tuple.elements.pop();
+        }
+        assert_eq!(tuple.to_string(), "(1)");
+
+        let mut list = value("x = [f]");
+        if let ExprKind::List(list) = &mut list.node {
+            list.elements[0] = value("f = {0 | 0}, {n | n}");
+        }
+        assert_eq!(list.to_string(), "[{0 | 0}, {n | n}]");
+    }
+
+    #[test]
+    fn strings() {
+        assert_prints("x = \"a{{b}\\n\\\"\\u{7}\"\n", "x = \"a{{b}\\n\\\"\\u{7}\"\n");
+        assert_prints("x = \"a {({b | b} <- 1)} c\"\n", "x = \"a {({b | b} <- 1)} c\"\n");
+        assert_prints("x = \"a {\"b {c}\"}\"\n", "x = \"a {\"b {c}\"}\"\n");
+    }
+
+    #[test]
+    fn declarations() {
+        assert_prints("x : xs\n", "x : xs\n");
+        assert_prints("(x : xs) ++ ys\n", "(x : xs) ++ ys\n");
+        assert_prints("(x) : xs == ys\n", "x : xs == ys\n");
+        assert_prints("f: (a -> b) -> [a] -> [b] = map\n", "f: (a -> b) -> [a] -> [b] = map\n");
+        assert_prints("import ./util\n", "import ./util\n");
+    }
+}
@@ -0,0 +1,284 @@
+// gwn::value: The values that programs compute at runtime.
+
+use crate::ast::FuncExpr;
+use crate::interpreter::{Env, RuntimeError};
+use crate::int::Int;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub enum Value {
+    Int(Int),
+    Float(f64),
+    Bool(bool),
+    String(Rc<str>),
+    List(List),
+    Tuple(Rc<Vec<Value>>),
+    Closure(Rc<Closure>),
+}
+
+impl Value {
+    // The value of an empty tuple, `()`, which is what functions that are
+    // only called for their effect, such as `print`, give back.
+    pub fn unit() -> Value {
+        Value::Tuple(Rc::new(vec![]))
+    }
+
+    pub fn is_unit(&self) -> bool {
+        match self {
+            Value::Tuple(elements) => elements.is_empty(),
+            _ => false,
+        }
+    }
+
+    pub fn string(s: &str) -> Value {
+        Value::String(Rc::from(s))
+    }
+
+    // Compare two values for equality. Lists are compared element by element,
+    // forcing them as we go. Functions can't be compared.
+    pub fn equals(&self, other: &Value) -> Result<bool, RuntimeError> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => Ok(a == b),
+            (Value::Float(a), Value::Float(b)) => Ok(a == b),
+            (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+            (Value::String(a), Value::String(b)) => Ok(a == b),
+
+            (Value::Tuple(a), Value::Tuple(b)) => {
+                for (a, b) in a.iter().zip(b.iter()) {
+                    if !a.equals(b)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(a.len() == b.len())
+            },
+
+            (Value::List(a), Value::List(b)) => {
+                let (mut a, mut b) = (a.clone(), b.clone());
+                loop {
+                    match (a.uncons()?, b.uncons()?) {
+                        (None, None) => return Ok(true),
+                        (Some((x, xs)), Some((y, ys))) => {
+                            if !x.equals(&y)? {
+                                return Ok(false);
+                            }
+                            a = xs;
+                            b = ys;
+                        },
+                        _ => return Ok(false),
+                    }
+                }
+            },
+
+            (Value::Closure(_), Value::Closure(_)) =>
+                Err(RuntimeError::new("Functions can't be compared.".to_string())),
+
+            _ => Ok(false),
+        }
+    }
+
+    // Display the value the way it would be written in a program, e.g. strings
+    // are quoted. `print` and `toString` use `to_text` instead.
+    pub fn show(&self) -> Result<String, RuntimeError> {
+        match self {
+            Value::String(s) => Ok(format!("{:?}", s)),
+
+            Value::List(list) => {
+                let elements = list.to_vec()?.iter()
+                    .map(Value::show)
+                    .collect::<Result<Vec<String>, RuntimeError>>()?;
+                Ok(format!("[{}]", elements.join(", ")))
+            },
+
+            Value::Tuple(elements) => {
+                let elements = elements.iter()
+                    .map(Value::show)
+                    .collect::<Result<Vec<String>, RuntimeError>>()?;
+                Ok(format!("({})", elements.join(", ")))
+            },
+
+            _ => Ok(self.to_string()),
+        }
+    }
+
+    // Display the value as text. This is the same as `show`, except that a
+    // string is just its contents.
+    pub fn to_text(&self) -> Result<String, RuntimeError> {
+        match self {
+            Value::String(s) => Ok(s.to_string()),
+            _ => self.show(),
+        }
+    }
+}
+
+// Display a value that doesn't need to be forced, i.e. anything but a list.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", s),
+            Value::List(_) => write!(f, "[...]"),
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, ")")
+            },
+            Value::Closure(_) => write!(f, "<function>"),
+        }
+    }
+}
+
+// A function, along with the arguments it has been given so far. Functions
+// are curried, so a function isn't called until it has been given as many
+// arguments as it has parameters.
+pub struct Closure {
+    pub func: Callable,
+    pub args: Vec<Value>,
+}
+
+impl Closure {
+    pub fn arity(&self) -> usize {
+        match &self.func {
+            Callable::Func(func, _) => func.cases[0].params.len(),
+            Callable::Native(native) => native.arity,
+        }
+    }
+}
+
+pub enum Callable {
+    // A function literal, along with the environment it was created in
+    Func(Rc<FuncExpr>, Env),
+
+    // A function built in to the language, like `print`
+    Native(Native),
+}
+
+pub type NativeFn = fn(&[Value]) -> Result<Value, RuntimeError>;
+
+#[derive(Clone, Copy)]
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub func: NativeFn,
+}
+
+// A list, which may be lazy. Lists such as `[1..]` or `[1..] -> map <- f`
+// are only worked out as far as something looks at them, so they may
+// even be infinite.
+#[derive(Clone)]
+pub struct List(Rc<Node>);
+
+enum Node {
+    Nil,
+    Cons(Value, List),
+
+    // A list that hasn't been worked out yet
+    Lazy(RefCell<Lazy>),
+}
+
+type Uncons = Result<Option<(Value, List)>, RuntimeError>;
+
+enum Lazy {
+    Pending(Box<dyn FnOnce() -> Uncons>),
+    Forcing,
+    Forced(Uncons),
+}
+
+impl List {
+    pub fn nil() -> List {
+        List(Rc::new(Node::Nil))
+    }
+
+    pub fn cons(head: Value, tail: List) -> List {
+        List(Rc::new(Node::Cons(head, tail)))
+    }
+
+    // A list that is worked out by calling `f` when something first looks at it.
+    // `f` gives back either nothing for an empty list, or the head and the tail.
+    pub fn lazy(f: impl FnOnce() -> Uncons + 'static) -> List {
+        List(Rc::new(Node::Lazy(RefCell::new(Lazy::Pending(Box::new(f))))))
+    }
+
+    pub fn from_vec(values: Vec<Value>) -> List {
+        values.into_iter().rev().fold(List::nil(), |tail, head| List::cons(head, tail))
+    }
+
+    // Split the list into its head and tail, or `None` if it is empty.
+    pub fn uncons(&self) -> Uncons {
+        match &*self.0 {
+            Node::Nil => Ok(None),
+            Node::Cons(head, tail) => Ok(Some((head.clone(), tail.clone()))),
+            Node::Lazy(cell) => {
+                let state = mem::replace(&mut *cell.borrow_mut(), Lazy::Forcing);
+                let result = match state {
+                    Lazy::Pending(f) => f(),
+                    Lazy::Forced(result) => result,
+                    Lazy::Forcing =>
+                        Err(RuntimeError::new("List depends on itself.".to_string())),
+                };
+
+                *cell.borrow_mut() = Lazy::Forced(result.clone());
+                result
+            },
+        }
+    }
+
+    // Force the whole list. This never finishes for an infinite list.
+    pub fn to_vec(&self) -> Result<Vec<Value>, RuntimeError> {
+        let mut values = vec![];
+        let mut list = self.clone();
+
+        while let Some((head, tail)) = list.uncons()? {
+            values.push(head);
+            list = tail;
+        }
+
+        Ok(values)
+    }
+
+    // `self` followed by `other`, without forcing either.
+    pub fn append(&self, other: List) -> List {
+        let list = self.clone();
+        List::lazy(move || match list.uncons()? {
+            Some((head, tail)) => Ok(Some((head, tail.append(other)))),
+            None => other.uncons(),
+        })
+    }
+
+    // Take the tail out of a node, leaving it empty.
+    fn take_tail(node: &mut Node) -> Option<List> {
+        match node {
+            Node::Cons(_, tail) => Some(mem::replace(tail, List::nil())),
+            Node::Lazy(cell) => match mem::replace(cell.get_mut(), Lazy::Forcing) {
+                Lazy::Forced(Ok(Some((_, tail)))) => Some(tail),
+                _ => None,
+            },
+            Node::Nil => None,
+        }
+    }
+}
+
+// Dropping a long list would otherwise recurse once per element and
+// overflow the stack, so unlink the nodes one at a time instead.
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut tail = List::take_tail(self);
+
+        while let Some(List(rc)) = tail {
+            tail = match Rc::try_unwrap(rc) {
+                Ok(mut node) => List::take_tail(&mut node),
+                Err(_) => None,
+            };
+        }
+    }
+}
@@ -0,0 +1,28 @@
+// Tests for the library API, as another crate would use it.
+
+use gwn::ast::{Decl, ExprKind};
+use gwn::TokenKind;
+
+#[test]
+fn tokenize_keeps_newlines_and_errors() {
+    let kinds: Vec<_> = gwn::tokenize("a = 1\n\nb = @\n").iter().map(|token| token.kind).collect();
+    assert_eq!(kinds, [
+        TokenKind::Identifier, TokenKind::Equal, TokenKind::Number, TokenKind::Newline, TokenKind::Newline,
+        TokenKind::Identifier, TokenKind::Equal, TokenKind::Error, TokenKind::Newline,
+        TokenKind::Eof,
+    ]);
+}
+
+#[test]
+fn parse_gives_back_declarations_and_errors() {
+    let result = gwn::parse("id = {x | x}\nprint <- (id <- 1)\n");
+    assert!(result.is_ok());
+    assert!(matches!(&result.decls[0], Decl::Constant(constant) if constant.name.lexeme == "id"));
+    assert!(matches!(&result.decls[1], Decl::Evaluated(evaluated) if matches!(evaluated.expr.node, ExprKind::Apply(_))));
+
+    // The declarations around a broken one are still parsed
+    let result = gwn::parse("a = 1\nb = )\nc = 2\n");
+    assert!(!result.is_ok());
+    assert_eq!(result.decls.len(), 2);
+    assert_eq!(result.errors.iter().map(|err| err.code).collect::<Vec<_>>(), ["E0002"]);
+}
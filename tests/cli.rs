@@ -0,0 +1,81 @@
+// Tests for the command line, which run the gwn binary with different
+// arguments.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+// Run gwn with `args`, giving back its exit code and what it wrote to
+// stdout and stderr.
+fn gwn(args: &[&str]) -> (i32, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_gwn")).args(args).output().unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+// The message of the usage error that running gwn with `args` gives.
+fn usage_error(args: &[&str]) -> String {
+    let (code, out, errors) = gwn(args);
+    assert_eq!((code, &out[..]), (2, ""), "{:?}", args);
+    assert!(errors.ends_with("\n\nSee `gwn --help` for how to use gwn.\n"), "{}", errors);
+
+    errors.lines().next().unwrap().to_string()
+}
+
+#[test]
+fn help_and_version() {
+    let (code, out, _) = gwn(&["--help"]);
+    assert_eq!(code, 0);
+    assert!(out.starts_with("Usage: gwn [options] <command>"), "{}", out);
+    assert_eq!(gwn(&["run", "-h"]).1, out);
+
+    let (code, out, _) = gwn(&["-V"]);
+    assert_eq!((code, out), (0, format!("gwn {}\n", gwn::version())));
+}
+
+#[test]
+fn unknown_options_and_commands() {
+    assert_eq!(usage_error(&["--colour"]), "error: Unknown option '--colour'.");
+    assert_eq!(usage_error(&["--error-format=xml", "check", "a.gwn"]), "error: Unknown option '--error-format=xml'.");
+    assert_eq!(usage_error(&["compile", "a.gwn"]), "error: Unknown command 'compile'.");
+}
+
+#[test]
+fn commands_are_given_the_right_number_of_files() {
+    assert_eq!(usage_error(&["run"]), "error: 'run' needs a file.");
+    assert_eq!(usage_error(&["run", "a.gwn", "b.gwn"]), "error: 'run' only takes one file.");
+    assert_eq!(usage_error(&["check"]), "error: 'check' needs at least one file.");
+    assert_eq!(usage_error(&["fmt", "--check"]), "error: 'fmt' needs at least one file.");
+    assert_eq!(usage_error(&["tokens"]), "error: 'tokens' needs a file.");
+    assert_eq!(usage_error(&["lsp", "a.gwn"]), "error: 'lsp' doesn't take any files, but was given 'a.gwn'.");
+}
+
+#[test]
+fn options_only_go_with_their_commands() {
+    assert_eq!(usage_error(&["--check", "check", "a.gwn"]), "error: '--check' can only be used with 'fmt'.");
+    assert_eq!(usage_error(&["check", "a.gwn", "--", "x"]), "error: Arguments after '--' can only be given to a program that is run.");
+}
+
+#[test]
+fn programs_are_given_the_arguments_after_dashes() {
+    let path = env::temp_dir().join(format!("gwn-cli-args-{}.gwn", std::process::id()));
+    fs::write(&path, "print <- args\n").unwrap();
+
+    let file = path.to_str().unwrap();
+    let short = gwn(&[file, "--", "one", "--two"]);
+    let long = gwn(&["run", file, "--", "one", "--two"]);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(short, (0, "one\n--two\n".to_string(), String::new()));
+    assert_eq!(long, short);
+}
+
+#[test]
+fn missing_files_are_an_error() {
+    let (code, _, errors) = gwn(&["run", "no/such/file.gwn"]);
+    assert_eq!(code, 1);
+    assert!(errors.contains("Can't read 'no/such/file.gwn'"), "{}", errors);
+}
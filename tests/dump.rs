@@ -0,0 +1,75 @@
+// Golden tests for `gwn tokens` and `gwn ast`: each example is dumped in
+// each format and compared with the file of the same name in
+// `tests/golden`. Run with `GWN_BLESS=1` to write the files afresh after
+// changing what the dumps look like.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const EXAMPLES: &[&str] = &["hello_world", "fibonacci", "fizz_buzz"];
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn dump(command: &str, format: &str, file: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_gwn"))
+        .args([command, "--format", format])
+        .arg(file)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "gwn {} failed: {}", command, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn check_golden(command: &str, format: &str, extension: &str) {
+    for example in EXAMPLES {
+        let source = root().join("examples").join(format!("{}.gwn", example));
+        let golden = root().join("tests/golden").join(format!("{}.{}", example, extension));
+        let actual = dump(command, format, &source);
+
+        if env::var_os("GWN_BLESS").is_some() {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&golden)
+            .unwrap_or_else(|err| panic!("Can't read {}: {}", golden.display(), err));
+        assert_eq!(actual, expected, "`gwn {} --format {}` of {} has changed", command, format, example);
+    }
+}
+
+#[test]
+fn tokens_text() {
+    check_golden("tokens", "text", "tokens.txt");
+}
+
+#[test]
+fn tokens_json() {
+    check_golden("tokens", "json", "tokens.json");
+}
+
+#[test]
+fn ast_sexpr() {
+    check_golden("ast", "sexpr", "ast.sexpr");
+}
+
+#[test]
+fn ast_json() {
+    check_golden("ast", "json", "ast.json");
+}
+
+#[test]
+fn json_is_valid() {
+    for example in EXAMPLES {
+        let source = root().join("examples").join(format!("{}.gwn", example));
+        let tokens: serde_json::Value = serde_json::from_str(&dump("tokens", "json", &source)).unwrap();
+        let decls: serde_json::Value = serde_json::from_str(&dump("ast", "json", &source)).unwrap();
+
+        assert_eq!(tokens.as_array().unwrap().last().unwrap()["kind"], "Eof");
+        assert!(decls.as_array().unwrap().iter().all(|decl| decl["kind"].is_string() && decl["span"].is_object()));
+    }
+}
@@ -0,0 +1,143 @@
+// Tests for imports, which write programs out to a temporary directory and
+// run the gwn binary on them.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// A directory of source files for one test, removed once it's done with.
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("gwn-modules-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    fn write(&self, file: &str, source: &str) -> &TempDir {
+        let path = self.path.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+        self
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Run `file` from `dir`, giving back what it printed and the errors it
+// reported.
+fn run(dir: &Path, file: &str, gwn_path: Option<&Path>) -> (String, String) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_gwn"));
+    command.arg(dir.join(file)).env_remove("GWN_PATH");
+    if let Some(gwn_path) = gwn_path {
+        command.env("GWN_PATH", gwn_path);
+    }
+
+    let output = command.output().unwrap();
+    (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn imports_bring_public_constants_into_scope() {
+    let dir = TempDir::new("public");
+    dir.write("main.gwn", "import ./lib/maths\nprint <- double <- 21\n")
+        .write("lib/maths.gwn", "import ./helpers\ndouble = {x | twice <- x}\n")
+        .write("lib/helpers.gwn", "twice = {x | x + x}\n");
+
+    assert_eq!(run(&dir.path, "main.gwn", None), ("42\n".to_string(), String::new()));
+}
+
+#[test]
+fn imports_are_not_passed_on() {
+    let dir = TempDir::new("transitive");
+    dir.write("main.gwn", "import ./maths\nprint <- twice <- 21\n")
+        .write("maths.gwn", "import ./helpers\ndouble = {x | twice <- x}\n")
+        .write("helpers.gwn", "twice = {x | x + x}\n");
+
+    let (out, errors) = run(&dir.path, "main.gwn", None);
+    assert_eq!(out, "");
+    assert!(errors.contains("Undefined constant 'twice'."), "{}", errors);
+}
+
+#[test]
+fn private_constants_are_not_imported() {
+    let dir = TempDir::new("private");
+    dir.write("main.gwn", "import ./util\nprint <- public\nprint <- _secret\n")
+        .write("util.gwn", "_secret = 1\npublic = _secret + 1\n");
+
+    let (out, errors) = run(&dir.path, "main.gwn", None);
+    assert_eq!(out, "");
+    assert!(errors.contains("'_secret' is private to the module"), "{}", errors);
+}
+
+#[test]
+fn each_module_is_only_loaded_once() {
+    let dir = TempDir::new("once");
+    dir.write("main.gwn", "import ./a\nimport ./b\nprint <- (a + b)\n")
+        .write("a.gwn", "import ./shared\na = n\n")
+        .write("b.gwn", "import ./shared\nb = n\n")
+        .write("shared.gwn", "n = 1\nprint <- \"loading shared\"\n");
+
+    assert_eq!(run(&dir.path, "main.gwn", None).0, "loading shared\n2\n");
+}
+
+#[test]
+fn errors_in_a_module_are_only_reported_once() {
+    let dir = TempDir::new("broken");
+    dir.write("main.gwn", "import ./a\nimport ./b\n")
+        .write("a.gwn", "import ./broken\n")
+        .write("b.gwn", "import ./broken\n")
+        .write("broken.gwn", "x = 1 + \"one\"\n");
+
+    let (_, errors) = run(&dir.path, "main.gwn", None);
+    assert_eq!(errors.matches("Expected type 'Int', found 'String'.").count(), 1, "{}", errors);
+}
+
+#[test]
+fn import_cycles_are_an_error() {
+    let dir = TempDir::new("cycle");
+    dir.write("a.gwn", "import ./b\nx = 1\n")
+        .write("b.gwn", "import ./c\ny = 2\n")
+        .write("c.gwn", "import ./a\nz = 3\n");
+
+    let (_, errors) = run(&dir.path, "a.gwn", None);
+    assert_eq!(errors.matches("Modules can't import each other in a cycle").count(), 1, "{}", errors);
+
+    let file = |name: &str| dir.path.join(name).display().to_string();
+    let cycle = format!("{} -> {} -> {} -> {}", file("a.gwn"), file("b.gwn"), file("c.gwn"), file("a.gwn"));
+    assert!(errors.contains(&cycle), "{}", errors);
+}
+
+#[test]
+fn modules_are_found_along_gwn_path() {
+    let dir = TempDir::new("path");
+    dir.write("project/main.gwn", "import Maths\nimport Local\nprint <- ((square <- 3) + local)\n")
+        .write("project/Local.gwn", "local = 1\n")
+        .write("library/Maths.gwn", "square = {x | x * x}\n");
+
+    let library = dir.path.join("library");
+    assert_eq!(run(&dir.path, "project/main.gwn", Some(&library)).0, "10\n");
+
+    let (_, errors) = run(&dir.path, "project/main.gwn", None);
+    assert!(errors.contains("Can't find the module 'Maths'."), "{}", errors);
+    assert!(errors.contains("Looked for 'Maths.gwn' in"), "{}", errors);
+}
+
+#[test]
+fn missing_relative_imports() {
+    let dir = TempDir::new("missing");
+    dir.write("main.gwn", "import ./nowhere\n");
+
+    let (_, errors) = run(&dir.path, "main.gwn", None);
+    assert!(errors.contains("Can't find the module './nowhere'."), "{}", errors);
+    assert!(errors.contains("nowhere.gwn"), "{}", errors);
+}